@@ -0,0 +1,100 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+/// Record of processing (GDPR Art. 30). Rows are only ever appended; each
+/// one hashes the previous, so editing or removing an entry breaks the
+/// chain. `details` holds ids and counts, never chat content.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "audit_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    pub created_at: ChronoDateTime,
+    pub action: String,
+    /// What the action was applied to, e.g. `conversation:12`
+    pub target: Option<String>,
+    /// JSON object
+    pub details: String,
+    pub prev_hash: String,
+    /// Hex SHA-256 over `prev_hash` and the fields above
+    pub hash: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub type AuditEntry = Model;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditAction {
+    ConversationCreated,
+    ConversationUpdated,
+    ConversationDeleted,
+    /// Filed under another project and re-sealed with its key
+    ConversationMoved,
+    ConversationPurged,
+    RetentionUpdated,
+    MessagesCreated,
+    MessageEdited,
+    MessageDeleted,
+    SettingsUpdated,
+    /// Personal data masked before a request left for the model
+    Redacted,
+    PiiVaultViewed,
+    PiiVaultPurged,
+    ModelCreated,
+    ModelUpdated,
+    ModelDeleted,
+    EncryptionEnabled,
+    AppUnlocked,
+    UnlockFailed,
+    AppLocked,
+    DataExported,
+    DataImported,
+    BackupCreated,
+    BackupRestored,
+    /// Orphaned rows removed when the database was opened
+    DatabaseRepaired,
+    ProjectCreated,
+    ProjectUpdated,
+    ProjectDeleted,
+    ProjectUnlocked,
+    ProjectLocked,
+    PromptCreated,
+    PromptUpdated,
+    PromptDeleted,
+    DocumentCreated,
+    DocumentDeleted,
+    DataErased,
+    AuditLogExported,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditLogQuery {
+    pub action: Option<AuditAction>,
+    /// Inclusive bounds on `created_at`
+    pub from: Option<ChronoDateTime>,
+    pub to: Option<ChronoDateTime>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditExportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditVerification {
+    pub entries: usize,
+    /// First entry whose hash or link does not match, if any
+    pub first_invalid_id: Option<i32>,
+}
@@ -14,9 +14,14 @@ pub struct Model {
     pub id: i32,
     pub name: String,
     pub content: String,
+    /// Project the document belongs to; it is only listed within it
+    #[serde(default)]
+    pub project_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {}
 
-impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
+impl ActiveModelBehavior for ActiveModel {}
+
+pub type Content = Model;
\ No newline at end of file
@@ -0,0 +1,39 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Condensed form of the oldest turns of a conversation. Summaries are only
+/// ever added; the messages they cover stay in `messages` untouched.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "conversation_summaries")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    pub conversation_id: i32,
+    pub content: String,
+    /// First and last message id (inclusive) of the range this summarises
+    pub first_message_id: i32,
+    pub last_message_id: i32,
+    pub created_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::conversations::Entity",
+        from = "Column::ConversationId",
+        to = "super::conversations::Column::Id"
+    )]
+    Conversation,
+}
+
+impl Related<super::conversations::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Conversation.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub type ConversationSummary = Model;
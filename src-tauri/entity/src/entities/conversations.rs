@@ -13,12 +13,30 @@ pub struct Model {
     pub system_message: Option<String>,
     pub options: String,
     pub last_message_at: ChronoDateTime,
+    /// Leaf of the branch currently shown and sent to the model
+    pub active_message_id: Option<i32>,
+    /// Days after `last_message_at` before the conversation is purged.
+    /// `None` follows the global `retentionDays` setting, 0 keeps it forever.
+    pub retention_days: Option<i32>,
+    /// Pinned conversations are never purged
+    #[sea_orm(default_value = false)]
+    #[serde(default)]
+    pub pinned: bool,
+    /// Kept for a legal claim or request; never purged
+    #[sea_orm(default_value = false)]
+    #[serde(default)]
+    pub legal_hold: bool,
+    /// Project the conversation is filed under, set when it is created
+    #[serde(default)]
+    pub project_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::messages::Entity")]
     Message,
+    #[sea_orm(has_many = "super::conversation_summaries::Entity")]
+    ConversationSummary,
 }
 
 impl Related<super::messages::Entity> for Entity {
@@ -27,10 +45,78 @@ impl Related<super::messages::Entity> for Entity {
     }
 }
 
+impl Related<super::conversation_summaries::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ConversationSummary.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 pub type Conversation = Model;
 
+/// Per-conversation exceptions to the global retention policy
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RetentionOverride {
+    pub retention_days: Option<i32>,
+    pub pinned: bool,
+    pub legal_hold: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    /// Stable schema, see `services::export`
+    Json,
+    /// Single file, no external resources
+    Html,
+    Docx,
+}
+
+/// What goes into an exported conversation besides its messages
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub include_system_message: bool,
+    /// Mask personal data with the detectors from the redaction setting
+    #[serde(default)]
+    pub redact: bool,
+    /// Label each generated reply and add a footer stating the document
+    /// contains AI-generated content
+    #[serde(default)]
+    pub ai_label: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// This app's JSON export
+    Json,
+    /// `conversations.json` from a ChatGPT data export
+    Chatgpt,
+    /// Chats exported from Open WebUI
+    Openwebui,
+}
+
+/// Items left out of an import for the same reason
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkippedImport {
+    /// Title of the conversation the items were in, `None` for the file itself
+    pub conversation: Option<String>,
+    pub reason: String,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Conversations created
+    pub conversations: Vec<Model>,
+    /// Messages created across them
+    pub messages: usize,
+    pub skipped: Vec<SkippedImport>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GenericOptions {
     pub options: String,
@@ -42,4 +128,15 @@ pub struct OllamaOptions {
     pub temperature: Option<f32>,
     pub top_p: Option<f32>,
     pub top_k: Option<f32>,
+    pub think: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
+pub struct OpenAIOptions {
+    pub stream: Option<bool>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub presence_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
 }
@@ -1,5 +1,5 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
-use sea_orm::entity::prelude::*;
+use sea_orm::{entity::prelude::*, FromQueryResult};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
@@ -15,6 +15,21 @@ pub struct Model {
     pub prompt_token: Option<i32>,
     pub completion_token: Option<i32>,
     pub reasoning_token: Option<i32>,
+    pub reasoning: Option<String>,
+    /// Message this one answers or follows; siblings are alternative branches
+    pub parent_id: Option<i32>,
+    // Provenance of an assistant reply the backend generated and stored, see
+    // `Provenance`. Empty for prompts and for messages stored by the webview.
+    /// Row in `models`, which may have been changed or deleted since
+    pub model_id: Option<i32>,
+    pub provider: Option<String>,
+    /// Name of the model on the provider's server
+    pub model_name: Option<String>,
+    /// `GenericOptions::options` as sent, JSON
+    pub generation_options: Option<String>,
+    /// `RedactionSetting` in force, JSON; empty when redaction was off
+    pub redaction: Option<String>,
+    pub app_version: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -41,4 +56,63 @@ pub type Message = Model;
 pub struct MessageDTO {
     pub role: String,
     pub content: String,
-}
\ No newline at end of file
+}
+
+impl From<Model> for MessageDTO {
+    fn from(message: Model) -> Self {
+        Self {
+            role: message.role,
+            content: message.content,
+        }
+    }
+}
+/// Asks the backend to store the exchange itself once the reply is complete,
+/// instead of relying on the webview to call `create_messages`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PersistOptions {
+    pub conversation_id: i32,
+    /// Answer this stored message instead of storing the prompt again, e.g.
+    /// to regenerate a reply or to answer an edited prompt
+    pub reply_to: Option<i32>,
+}
+
+/// What produced an assistant reply, stored with it so that it can later be
+/// labelled as generated by a given model on a given date
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub model_id: Option<i32>,
+    pub provider: Option<String>,
+    pub model_name: Option<String>,
+    pub generation_options: Option<String>,
+    pub redaction: Option<String>,
+    pub app_version: Option<String>,
+}
+
+/// Full-text search over message content
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MessageSearchQuery {
+    /// Words to look for; a trailing `*` matches by prefix
+    pub query: String,
+    pub conversation_id: Option<i32>,
+    /// Only this project's conversations; without it, projects with a
+    /// passphrase of their own are left out
+    #[serde(default)]
+    pub project_id: Option<i32>,
+    /// Inclusive bounds on `created_at`
+    pub from: Option<ChronoDateTime>,
+    pub to: Option<ChronoDateTime>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, FromQueryResult)]
+pub struct MessageSearchHit {
+    pub message_id: i32,
+    pub conversation_id: i32,
+    pub conversation_name: String,
+    pub role: String,
+    /// Matching excerpt with hits wrapped in `<mark>`/`</mark>`
+    pub snippet: String,
+    pub created_at: ChronoDateTime,
+    /// Relevance, higher is better
+    pub score: f64,
+}
@@ -1,8 +1,14 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
+pub mod audit_log;
 pub mod contents;
+pub mod conversation_summaries;
 pub mod conversations;
 pub mod messages;
 pub mod models;
+pub mod pagination;
+pub mod pii_vault;
 pub mod prelude;
+pub mod projects;
 pub mod prompts;
+pub mod retention_purges;
 pub mod settings;
\ No newline at end of file
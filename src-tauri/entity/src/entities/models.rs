@@ -23,6 +23,8 @@ impl ActiveModelBehavior for ActiveModel {}
 #[strum(serialize_all = "lowercase")]
 pub enum Providers {
     Ollama,
+    #[strum(serialize = "openai-compatible")]
+    OpenAICompatible,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -38,6 +40,10 @@ impl Provider {
                 id: "ollama".to_string(),
                 name: "Ollama".to_string(),
             },
+            Provider {
+                id: "openai-compatible".to_string(),
+                name: "OpenAI Compatible".to_string(),
+            },
         ]
     }
 }
@@ -0,0 +1,38 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Cursor-based paging shared by the list queries
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn reverse(self) -> Self {
+        match self {
+            SortOrder::Asc => SortOrder::Desc,
+            SortOrder::Desc => SortOrder::Asc,
+        }
+    }
+}
+
+/// Cursors are row ids. `after` continues the listing past that row,
+/// `before` returns the rows preceding it; either way the page comes back
+/// in listing order. Without a cursor the first page is returned.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageQuery {
+    pub before: Option<i32>,
+    pub after: Option<i32>,
+    pub limit: Option<u64>,
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Whether more rows exist beyond this page in the direction fetched
+    pub has_more: bool,
+}
@@ -0,0 +1,39 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What a placeholder such as `[PERSON_1]` stands for in one conversation,
+/// so the same value keeps its placeholder across turns. `value` is sealed
+/// when encryption is on.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "pii_vault")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    pub conversation_id: i32,
+    pub placeholder: String,
+    pub label: String,
+    pub value: String,
+    pub created_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::conversations::Entity",
+        from = "Column::ConversationId",
+        to = "super::conversations::Column::Id"
+    )]
+    Conversation,
+}
+
+impl Related<super::conversations::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Conversation.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub type PiiVaultEntry = Model;
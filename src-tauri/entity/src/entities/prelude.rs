@@ -1,7 +1,12 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
+pub use super::audit_log::Entity as AuditLog;
 pub use super::contents::Entity as Contents;
+pub use super::conversation_summaries::Entity as ConversationSummaries;
 pub use super::conversations::Entity as Conversations;
 pub use super::messages::Entity as Messages;
 pub use super::models::Entity as Models;
+pub use super::pii_vault::Entity as PiiVault;
+pub use super::projects::Entity as Projects;
 pub use super::prompts::Entity as Prompts;
+pub use super::retention_purges::Entity as RetentionPurges;
 pub use super::settings::Entity as Settings;
\ No newline at end of file
@@ -0,0 +1,63 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A client or matter. Conversations, prompts and documents filed under a
+/// project are only listed and searched together with that project's own.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "projects")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    pub name: String,
+    /// Client or matter number, unique when set
+    #[sea_orm(unique)]
+    pub reference: Option<String>,
+    /// `#rrggbb`
+    pub colour: Option<String>,
+    /// JSON object, e.g. the responsible lawyer or the opposing party
+    pub metadata: String,
+    /// Days its conversations are kept once quiet, instead of the global
+    /// `retentionDays`; a conversation's own period still wins. 0 keeps them.
+    pub retention_days: Option<i32>,
+    /// Key parameters when the project has a passphrase of its own, see
+    /// `settings::EncryptionSetting`
+    #[serde(skip)]
+    pub encryption: Option<String>,
+    pub created_at: ChronoDateTime,
+    pub updated_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// What the webview sends to create or change a project
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProjectPayload {
+    pub name: String,
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default)]
+    pub colour: Option<String>,
+    /// JSON object, `{}` when left out
+    #[serde(default)]
+    pub metadata: Option<String>,
+    #[serde(default)]
+    pub retention_days: Option<i32>,
+}
+
+/// A project with the state of its passphrase
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProjectDTO {
+    #[serde(flatten)]
+    pub project: Model,
+    /// Whether the project has a passphrase of its own
+    pub encrypted: bool,
+    /// Whether its content cannot be read until that passphrase is entered
+    pub locked: bool,
+}
+
+pub type Project = Model;
@@ -10,6 +10,9 @@ pub struct Model {
     pub id: i32,
     pub name: String,
     pub content: String,
+    /// Project the prompt belongs to; `None` for templates shared by all
+    #[serde(default)]
+    pub project_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,30 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Record of a conversation purged by the retention policy. Only what is
+/// needed to account for the purge is kept, not the name or content.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "retention_purges")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    #[serde(skip_deserializing)]
+    pub id: i32,
+    /// Id the conversation had; the row itself is gone
+    pub conversation_id: i32,
+    pub message_count: i32,
+    pub last_message_at: ChronoDateTime,
+    /// Retention period applied, in days
+    pub retention_days: i32,
+    /// Where the period came from: the "conversation", its "project" or the
+    /// "global" setting
+    pub policy: String,
+    pub purged_at: ChronoDateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+pub type RetentionPurge = Model;
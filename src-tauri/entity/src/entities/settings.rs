@@ -24,6 +24,8 @@ pub enum SettingKey {
     Ollama,
     Appearance,
     Proxy,
+    Redaction,
+    Backup,
 }
 
 impl SettingKey {
@@ -33,6 +35,8 @@ impl SettingKey {
             SettingKey::Ollama => "ollama",
             SettingKey::Appearance => "appearance",
             SettingKey::Proxy => "proxy",
+            SettingKey::Redaction => "redaction",
+            SettingKey::Backup => "backup",
         }
     }
 }
@@ -82,6 +86,193 @@ pub struct ProxySetting {
     pub port: u16,
 }
 
+/// Kinds of personal data the detectors look for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PiiKind {
+    Email,
+    Phone,
+    Iban,
+    /// Dutch citizen service number, validated with the eleven-check
+    Bsn,
+    /// German Steuer-IdNr, validated with its check digit
+    GermanTaxId,
+    /// Dutch and German postcodes
+    Postcode,
+    /// Dates preceded by a word like "born" or "geboren"
+    DateOfBirth,
+    /// Matched by a `CustomPiiPattern`
+    Custom,
+    /// Names tagged by the NER model
+    Person,
+    Organisation,
+    Location,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomPiiPattern {
+    /// Used for the placeholder, e.g. `CASE_NO` gives `[CASE_NO_1]`
+    pub label: String,
+    pub pattern: String,
+}
+
+/// Lowest confidence at which a name tagged by the NER model is masked
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NerThresholds {
+    pub person: f32,
+    pub organisation: f32,
+    pub location: f32,
+}
+
+impl Default for NerThresholds {
+    fn default() -> Self {
+        Self {
+            person: 0.5,
+            organisation: 0.7,
+            location: 0.7,
+        }
+    }
+}
+
+impl NerThresholds {
+    pub fn get(&self, kind: PiiKind) -> Option<f32> {
+        match kind {
+            PiiKind::Person => Some(self.person),
+            PiiKind::Organisation => Some(self.organisation),
+            PiiKind::Location => Some(self.location),
+            _ => None,
+        }
+    }
+}
+
+/// Name detection by a local model, on top of the pattern detectors
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NerSetting {
+    pub enabled: bool,
+    /// Model asked to tag the names; it must run on this machine
+    pub model_id: Option<i32>,
+    #[serde(default)]
+    pub thresholds: NerThresholds,
+}
+
+/// How messages of a redacted exchange are persisted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RedactedStorage {
+    /// As the user wrote and reads them
+    #[default]
+    Clear,
+    /// With placeholders, as the model saw them; the vault maps them back.
+    /// Needs a passphrase, since the vault is only kept sealed.
+    Masked,
+}
+
+/// Masking of personal data before chat history is sent to a model
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionSetting {
+    pub enabled: bool,
+    pub detectors: Vec<PiiKind>,
+    #[serde(default)]
+    pub custom_patterns: Vec<CustomPiiPattern>,
+    #[serde(default)]
+    pub ner: NerSetting,
+    #[serde(default)]
+    pub storage: RedactedStorage,
+}
+
+impl Default for RedactionSetting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detectors: vec![
+                PiiKind::Email,
+                PiiKind::Phone,
+                PiiKind::Iban,
+                PiiKind::Bsn,
+                PiiKind::GermanTaxId,
+                PiiKind::Postcode,
+                PiiKind::DateOfBirth,
+            ],
+            custom_patterns: Vec::new(),
+            ner: NerSetting::default(),
+            storage: RedactedStorage::default(),
+        }
+    }
+}
+
+/// Scheduled snapshots of the database into a local folder
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSetting {
+    pub enabled: bool,
+    /// `backups` in the app data folder when unset
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default = "default_backup_interval_hours")]
+    pub interval_hours: u32,
+    /// Days for which the newest snapshot is kept
+    #[serde(default = "default_keep_daily")]
+    pub keep_daily: u32,
+    /// Weeks for which the newest snapshot is kept
+    #[serde(default = "default_keep_weekly")]
+    pub keep_weekly: u32,
+}
+
+fn default_backup_interval_hours() -> u32 {
+    24
+}
+
+fn default_keep_daily() -> u32 {
+    7
+}
+
+fn default_keep_weekly() -> u32 {
+    4
+}
+
+impl Default for BackupSetting {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            folder: None,
+            interval_hours: default_backup_interval_hours(),
+            keep_daily: default_keep_daily(),
+            keep_weekly: default_keep_weekly(),
+        }
+    }
+}
+
+/// Row in `settings` holding the encryption parameters. It is not a
+/// `SettingKey`, so `update_settings` cannot overwrite it from the webview.
+pub const ENCRYPTION_SETTING_KEY: &str = "encryption";
+
+/// How the data key is derived from the passphrase, plus a value sealed
+/// with it to tell a wrong passphrase apart. The key itself is never stored.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionSetting {
+    /// Base64 Argon2id salt
+    pub salt: String,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub verifier: String,
+}
+
+/// How history is cut down when it does not fit the model's context window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContextStrategy {
+    DropOldest,
+    #[default]
+    KeepSystemPlusRecent,
+    SummariseOlderTurns,
+}
+
 // get max_tokens from general setting
 pub async fn get_max_tokens(db: &DatabaseConnection) -> Result<u32, DbErr> {
     let setting = Entity::find_by_id(SettingKey::General.as_str().to_string()).one(db).await?;
@@ -93,3 +284,60 @@ pub async fn get_max_tokens(db: &DatabaseConnection) -> Result<u32, DbErr> {
         Ok(0)
     }
 }
+
+/// Idle minutes before the app locks itself when no `autoLockMinutes` is set
+pub const DEFAULT_AUTO_LOCK_MINUTES: u64 = 15;
+
+// get autoLockMinutes from general setting, 0 disables auto-lock
+pub async fn get_auto_lock_minutes(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let setting = Entity::find_by_id(SettingKey::General.as_str().to_string()).one(db).await?;
+    if let Some(s) = setting {
+        let general_setting: serde_json::Value = serde_json::from_str(&s.value).unwrap_or_default();
+        Ok(general_setting["autoLockMinutes"].as_u64().unwrap_or(DEFAULT_AUTO_LOCK_MINUTES))
+    } else {
+        Ok(DEFAULT_AUTO_LOCK_MINUTES)
+    }
+}
+
+// get retentionDays from general setting, 0 keeps conversations forever
+pub async fn get_retention_days(db: &DatabaseConnection) -> Result<u64, DbErr> {
+    let setting = Entity::find_by_id(SettingKey::General.as_str().to_string()).one(db).await?;
+    if let Some(s) = setting {
+        let general_setting: serde_json::Value = serde_json::from_str(&s.value).unwrap_or_default();
+        Ok(general_setting["retentionDays"].as_u64().unwrap_or(0))
+    } else {
+        Ok(0)
+    }
+}
+
+// get the redaction setting, off with every built-in detector selected when unset
+pub async fn get_redaction_setting(db: &DatabaseConnection) -> Result<RedactionSetting, DbErr> {
+    let setting = Entity::find_by_id(SettingKey::Redaction.as_str().to_string()).one(db).await?;
+    match setting {
+        Some(s) => serde_json::from_str(&s.value)
+            .map_err(|_| DbErr::Json("Failed to parse redaction setting".to_string())),
+        None => Ok(RedactionSetting::default()),
+    }
+}
+
+// get contextStrategy from general setting
+pub async fn get_context_strategy(db: &DatabaseConnection) -> Result<ContextStrategy, DbErr> {
+    let setting = Entity::find_by_id(SettingKey::General.as_str().to_string()).one(db).await?;
+    if let Some(s) = setting {
+        let general_setting: serde_json::Value = serde_json::from_str(&s.value).unwrap_or_default();
+        let strategy = serde_json::from_value(general_setting["contextStrategy"].clone()).unwrap_or_default();
+        Ok(strategy)
+    } else {
+        Ok(ContextStrategy::default())
+    }
+}
+
+// get the backup setting, off when unset
+pub async fn get_backup_setting(db: &DatabaseConnection) -> Result<BackupSetting, DbErr> {
+    let setting = Entity::find_by_id(SettingKey::Backup.as_str().to_string()).one(db).await?;
+    match setting {
+        Some(s) => serde_json::from_str(&s.value)
+            .map_err(|_| DbErr::Json("Failed to parse backup setting".to_string())),
+        None => Ok(BackupSetting::default()),
+    }
+}
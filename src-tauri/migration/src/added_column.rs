@@ -0,0 +1,44 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+/// Runs a migration that adds `column` only where the column is missing.
+/// The create migrations build tables from the current entities, so a fresh
+/// database already has every column before the migrations that added them
+/// to older databases run.
+pub struct AddedColumn<M> {
+    migration: M,
+    table: &'static str,
+    column: &'static str,
+}
+
+impl<M> AddedColumn<M> {
+    pub fn new(migration: M, table: &'static str, column: &'static str) -> Self {
+        Self {
+            migration,
+            table,
+            column,
+        }
+    }
+}
+
+/// Recorded under the name of the wrapped migration, so databases that ran
+/// it before see it as applied
+impl<M: MigrationName> MigrationName for AddedColumn<M> {
+    fn name(&self) -> &str {
+        self.migration.name()
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: MigrationTrait> MigrationTrait for AddedColumn<M> {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if manager.has_column(self.table, self.column).await? {
+            return Ok(());
+        }
+        self.migration.up(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        self.migration.down(manager).await
+    }
+}
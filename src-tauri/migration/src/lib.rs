@@ -1,6 +1,7 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
 use sea_orm_migration::prelude::*;
 
+mod added_column;
 mod m20240101_000001_create_models;
 mod m20240101_000002_create_settings;
 mod m20240101_000003_create_conversations;
@@ -11,6 +12,19 @@ mod m20240101_100001_seed_settings;
 mod m20240101_100002_seed_prompts;
 mod m20240820_000001_conversations_add_last_message_at;
 mod m20250214_000001_messages_add_reasoning_fields;
+mod m20261017_000001_messages_add_reasoning;
+mod m20261017_000002_create_conversation_summaries;
+mod m20261017_000003_create_messages_fts;
+mod m20261017_000004_add_listing_indexes;
+mod m20261017_000005_messages_add_parent_id;
+mod m20261017_000006_add_retention;
+mod m20261017_000007_create_audit_log;
+mod m20261017_000008_create_pii_vault;
+mod m20261017_000009_messages_add_provenance;
+mod m20261017_000010_create_projects;
+mod m20261017_000011_projects_add_retention;
+
+use added_column::AddedColumn;
 
 pub struct Migrator;
 
@@ -26,8 +40,31 @@ impl MigratorTrait for Migrator {
             Box::new(m20240101_000006_create_contents::Migration),
             Box::new(m20240101_100001_seed_settings::Migration),
             Box::new(m20240101_100002_seed_prompts::Migration),
-            Box::new(m20240820_000001_conversations_add_last_message_at::Migration),
-            Box::new(m20250214_000001_messages_add_reasoning_fields::Migration),
+            Box::new(AddedColumn::new(
+                m20240820_000001_conversations_add_last_message_at::Migration,
+                "conversations",
+                "last_message_at",
+            )),
+            Box::new(AddedColumn::new(
+                m20250214_000001_messages_add_reasoning_fields::Migration,
+                "messages",
+                "prompt_token",
+            )),
+            Box::new(AddedColumn::new(m20261017_000001_messages_add_reasoning::Migration, "messages", "reasoning")),
+            Box::new(m20261017_000002_create_conversation_summaries::Migration),
+            Box::new(m20261017_000003_create_messages_fts::Migration),
+            Box::new(m20261017_000004_add_listing_indexes::Migration),
+            Box::new(m20261017_000005_messages_add_parent_id::Migration),
+            Box::new(m20261017_000006_add_retention::Migration),
+            Box::new(m20261017_000007_create_audit_log::Migration),
+            Box::new(m20261017_000008_create_pii_vault::Migration),
+            Box::new(m20261017_000009_messages_add_provenance::Migration),
+            Box::new(m20261017_000010_create_projects::Migration),
+            Box::new(AddedColumn::new(
+                m20261017_000011_projects_add_retention::Migration,
+                "projects",
+                "retention_days",
+            )),
         ]
     }
 }
\ No newline at end of file
@@ -0,0 +1,32 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use entity::entities::messages;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(messages::Entity)
+                    .add_column(ColumnDef::new(Alias::new("reasoning")).text())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(messages::Entity)
+                    .drop_column(Alias::new("reasoning"))
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,56 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use entity::entities::{conversations, messages};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Indexes matching the keyset paging of conversations and messages
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_conversations_last_message_at")
+                    .table(conversations::Entity)
+                    .col(conversations::Column::LastMessageAt)
+                    .col(conversations::Column::Id)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_messages_conversation_created_at")
+                    .table(messages::Entity)
+                    .col(messages::Column::ConversationId)
+                    .col(messages::Column::CreatedAt)
+                    .col(messages::Column::Id)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_messages_conversation_created_at")
+                    .table(messages::Entity)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_conversations_last_message_at")
+                    .table(conversations::Entity)
+                    .to_owned(),
+            )
+            .await
+    }
+}
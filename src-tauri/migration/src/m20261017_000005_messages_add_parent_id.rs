@@ -0,0 +1,86 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use entity::entities::{conversations, messages};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Existing conversations are linear: each message follows the one before it
+const BACKFILL: &[&str] = &[
+    "UPDATE messages SET parent_id = (
+        SELECT MAX(p.id) FROM messages p
+        WHERE p.conversation_id = messages.conversation_id AND p.id < messages.id
+    ) WHERE parent_id IS NULL",
+    "UPDATE conversations SET active_message_id = (
+        SELECT MAX(m.id) FROM messages m WHERE m.conversation_id = conversations.id
+    ) WHERE active_message_id IS NULL",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager.has_column("messages", "parent_id").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(messages::Entity)
+                        .add_column(ColumnDef::new(Alias::new("parent_id")).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager.has_column("conversations", "active_message_id").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(conversations::Entity)
+                        .add_column(ColumnDef::new(Alias::new("active_message_id")).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_messages_parent_id")
+                    .table(messages::Entity)
+                    .col(messages::Column::ParentId)
+                    .to_owned(),
+            )
+            .await?;
+        let db = manager.get_connection();
+        for sql in BACKFILL {
+            db.execute_unprepared(sql).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_messages_parent_id")
+                    .table(messages::Entity)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(conversations::Entity)
+                    .drop_column(Alias::new("active_message_id"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(messages::Entity)
+                    .drop_column(Alias::new("parent_id"))
+                    .to_owned(),
+            )
+            .await
+    }
+}
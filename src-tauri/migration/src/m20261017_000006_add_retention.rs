@@ -0,0 +1,67 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+use entity::entities::{conversations, retention_purges};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager.has_column("conversations", "retention_days").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(conversations::Entity)
+                        .add_column(ColumnDef::new(Alias::new("retention_days")).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for flag in ["pinned", "legal_hold"] {
+            if !manager.has_column("conversations", flag).await? {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(conversations::Entity)
+                            .add_column(
+                                ColumnDef::new(Alias::new(flag))
+                                    .boolean()
+                                    .not_null()
+                                    .default(false),
+                            )
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        let schema = Schema::new(manager.get_database_backend());
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(retention_purges::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(retention_purges::Entity).to_owned())
+            .await?;
+        for column in ["legal_hold", "pinned", "retention_days"] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(conversations::Entity)
+                        .drop_column(Alias::new(column))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
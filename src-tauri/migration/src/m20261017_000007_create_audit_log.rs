@@ -0,0 +1,54 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+use entity::entities::audit_log;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// The log is append-only at the database level too
+const APPEND_ONLY: &[&str] = &[
+    "CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+        SELECT RAISE(ABORT, 'audit_log is append-only');
+    END",
+    "CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+        SELECT RAISE(ABORT, 'audit_log is append-only');
+    END",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(audit_log::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx_audit_log_created_at")
+                    .table(audit_log::Entity)
+                    .col(audit_log::Column::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+        let db = manager.get_connection();
+        for sql in APPEND_ONLY {
+            db.execute_unprepared(sql).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(audit_log::Entity).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,42 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+use entity::entities::pii_vault;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(pii_vault::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        // one value per placeholder within a conversation
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .unique()
+                    .name("idx_pii_vault_conversation_placeholder")
+                    .table(pii_vault::Entity)
+                    .col(pii_vault::Column::ConversationId)
+                    .col(pii_vault::Column::Placeholder)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(pii_vault::Entity).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,58 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use entity::entities::messages;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+const TEXT_COLUMNS: &[&str] = &[
+    "provider",
+    "model_name",
+    "generation_options",
+    "redaction",
+    "app_version",
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager.has_column("messages", "model_id").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(messages::Entity)
+                        .add_column(ColumnDef::new(Alias::new("model_id")).integer())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        for column in TEXT_COLUMNS {
+            if !manager.has_column("messages", column).await? {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(messages::Entity)
+                            .add_column(ColumnDef::new(Alias::new(*column)).string())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in TEXT_COLUMNS.iter().rev().chain(["model_id"].iter()) {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(messages::Entity)
+                        .drop_column(Alias::new(*column))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
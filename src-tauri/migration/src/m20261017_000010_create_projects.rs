@@ -0,0 +1,73 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+use entity::entities::projects;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Tables that are filed under a project, with the index for listing a
+/// project's rows
+const FILED: &[(&str, &str)] = &[
+    ("conversations", "idx_conversations_project_id"),
+    ("prompts", "idx_prompts_project_id"),
+    ("contents", "idx_contents_project_id"),
+];
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        manager
+            .create_table(
+                schema
+                    .create_table_from_entity(projects::Entity)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await?;
+        for (table, index) in FILED {
+            if !manager.has_column(table, "project_id").await? {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(Alias::new(*table))
+                            .add_column(ColumnDef::new(Alias::new("project_id")).integer())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+            manager
+                .create_index(
+                    Index::create()
+                        .if_not_exists()
+                        .name(*index)
+                        .table(Alias::new(*table))
+                        .col(Alias::new("project_id"))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (table, index) in FILED {
+            manager
+                .drop_index(Index::drop().name(*index).table(Alias::new(*table)).to_owned())
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Alias::new(*table))
+                        .drop_column(Alias::new("project_id"))
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .drop_table(Table::drop().table(projects::Entity).to_owned())
+            .await
+    }
+}
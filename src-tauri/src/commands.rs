@@ -1,7 +1,8 @@
 // This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
 // BEAR LLM AI changes - Removed unused imports State and Wry
 // MIT License Copyright (c) 2024-present Frank Zhang
-use serde_json::Value;
+use serde_json::{json, Value};
+use zeroize::Zeroizing;
 use tauri::{
     AppHandle,
     Emitter,
@@ -9,23 +10,51 @@ use tauri::{
 };
 
 use crate::{
-    core::handle::BearLlmAiHandle,
+    core::{
+        backups::BackupStatus,
+        handle::BearLlmAiHandle,
+        session,
+        streams::{
+            ChatStreamChunk, ChatStreamEnd, ChatStreamError, ChatStreamStatus,
+            CHAT_STREAM_CHUNK_EVENT, CHAT_STREAM_END_EVENT, CHAT_STREAM_ERROR_EVENT,
+            STREAMS_STORED_TIMEOUT,
+        },
+        tasks,
+    },
     errors::BearLlmAiError,
     services::{
+        audit,
+        backup::{self, BackupManifest, BackupSnapshot, RestoreReport},
         db::Db,
+        cache,
+        encryption::{self, Encryption, EncryptionStatus},
+        erasure::{self, ErasureReport},
+        export,
+        import,
         llm::{
             chat::{BotReply, GlobalSettings},
             client::LLMClient,
             models::RemoteModel,
         },
+        pii::{self, ner::NerDetector, Masking, PiiDetectors, PiiPreview, Pseudonym, Pseudonyms, ReplyUnmasker},
+        summaries,
     },
 };
 use entity::entities::{
-    conversations::{self, Conversation, GenericOptions},
-    messages::{self, Message, MessageDTO},
+    audit_log::{AuditAction, AuditEntry, AuditExportFormat, AuditLogQuery, AuditVerification},
+    contents::{self, Content},
+    conversation_summaries::ConversationSummary,
+    conversations::{
+        self, Conversation, ExportFormat, ExportOptions, GenericOptions, ImportFormat, ImportReport, RetentionOverride,
+    },
+    messages::{self, Message, MessageDTO, MessageSearchHit, MessageSearchQuery, PersistOptions, Provenance},
     models::{self, Model, Provider},
+    pagination::{Page, PageQuery},
     prompts::{self, Prompt},
-    settings::{self, Setting, SettingKey},
+    retention_purges::RetentionPurge,
+    pii_vault::PiiVaultEntry,
+    projects::{Project, ProjectDTO, ProjectPayload},
+    settings::{self, RedactedStorage, RedactionSetting, Setting, SettingKey},
 };
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
@@ -42,7 +71,10 @@ pub async fn update_settings(
     payload: Vec<Setting>,
     handle: AppHandle,
 ) -> Result<(), BearLlmAiError> {
-    Db::update_settings(&handle.state::<BearLlmAiHandle>().db, payload).await?;
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    let keys: Vec<String> = payload.iter().map(|s| s.key.to_string()).collect();
+    Db::update_settings(db, payload).await?;
+    audit::record(db, AuditAction::SettingsUpdated, None, json!({ "keys": keys })).await;
     Ok(())
 }
 
@@ -55,6 +87,379 @@ pub async fn get_setting(
     Ok(res)
 }
 
+// --- Encryption
+#[tauri::command]
+pub async fn get_encryption_status(handle: AppHandle) -> Result<EncryptionStatus, BearLlmAiError> {
+    Ok(handle.state::<BearLlmAiHandle>().encryption.status())
+}
+
+/// Encrypt the stored history with a key derived from `passphrase`. There is
+/// no recovery: without the passphrase the history cannot be read again.
+#[tauri::command]
+pub async fn enable_encryption(
+    passphrase: String,
+    handle: AppHandle,
+) -> Result<EncryptionStatus, BearLlmAiError> {
+    let passphrase = Zeroizing::new(passphrase);
+    // Argon2 is slow on purpose, keep it off the async workers
+    let (key, setting) =
+        tauri::async_runtime::spawn_blocking(move || encryption::new_key(&passphrase)).await??;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    Db::enable_encryption(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, key, setting).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::EncryptionEnabled, None, json!({})).await;
+    // the plaintext is still in free pages and the WAL
+    Db::vacuum(&bear_llm_ai_handle.db).await;
+    Ok(bear_llm_ai_handle.encryption.status())
+}
+
+#[tauri::command]
+pub async fn unlock_database(
+    passphrase: String,
+    handle: AppHandle,
+) -> Result<EncryptionStatus, BearLlmAiError> {
+    let passphrase = Zeroizing::new(passphrase);
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let setting = Db::get_encryption_setting(&bear_llm_ai_handle.db)
+        .await?
+        .ok_or(BearLlmAiError::Encryption("Encryption is not enabled".to_string()))?;
+    let key = match tauri::async_runtime::spawn_blocking(move || encryption::unlock_key(&passphrase, &setting))
+        .await?
+    {
+        Ok(key) => key,
+        Err(err) => {
+            audit::record(&bear_llm_ai_handle.db, AuditAction::UnlockFailed, None, json!({})).await;
+            return Err(err);
+        }
+    };
+    bear_llm_ai_handle.encryption.unlock(key);
+    audit::record(&bear_llm_ai_handle.db, AuditAction::AppUnlocked, None, json!({})).await;
+    Ok(bear_llm_ai_handle.encryption.status())
+}
+
+/// Lock right away, e.g. before leaving the workstation. Replies still
+/// streaming are stopped and what arrived of them is stored first, since the
+/// key is needed to seal it.
+#[tauri::command]
+pub async fn lock_app(handle: AppHandle) -> Result<EncryptionStatus, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    if !bear_llm_ai_handle.encryption.is_enabled() {
+        return Err(BearLlmAiError::Encryption(
+            "Set a passphrase before locking the app".to_string(),
+        ));
+    }
+    bear_llm_ai_handle.streams.cancel_all();
+    if tokio::time::timeout(STREAMS_STORED_TIMEOUT, bear_llm_ai_handle.streams.wait_finished())
+        .await
+        .is_err()
+    {
+        log::warn!("Locking before every cancelled reply was stored");
+    }
+    if session::lock(&handle) {
+        audit::record(&bear_llm_ai_handle.db, AuditAction::AppLocked, None, json!({ "reason": "manual" })).await;
+    }
+    Ok(bear_llm_ai_handle.encryption.status())
+}
+
+// --- Erasure
+/// Erase everything the app keeps on this machine (GDPR Art. 17) and create a
+/// fresh database through the migrations. The connection held by the running
+/// app is closed, so the webview should call `restart_app` once it has shown
+/// the report.
+#[tauri::command]
+pub async fn erase_all_data(handle: AppHandle) -> Result<ErasureReport, BearLlmAiError> {
+    erase_everything(&handle, "request").await
+}
+
+/// The way out of a forgotten passphrase, available on the lock screen: the
+/// same erasure as `erase_all_data`, but only once the user has typed
+/// `session::RESET_CONFIRMATION`. Nothing encrypted can be recovered without
+/// the passphrase, so starting over is all that is left.
+#[tauri::command]
+pub async fn reset_forgotten_passphrase(
+    confirmation: String,
+    handle: AppHandle,
+) -> Result<ErasureReport, BearLlmAiError> {
+    if !handle.state::<BearLlmAiHandle>().encryption.is_enabled() {
+        return Err(BearLlmAiError::Encryption("No passphrase has been set".to_string()));
+    }
+    if confirmation.trim() != session::RESET_CONFIRMATION {
+        return Err(BearLlmAiError::Invalid(format!(
+            "Type {} to erase all data",
+            session::RESET_CONFIRMATION
+        )));
+    }
+    erase_everything(&handle, "forgotten passphrase").await
+}
+
+async fn erase_everything(handle: &AppHandle, reason: &str) -> Result<ErasureReport, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let app_data_dir = handle.path().app_data_dir()?;
+    log::warn!("Erasing all data in {:?}", app_data_dir);
+    let backup_folder = settings::get_backup_setting(&bear_llm_ai_handle.db)
+        .await
+        .map(|setting| backup::folder(&app_data_dir, &setting))
+        .unwrap_or_else(|_| backup::default_folder(&app_data_dir));
+
+    // Nothing may write to the files while they are erased
+    bear_llm_ai_handle.streams.cancel_all();
+    bear_llm_ai_handle.db.clone().close().await?;
+
+    let mut report = ErasureReport::default();
+    for path in Db::files(&app_data_dir) {
+        report.erase(&path);
+    }
+    // copies left by a backup or restore that did not finish
+    for path in backup::scratch_files(&app_data_dir) {
+        report.erase(&path);
+    }
+    // scheduled backups and damaged databases set aside hold copies of it all
+    for snapshot in backup::list_snapshots(&backup_folder).unwrap_or_default() {
+        report.erase(&snapshot.path);
+    }
+    report.erase(&backup::default_folder(&app_data_dir));
+    for path in backup::damaged_copies(&app_data_dir).unwrap_or_default() {
+        report.erase(&path);
+    }
+    report.erase(&app_data_dir.join("crash.log"));
+    report.erase(&app_data_dir.join("diagnostics.log"));
+    if let Ok(log_dir) = handle.path().app_log_dir() {
+        report.erase(&log_dir);
+    }
+    if let Some(local_app_dir) = erasure::local_app_dir() {
+        report.erase(&local_app_dir.join("preinit.log"));
+        report.erase(&local_app_dir.join("fatal_error.log"));
+        let webview_dir = std::env::var("WEBVIEW2_USER_DATA_FOLDER")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|_| local_app_dir.join("WebView2"));
+        report.erase(&webview_dir);
+        // The running webview keeps its profile open
+        report.schedule_pending(&webview_dir);
+    }
+    cache::clear_prompts_cache(handle);
+    pii::ner::forget_tagged();
+    bear_llm_ai_handle.encryption.disable();
+
+    let fresh = Db::new(&app_data_dir)
+        .await
+        .map_err(|e| sea_orm::DbErr::Custom(e.to_string()))?;
+    // the old log went with the database, so the new one starts with the erasure
+    audit::record(
+        &fresh.0,
+        AuditAction::DataErased,
+        None,
+        json!({
+            "reason": reason,
+            "removed": report.removed.len(),
+            "left_behind": report.left_behind.len(),
+        }),
+    )
+    .await;
+    fresh.0.close().await?;
+    report.database_recreated = true;
+    report.restart_required = true;
+    log::warn!(
+        "Erasure finished: {} removed, {} left behind",
+        report.removed.len(),
+        report.left_behind.len()
+    );
+    Ok(report)
+}
+
+// --- Backup
+/// Write a backup of the whole database to `path`, sealed with `passphrase`
+/// when one is given
+#[tauri::command]
+pub async fn create_backup(
+    path: String,
+    passphrase: Option<String>,
+    handle: AppHandle,
+) -> Result<BackupManifest, BearLlmAiError> {
+    let passphrase = passphrase.map(Zeroizing::new);
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    let app_data_dir = handle.path().app_data_dir()?;
+    let (manifest, archive) = backup::create(
+        db,
+        &app_data_dir,
+        &handle.package_info().version.to_string(),
+        passphrase.as_deref().map(String::as_str),
+    )
+    .await?;
+    tokio::fs::write(&path, archive).await?;
+    audit::record(
+        db,
+        AuditAction::BackupCreated,
+        None,
+        json!({ "encrypted": manifest.encryption.is_some(), "schema_version": manifest.schema_version }),
+    )
+    .await;
+    Ok(manifest)
+}
+
+/// Read the manifest of the backup at `path`, e.g. to ask for a passphrase
+/// only when the backup is sealed
+#[tauri::command]
+pub async fn inspect_backup(path: String) -> Result<BackupManifest, BearLlmAiError> {
+    let archive = tokio::fs::read(&path).await?;
+    backup::read_manifest(&archive)
+}
+
+/// Replace the database with the one in the backup at `path`. A backup from
+/// an older version is migrated first, one from a newer version is refused.
+/// The live database is only touched once the restored one has been checked.
+#[tauri::command]
+pub async fn restore_backup(
+    path: String,
+    passphrase: Option<String>,
+    handle: AppHandle,
+) -> Result<RestoreReport, BearLlmAiError> {
+    let passphrase = passphrase.map(Zeroizing::new);
+    let app_data_dir = handle.path().app_data_dir()?;
+    let archive = tokio::fs::read(&path).await?;
+    let (manifest, snapshot) =
+        tauri::async_runtime::spawn_blocking(move || backup::unpack(&archive, passphrase.as_deref().map(String::as_str)))
+            .await??;
+    let migrations_applied = backup::stage(&app_data_dir, snapshot).await?;
+    replace_database(&handle, &app_data_dir, manifest, migrations_applied).await
+}
+
+/// Restore the newest scheduled backup that passes its checks, trying older
+/// ones when it does not; offered after a damaged database was set aside
+#[tauri::command]
+pub async fn restore_latest_backup(handle: AppHandle) -> Result<RestoreReport, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let app_data_dir = handle.path().app_data_dir()?;
+    let folder = match bear_llm_ai_handle.backups.recovery() {
+        Some(recovery) => recovery.folder,
+        None => backup::folder(&app_data_dir, &settings::get_backup_setting(&bear_llm_ai_handle.db).await?),
+    };
+    let mut last_error = None;
+    for snapshot in backup::list_snapshots(&folder)? {
+        let staged = async {
+            let archive = tokio::fs::read(&snapshot.path).await?;
+            let (manifest, data) = tauri::async_runtime::spawn_blocking(move || backup::unpack(&archive, None)).await??;
+            let migrations_applied = backup::stage(&app_data_dir, data).await?;
+            Ok::<_, BearLlmAiError>((manifest, migrations_applied))
+        }
+        .await;
+        match staged {
+            Ok((manifest, migrations_applied)) => {
+                return replace_database(&handle, &app_data_dir, manifest, migrations_applied).await;
+            }
+            Err(err) => {
+                log::warn!("Skipping backup {:?}: {}", snapshot.path, err);
+                last_error = Some(err);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        BearLlmAiError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, "No backups to restore"))
+    }))
+}
+
+/// Swap the staged snapshot in for the live database and record the restore
+/// in it
+async fn replace_database(
+    handle: &AppHandle,
+    app_data_dir: &std::path::Path,
+    manifest: BackupManifest,
+    migrations_applied: usize,
+) -> Result<RestoreReport, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    log::warn!("Restoring the database from a backup made on {}", manifest.created_at);
+
+    // Nothing may write to the database while it is replaced
+    bear_llm_ai_handle.streams.cancel_all();
+    bear_llm_ai_handle.db.clone().close().await?;
+    backup::swap_in(app_data_dir, &Db::files(app_data_dir))?;
+    cache::clear_prompts_cache(handle);
+    // the restored database has its own passphrase, if any
+    bear_llm_ai_handle.encryption.lock();
+
+    let restored = Db::new(app_data_dir)
+        .await
+        .map_err(|e| sea_orm::DbErr::Custom(e.to_string()))?;
+    audit::record(
+        &restored.0,
+        AuditAction::BackupRestored,
+        None,
+        json!({
+            "created_at": manifest.created_at,
+            "app_version": manifest.app_version,
+            "schema_version": manifest.schema_version,
+            "migrations_applied": migrations_applied,
+        }),
+    )
+    .await;
+    restored.0.close().await?;
+    Ok(RestoreReport {
+        manifest,
+        migrations_applied,
+        restart_required: true,
+    })
+}
+
+#[tauri::command]
+pub async fn get_backup_status(handle: AppHandle) -> Result<BackupStatus, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let setting = settings::get_backup_setting(&bear_llm_ai_handle.db).await?;
+    Ok(bear_llm_ai_handle
+        .backups
+        .status(&handle.path().app_data_dir()?, &setting))
+}
+
+/// Take a scheduled backup now if one is due, e.g. after the backup setting
+/// changed
+#[tauri::command]
+pub async fn run_scheduled_backup(handle: AppHandle) -> Result<Option<BackupSnapshot>, BearLlmAiError> {
+    let res = tasks::backup_if_due(&handle).await;
+    let backups = &handle.state::<BearLlmAiHandle>().backups;
+    match &res {
+        Ok(Some(_)) => backups.succeeded(),
+        Ok(None) => {}
+        Err(err) => backups.failed(err.to_string()),
+    }
+    res
+}
+
+/// Restart the app, e.g. to open the database created by `erase_all_data`
+#[tauri::command]
+pub fn restart_app(handle: AppHandle) {
+    handle.restart()
+}
+
+// --- Audit log
+#[tauri::command]
+pub async fn get_audit_log(query: AuditLogQuery, handle: AppHandle) -> Result<Vec<AuditEntry>, BearLlmAiError> {
+    let res = Db::get_audit_log(&handle.state::<BearLlmAiHandle>().db, query).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn verify_audit_log(handle: AppHandle) -> Result<AuditVerification, BearLlmAiError> {
+    let entries = Db::get_audit_chain(&handle.state::<BearLlmAiHandle>().db).await?;
+    Ok(audit::verify(&entries))
+}
+
+/// Write the whole log to `path`, oldest entry first. Returns the number of
+/// entries written.
+#[tauri::command]
+pub async fn export_audit_log(
+    format: AuditExportFormat,
+    path: String,
+    handle: AppHandle,
+) -> Result<usize, BearLlmAiError> {
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    let entries = Db::get_audit_chain(db).await?;
+    let content = match format {
+        AuditExportFormat::Csv => audit::to_csv(&entries),
+        AuditExportFormat::Json => serde_json::to_string_pretty(&entries)
+            .map_err(|err| sea_orm::DbErr::Json(err.to_string()))?,
+    };
+    tokio::fs::write(&path, content).await?;
+    audit::record(db, AuditAction::AuditLogExported, None, json!({ "format": format, "entries": entries.len() })).await;
+    Ok(entries.len())
+}
+
 // --- Models
 #[tauri::command]
 pub async fn get_models(handle: AppHandle) -> Result<Vec<Model>, BearLlmAiError> {
@@ -64,7 +469,9 @@ pub async fn get_models(handle: AppHandle) -> Result<Vec<Model>, BearLlmAiError>
 
 #[tauri::command]
 pub async fn create_model(payload: models::Model, handle: AppHandle) -> Result<Model, BearLlmAiError> {
-    let res = Db::create_model(&handle.state::<BearLlmAiHandle>().db, payload).await?;
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    let res = Db::create_model(db, payload).await?;
+    audit::record(db, AuditAction::ModelCreated, audit::target("model", res.id), json!({ "provider": res.provider, "name": res.name })).await;
     Ok(res)
 }
 
@@ -74,13 +481,17 @@ pub async fn update_model(
     payload: models::Model,
     handle: AppHandle,
 ) -> Result<Model, BearLlmAiError> {
-    let res = Db::update_model(&handle.state::<BearLlmAiHandle>().db, id, payload).await?;
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    let res = Db::update_model(db, id, payload).await?;
+    audit::record(db, AuditAction::ModelUpdated, audit::target("model", id), json!({ "provider": res.provider, "name": res.name })).await;
     Ok(res)
 }
 
 #[tauri::command]
 pub async fn delete_model(id: i32, handle: AppHandle) -> Result<(), BearLlmAiError> {
-    Db::delete_model(&handle.state::<BearLlmAiHandle>().db, id).await?;
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    Db::delete_model(db, id).await?;
+    audit::record(db, AuditAction::ModelDeleted, audit::target("model", id), json!({})).await;
     Ok(())
 }
 
@@ -107,10 +518,134 @@ pub async fn list_remote_models(
     Ok(models)
 }
 
+// --- Projects
+fn project_dto(project: Project, encryption: &Encryption) -> ProjectDTO {
+    ProjectDTO {
+        encrypted: encryption.is_project_encrypted(project.id),
+        locked: encryption.is_project_locked(project.id),
+        project,
+    }
+}
+
+#[tauri::command]
+pub async fn get_projects(handle: AppHandle) -> Result<Vec<ProjectDTO>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_projects(&bear_llm_ai_handle.db).await?;
+    Ok(res
+        .into_iter()
+        .map(|project| project_dto(project, &bear_llm_ai_handle.encryption))
+        .collect())
+}
+
+/// Create a client or matter folder. With a `passphrase` its content is
+/// sealed with a key of its own, which cannot be recovered either.
+#[tauri::command]
+pub async fn create_project(
+    payload: ProjectPayload,
+    passphrase: Option<String>,
+    handle: AppHandle,
+) -> Result<ProjectDTO, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let key = match passphrase.map(Zeroizing::new) {
+        // Argon2 is slow on purpose, keep it off the async workers
+        Some(passphrase) => Some(
+            tauri::async_runtime::spawn_blocking(move || encryption::new_key(&passphrase)).await??,
+        ),
+        None => None,
+    };
+    let (key, setting) = key.unzip();
+    let res = Db::create_project(&bear_llm_ai_handle.db, payload, setting).await?;
+    if let Some(key) = key {
+        bear_llm_ai_handle.encryption.register_project(res.id);
+        bear_llm_ai_handle.encryption.unlock_project(res.id, key);
+    }
+    let res = project_dto(res, &bear_llm_ai_handle.encryption);
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ProjectCreated, audit::target("project", res.project.id), json!({ "encrypted": res.encrypted })).await;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn update_project(
+    id: i32,
+    payload: ProjectPayload,
+    handle: AppHandle,
+) -> Result<ProjectDTO, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::update_project(&bear_llm_ai_handle.db, id, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ProjectUpdated, audit::target("project", id), json!({})).await;
+    Ok(project_dto(res, &bear_llm_ai_handle.encryption))
+}
+
+/// Delete a project together with its conversations, prompts and documents
+#[tauri::command]
+pub async fn delete_project(id: i32, handle: AppHandle) -> Result<(), BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let conversations = Db::delete_project(&bear_llm_ai_handle.db, id).await?;
+    bear_llm_ai_handle.encryption.forget_project(id);
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ProjectDeleted, audit::target("project", id), json!({ "conversations": conversations })).await;
+    Db::vacuum(&bear_llm_ai_handle.db).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unlock_project(id: i32, passphrase: String, handle: AppHandle) -> Result<ProjectDTO, BearLlmAiError> {
+    let passphrase = Zeroizing::new(passphrase);
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let setting = Db::get_project_encryption_setting(&bear_llm_ai_handle.db, id)
+        .await?
+        .ok_or(BearLlmAiError::Encryption("The project has no passphrase".to_string()))?;
+    let key = match tauri::async_runtime::spawn_blocking(move || encryption::unlock_key(&passphrase, &setting))
+        .await?
+    {
+        Ok(key) => key,
+        Err(err) => {
+            audit::record(&bear_llm_ai_handle.db, AuditAction::UnlockFailed, audit::target("project", id), json!({})).await;
+            return Err(err);
+        }
+    };
+    bear_llm_ai_handle.encryption.unlock_project(id, key);
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ProjectUnlocked, audit::target("project", id), json!({})).await;
+    let res = Db::get_project(&bear_llm_ai_handle.db, id).await?;
+    Ok(project_dto(res, &bear_llm_ai_handle.encryption))
+}
+
+#[tauri::command]
+pub async fn lock_project(id: i32, handle: AppHandle) -> Result<ProjectDTO, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_project(&bear_llm_ai_handle.db, id).await?;
+    if !bear_llm_ai_handle.encryption.is_project_encrypted(id) {
+        return Err(BearLlmAiError::Encryption("The project has no passphrase".to_string()));
+    }
+    bear_llm_ai_handle.encryption.lock_project(id);
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ProjectLocked, audit::target("project", id), json!({})).await;
+    Ok(project_dto(res, &bear_llm_ai_handle.encryption))
+}
+
 // --- Conversations
 #[tauri::command]
 pub async fn get_conversations(handle: AppHandle) -> Result<Vec<Conversation>, BearLlmAiError> {
-    let res = Db::get_conversations(&handle.state::<BearLlmAiHandle>().db).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_conversations(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn get_conversations_page(
+    query: PageQuery,
+    handle: AppHandle,
+) -> Result<Page<Conversation>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_conversations_page(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, query).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn get_project_conversations(
+    project_id: i32,
+    handle: AppHandle,
+) -> Result<Vec<Conversation>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_project_conversations(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, project_id).await?;
     Ok(res)
 }
 
@@ -119,7 +654,9 @@ pub async fn create_conversation(
     payload: conversations::Model,
     handle: AppHandle,
 ) -> Result<Conversation, BearLlmAiError> {
-    let res = Db::create_conversation(&handle.state::<BearLlmAiHandle>().db, payload).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::create_conversation(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ConversationCreated, audit::target("conversation", res.id), json!({ "model_id": res.model_id, "project_id": res.project_id })).await;
     Ok(res)
 }
 
@@ -129,45 +666,281 @@ pub async fn update_conversation(
     payload: conversations::Model,
     handle: AppHandle,
 ) -> Result<Conversation, BearLlmAiError> {
-    let res = Db::update_conversation(&handle.state::<BearLlmAiHandle>().db, id, payload).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::update_conversation(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::ConversationUpdated, audit::target("conversation", id), json!({ "model_id": res.model_id })).await;
+    Ok(res)
+}
+
+/// File a conversation under another project, or under none
+#[tauri::command]
+pub async fn move_conversation(
+    id: i32,
+    project_id: Option<i32>,
+    handle: AppHandle,
+) -> Result<Conversation, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let (res, from) = Db::move_conversation(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id, project_id).await?;
+    if from != project_id {
+        audit::record(&bear_llm_ai_handle.db, AuditAction::ConversationMoved, audit::target("conversation", id), json!({ "from": from, "to": project_id })).await;
+        if project_id.is_some_and(|project_id| bear_llm_ai_handle.encryption.is_project_encrypted(project_id)) {
+            Db::vacuum(&bear_llm_ai_handle.db).await;
+        }
+    }
     Ok(res)
 }
 
 #[tauri::command]
 pub async fn delete_conversation(id: i32, handle: AppHandle) -> Result<(), BearLlmAiError> {
-    Db::delete_conversation(&handle.state::<BearLlmAiHandle>().db, id).await?;
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    Db::delete_conversation(db, id).await?;
+    audit::record(db, AuditAction::ConversationDeleted, audit::target("conversation", id), json!({})).await;
     Ok(())
 }
 
+// --- Retention
+#[tauri::command]
+pub async fn update_conversation_retention(
+    id: i32,
+    payload: RetentionOverride,
+    handle: AppHandle,
+) -> Result<Conversation, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let details = json!(payload);
+    let res = Db::update_conversation_retention(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::RetentionUpdated, audit::target("conversation", id), details).await;
+    Ok(res)
+}
+
+/// Apply the retention policy now instead of waiting for the hourly run
+#[tauri::command]
+pub async fn purge_expired_conversations(handle: AppHandle) -> Result<Vec<RetentionPurge>, BearLlmAiError> {
+    tasks::purge_expired(&handle).await
+}
+
+#[tauri::command]
+pub async fn get_retention_purges(handle: AppHandle) -> Result<Vec<RetentionPurge>, BearLlmAiError> {
+    let res = Db::get_retention_purges(&handle.state::<BearLlmAiHandle>().db).await?;
+    Ok(res)
+}
+
+/// What the placeholders of a conversation stand for, in clear
+#[tauri::command]
+pub async fn get_pii_vault(conversation_id: i32, handle: AppHandle) -> Result<Vec<PiiVaultEntry>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_pii_vault(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, conversation_id).await?;
+    audit::record(
+        &bear_llm_ai_handle.db,
+        AuditAction::PiiVaultViewed,
+        audit::target("conversation", conversation_id),
+        json!({ "entries": res.len() }),
+    )
+    .await;
+    Ok(res)
+}
+
+/// Forget the vault of a conversation; returns how many entries went
+#[tauri::command]
+pub async fn purge_pii_vault(conversation_id: i32, handle: AppHandle) -> Result<u64, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let purged = Db::purge_pii_vault(&bear_llm_ai_handle.db, conversation_id).await?;
+    audit::record(
+        &bear_llm_ai_handle.db,
+        AuditAction::PiiVaultPurged,
+        audit::target("conversation", conversation_id),
+        json!({ "entries": purged }),
+    )
+    .await;
+    if purged > 0 {
+        Db::vacuum(&bear_llm_ai_handle.db).await;
+    }
+    Ok(purged)
+}
+
 #[tauri::command]
 pub async fn get_conversation_messages(
     conversation_id: i32,
     handle: AppHandle,
 ) -> Result<Vec<Message>, BearLlmAiError> {
-    let res = Db::get_conversation_messages(&handle.state::<BearLlmAiHandle>().db, conversation_id).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_conversation_messages(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, conversation_id).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn get_conversation_messages_page(
+    conversation_id: i32,
+    query: PageQuery,
+    handle: AppHandle,
+) -> Result<Page<Message>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_conversation_messages_page(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, conversation_id, query).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn get_conversation_summaries(
+    conversation_id: i32,
+    handle: AppHandle,
+) -> Result<Vec<ConversationSummary>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_conversation_summaries(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, conversation_id).await?;
+    Ok(res)
+}
+
+/// Summarise a conversation afresh with the given model. Without
+/// `last_message_id` the range of the latest summary is redone, or the whole
+/// conversation if there is none yet. With redaction on, the model is sent
+/// the masked turns, as in a chat request.
+#[tauri::command]
+pub async fn regenerate_conversation_summary(
+    conversation_id: i32,
+    model_id: i32,
+    last_message_id: Option<i32>,
+    handle: AppHandle,
+) -> Result<ConversationSummary, String> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let model = Db::get_model(&bear_llm_ai_handle.db, model_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let proxy_setting = Db::get_proxy_setting(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let client = LLMClient::new(model.into(), proxy_setting)?;
+    let max_tokens = settings::get_max_tokens(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let budget = client.context_budget(max_tokens).await;
+    let mut redaction = redaction(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, Some(conversation_id)).await?;
+    let res = summaries::regenerate_summary(
+        &bear_llm_ai_handle.db,
+        &bear_llm_ai_handle.encryption,
+        &client,
+        conversation_id,
+        last_message_id,
+        &budget,
+        redaction.as_mut().map(|redaction| &mut redaction.masking),
+    )
+    .await?;
+    keep_pseudonyms(
+        &bear_llm_ai_handle.db,
+        &bear_llm_ai_handle.encryption,
+        Some(conversation_id),
+        redaction.as_ref(),
+    )
+    .await?;
     Ok(res)
 }
 
 // --- Messages
+async fn record_messages_created(db: &sea_orm::DatabaseConnection, messages: &[Message]) {
+    let Some(first) = messages.first() else {
+        return;
+    };
+    let ids: Vec<i32> = messages.iter().map(|m| m.id).collect();
+    audit::record(
+        db,
+        AuditAction::MessagesCreated,
+        audit::target("conversation", first.conversation_id),
+        json!({ "message_ids": ids }),
+    )
+    .await;
+}
+
 #[tauri::command]
 pub async fn create_messages(
     payload: Vec<messages::Model>,
     handle: AppHandle,
 ) -> Result<Vec<Message>, BearLlmAiError> {
-    let res = Db::create_messages(&handle.state::<BearLlmAiHandle>().db, payload).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::create_messages(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, payload).await?;
+    record_messages_created(&bear_llm_ai_handle.db, &res).await;
+    Ok(res)
+}
+
+/// The messages to send for the active branch of a conversation
+#[tauri::command]
+pub async fn get_active_path(conversation_id: i32, handle: AppHandle) -> Result<Vec<Message>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_message_path(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, conversation_id, None).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn edit_message(id: i32, content: String, handle: AppHandle) -> Result<Message, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::edit_message(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id, content).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::MessageEdited, audit::target("message", id), json!({ "conversation_id": res.conversation_id, "new_message_id": res.id })).await;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn get_message_branches(id: i32, handle: AppHandle) -> Result<Vec<Message>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_message_branches(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn switch_branch(id: i32, handle: AppHandle) -> Result<Vec<Message>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::switch_branch(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn delete_message(id: i32, handle: AppHandle) -> Result<(), BearLlmAiError> {
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    Db::delete_message(db, id).await?;
+    audit::record(db, AuditAction::MessageDeleted, audit::target("message", id), json!({})).await;
+    Ok(())
+}
+
+// --- Search
+#[tauri::command]
+pub async fn search_messages(
+    query: MessageSearchQuery,
+    handle: AppHandle,
+) -> Result<Vec<MessageSearchHit>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::search_messages(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, query).await?;
+    Ok(res)
+}
+
+/// `search_messages` within one project
+#[tauri::command]
+pub async fn search_project_messages(
+    project_id: i32,
+    mut query: MessageSearchQuery,
+    handle: AppHandle,
+) -> Result<Vec<MessageSearchHit>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    Db::get_project(&bear_llm_ai_handle.db, project_id).await?;
+    query.project_id = Some(project_id);
+    let res = Db::search_messages(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, query).await?;
     Ok(res)
 }
 
 // --- Prompts
 #[tauri::command]
 pub async fn get_prompts(handle: AppHandle) -> Result<Vec<Prompt>, BearLlmAiError> {
-    let res = Db::get_prompts(&handle.state::<BearLlmAiHandle>().db).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_prompts(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn get_project_prompts(project_id: i32, handle: AppHandle) -> Result<Vec<Prompt>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_project_prompts(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, project_id).await?;
     Ok(res)
 }
 
 #[tauri::command]
 pub async fn create_prompt(payload: prompts::Model, handle: AppHandle) -> Result<Prompt, BearLlmAiError> {
-    let res = Db::create_prompt(&handle.state::<BearLlmAiHandle>().db, payload).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::create_prompt(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::PromptCreated, audit::target("prompt", res.id), json!({ "project_id": res.project_id })).await;
     Ok(res)
 }
 
@@ -177,22 +950,321 @@ pub async fn update_prompt(
     payload: prompts::Model,
     handle: AppHandle,
 ) -> Result<Prompt, BearLlmAiError> {
-    let res = Db::update_prompt(&handle.state::<BearLlmAiHandle>().db, id, payload).await?;
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::update_prompt(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::PromptUpdated, audit::target("prompt", id), json!({ "project_id": res.project_id })).await;
     Ok(res)
 }
 
 #[tauri::command]
 pub async fn delete_prompt(id: i32, handle: AppHandle) -> Result<(), BearLlmAiError> {
-    Db::delete_prompt(&handle.state::<BearLlmAiHandle>().db, id).await?;
+    let db = &handle.state::<BearLlmAiHandle>().db;
+    Db::delete_prompt(db, id).await?;
+    audit::record(db, AuditAction::PromptDeleted, audit::target("prompt", id), json!({})).await;
     Ok(())
 }
 
+// --- Documents
+#[tauri::command]
+pub async fn get_documents(project_id: Option<i32>, handle: AppHandle) -> Result<Vec<Content>, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::get_documents(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, project_id).await?;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn create_document(payload: contents::Model, handle: AppHandle) -> Result<Content, BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let res = Db::create_document(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, payload).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::DocumentCreated, audit::target("document", res.id), json!({ "project_id": res.project_id })).await;
+    Ok(res)
+}
+
+#[tauri::command]
+pub async fn delete_document(id: i32, handle: AppHandle) -> Result<(), BearLlmAiError> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let project_id = Db::delete_document(&bear_llm_ai_handle.db, &bear_llm_ai_handle.encryption, id).await?;
+    audit::record(&bear_llm_ai_handle.db, AuditAction::DocumentDeleted, audit::target("document", id), json!({ "project_id": project_id })).await;
+    Ok(())
+}
+
+// --- Export
+/// Write the active branch of a conversation to `path`. Returns the number
+/// of messages written.
+#[tauri::command]
+pub async fn export_conversation(
+    conversation_id: i32,
+    format: ExportFormat,
+    options: ExportOptions,
+    path: String,
+    handle: AppHandle,
+) -> Result<usize, String> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let db = &bear_llm_ai_handle.db;
+    let conversation = Db::get_conversation(db, &bear_llm_ai_handle.encryption, conversation_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let messages = Db::get_message_path(db, &bear_llm_ai_handle.encryption, conversation_id, None)
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut document = export::document(
+        conversation,
+        messages,
+        &options,
+        &handle.package_info().version.to_string(),
+        chrono::Utc::now().naive_utc(),
+    );
+    if options.redact {
+        let setting = settings::get_redaction_setting(db)
+            .await
+            .map_err(|err| err.to_string())?;
+        let detectors = pii_detectors(db, &setting).await?;
+        export::redact(&mut document, &detectors).await?;
+    }
+    let content = export::render(&document, format).map_err(|err| err.to_string())?;
+    tokio::fs::write(&path, content)
+        .await
+        .map_err(|err| err.to_string())?;
+    audit::record(
+        db,
+        AuditAction::DataExported,
+        audit::target("conversation", conversation_id),
+        json!({ "format": format, "messages": document.messages.len(), "redacted": document.redacted }),
+    )
+    .await;
+    Ok(document.messages.len())
+}
+
+/// Create conversations from a file exported by this app or another chat
+/// client, all bound to `model_id` and filed under `project_id`. A
+/// conversation that cannot be stored is reported as skipped and the rest are
+/// still imported. Conversations keep their original dates, so the retention
+/// policy applies to them as usual.
+#[tauri::command]
+pub async fn import_conversations(
+    format: ImportFormat,
+    path: String,
+    model_id: i32,
+    project_id: Option<i32>,
+    handle: AppHandle,
+) -> Result<ImportReport, String> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let db = &bear_llm_ai_handle.db;
+    Db::get_model(db, model_id).await.map_err(|err| err.to_string())?;
+    if let Some(project_id) = project_id {
+        Db::get_project(db, project_id).await.map_err(|err| err.to_string())?;
+    }
+    let data = tokio::fs::read(&path).await.map_err(|err| err.to_string())?;
+    let imported = import::parse(format, &data)?;
+    let mut report = ImportReport {
+        skipped: imported.skipped,
+        ..Default::default()
+    };
+    for conversation in imported.conversations {
+        let name = conversation.conversation.name.clone();
+        let count = conversation.messages.len();
+        match Db::import_conversation(db, &bear_llm_ai_handle.encryption, model_id, project_id, conversation).await {
+            Ok(created) => {
+                report.conversations.push(created);
+                report.messages += count;
+            }
+            Err(err) => report.skipped.push(conversations::SkippedImport {
+                conversation: Some(name),
+                reason: err.to_string(),
+                count: 1,
+            }),
+        }
+    }
+    audit::record(
+        db,
+        AuditAction::DataImported,
+        None,
+        json!({
+            "format": format,
+            "project_id": project_id,
+            "conversations": report.conversations.iter().map(|c| c.id).collect::<Vec<_>>(),
+            "messages": report.messages,
+            "skipped": report.skipped.iter().map(|s| s.count).sum::<usize>(),
+        }),
+    )
+    .await;
+    Ok(report)
+}
+
 // --- Chat
+/// The prompt to store with the reply: the last user turn of the history,
+/// unless the reply answers a message that is stored already
+fn last_user_message(messages: &[MessageDTO], persist: Option<&PersistOptions>) -> Option<MessageDTO> {
+    if persist.is_some_and(|p| p.reply_to.is_some()) {
+        return None;
+    }
+    messages.iter().rev().find(|m| m.role == "user").cloned()
+}
+
+/// The pattern detectors, plus name tagging by a local model when set up
+async fn pii_detectors(db: &sea_orm::DatabaseConnection, setting: &RedactionSetting) -> Result<PiiDetectors, String> {
+    let detectors = PiiDetectors::new(setting)?;
+    if !setting.ner.enabled {
+        return Ok(detectors);
+    }
+    let model_id = setting
+        .ner
+        .model_id
+        .ok_or("No model is set for name detection".to_string())?;
+    let model = Db::get_model(db, model_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let proxy_setting = Db::get_proxy_setting(db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let client = LLMClient::new(model.into(), proxy_setting)?;
+    // the text goes to this model unmasked
+    if !client.is_local() {
+        return Err("The model for name detection must run on this machine".to_string());
+    }
+    Ok(detectors.with(NerDetector::new(client, setting.ner.thresholds.clone())))
+}
+
+/// Masking applied to one chat request
+struct Redaction {
+    /// The placeholders also turn the reply back into the real values
+    masking: Masking,
+    /// Whether the placeholders can be stored in the conversation's vault
+    keep_vault: bool,
+    /// Persist the exchange as the model saw it
+    store_masked: bool,
+    setting: RedactionSetting,
+}
+
+/// The masking to apply when redaction is on. For a persisted conversation,
+/// placeholders come from its vault.
+async fn redaction(
+    db: &sea_orm::DatabaseConnection,
+    encryption: &Encryption,
+    conversation_id: Option<i32>,
+) -> Result<Option<Redaction>, String> {
+    let setting = settings::get_redaction_setting(db)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !setting.enabled {
+        return Ok(None);
+    }
+    let detectors = pii_detectors(db, &setting).await?;
+    let pseudonyms = match conversation_id {
+        Some(conversation_id) => {
+            let vault = Db::get_pii_vault(db, encryption, conversation_id)
+                .await
+                .map_err(|err| err.to_string())?;
+            Pseudonyms::with_known(vault.into_iter().map(Pseudonym::from))
+        }
+        None => Pseudonyms::default(),
+    };
+    // without a passphrase the placeholders only hold for this request, and
+    // the exchange is stored as written since nothing could unmask it later
+    let keep_vault = match conversation_id {
+        Some(conversation_id) => Db::can_keep_pii_vault(db, encryption, conversation_id)
+            .await
+            .map_err(|err| err.to_string())?,
+        None => false,
+    };
+    if conversation_id.is_some() && !keep_vault && setting.storage == RedactedStorage::Masked {
+        log::warn!("Storing a redacted exchange as written: masked storage needs a passphrase");
+    }
+    Ok(Some(Redaction {
+        masking: Masking { detectors, pseudonyms },
+        keep_vault,
+        store_masked: keep_vault && setting.storage == RedactedStorage::Masked,
+        setting,
+    }))
+}
+
+/// Mask personal data in the history when redaction is on
+async fn redact_history(
+    db: &sea_orm::DatabaseConnection,
+    encryption: &Encryption,
+    messages: Vec<MessageDTO>,
+    persist: Option<&PersistOptions>,
+) -> Result<(Vec<MessageDTO>, Option<Redaction>), String> {
+    let Some(mut redaction) = redaction(db, encryption, persist.map(|p| p.conversation_id)).await? else {
+        return Ok((messages, None));
+    };
+    let messages = redaction.masking.mask_messages(messages).await?;
+    Ok((messages, Some(redaction)))
+}
+
+/// Store the placeholders handed out for a request in the conversation's
+/// vault, once nothing more will be masked, and log what was masked
+async fn keep_pseudonyms(
+    db: &sea_orm::DatabaseConnection,
+    encryption: &Encryption,
+    conversation_id: Option<i32>,
+    redaction: Option<&Redaction>,
+) -> Result<(), String> {
+    let Some(redaction) = redaction else {
+        return Ok(());
+    };
+    let pseudonyms = &redaction.masking.pseudonyms;
+    if let (Some(conversation_id), true) = (conversation_id, redaction.keep_vault) {
+        Db::add_to_pii_vault(db, encryption, conversation_id, pseudonyms.fresh())
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    if !pseudonyms.is_empty() {
+        let target = conversation_id.and_then(|id| audit::target("conversation", id));
+        audit::record(db, AuditAction::Redacted, target, json!({ "masked": pseudonyms.counts() })).await;
+    }
+    Ok(())
+}
+
+/// What produces the reply, to be stored with it
+fn provenance(
+    handle: &AppHandle,
+    model: &Model,
+    client: &LLMClient,
+    options: &GenericOptions,
+    redaction: Option<&Redaction>,
+) -> Provenance {
+    Provenance {
+        model_id: Some(model.id),
+        provider: Some(model.provider.clone()),
+        model_name: client.model_name().map(str::to_string),
+        generation_options: Some(options.options.clone()),
+        redaction: redaction.and_then(|redaction| serde_json::to_string(&redaction.setting).ok()),
+        app_version: Some(handle.package_info().version.to_string()),
+    }
+}
+
+/// The prompt to store: the masked one when the setting asks for it
+fn stored_user_message(
+    clear: Option<MessageDTO>,
+    masked: &[MessageDTO],
+    persist: Option<&PersistOptions>,
+    redaction: Option<&Redaction>,
+) -> Option<MessageDTO> {
+    match redaction {
+        Some(redaction) if redaction.store_masked => last_user_message(masked, persist),
+        _ => clear,
+    }
+}
+
+/// Show what redaction would mask in `text` with the current settings,
+/// without sending anything
+#[tauri::command]
+pub async fn detect_pii(text: String, handle: AppHandle) -> Result<PiiPreview, String> {
+    let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+    let setting = settings::get_redaction_setting(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let detectors = pii_detectors(&bear_llm_ai_handle.db, &setting).await?;
+    pii::preview(&detectors, &text).await
+}
+
 #[tauri::command]
 pub async fn chat_completions(
     model_id: i32,
     messages: Vec<MessageDTO>,
     options: GenericOptions,
+    persist: Option<PersistOptions>,
     handle: AppHandle,
 ) -> Result<BotReply, String> {
     let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
@@ -202,13 +1274,75 @@ pub async fn chat_completions(
     let proxy_setting = Db::get_proxy_setting(&bear_llm_ai_handle.db)
         .await
         .map_err(|err| err.to_string())?;
-    let client = LLMClient::new(model.into(), proxy_setting)?;
+    let client = LLMClient::new(model.clone().into(), proxy_setting)?;
+    let max_tokens = settings::get_max_tokens(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let context_strategy = settings::get_context_strategy(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let user_message = last_user_message(&messages, persist.as_ref());
+    // masked before fitting; a summary of older turns is masked with the
+    // same placeholders
+    let (messages, mut redaction) = redact_history(
+        &bear_llm_ai_handle.db,
+        &bear_llm_ai_handle.encryption,
+        messages,
+        persist.as_ref(),
+    )
+    .await?;
+    let user_message = stored_user_message(user_message, &messages, persist.as_ref(), redaction.as_ref());
+    let provenance = provenance(&handle, &model, &client, &options, redaction.as_ref());
+    let (messages, context) = match &persist {
+        Some(persist) => {
+            summaries::fit_conversation_context(
+                &bear_llm_ai_handle.db,
+                &bear_llm_ai_handle.encryption,
+                &client,
+                persist.conversation_id,
+                messages,
+                context_strategy,
+                max_tokens,
+                redaction.as_mut().map(|redaction| &mut redaction.masking),
+            )
+            .await?
+        }
+        None => client.fit_context(messages, context_strategy, max_tokens).await?,
+    };
+    keep_pseudonyms(
+        &bear_llm_ai_handle.db,
+        &bear_llm_ai_handle.encryption,
+        persist.as_ref().map(|p| p.conversation_id),
+        redaction.as_ref(),
+    )
+    .await?;
     let global_settings = GlobalSettings {
-        max_tokens: settings::get_max_tokens(&bear_llm_ai_handle.db)
-            .await
-            .map_err(|err| err.to_string())?,
+        max_tokens,
+        context_length: Some(context.context_length),
     };
-    let reply = client.chat(messages, options, global_settings).await?;
+    let mut reply = client.chat(messages, options, global_settings).await?;
+    let masked_reply = redaction
+        .as_ref()
+        .filter(|redaction| redaction.store_masked)
+        .map(|_| reply.clone());
+    if let Some(redaction) = &redaction {
+        redaction.masking.pseudonyms.unmask_reply(&mut reply);
+    }
+    reply.context = Some(context);
+    if let Some(persist) = persist {
+        let persisted = Db::create_exchange(
+            &bear_llm_ai_handle.db,
+            &bear_llm_ai_handle.encryption,
+            persist.conversation_id,
+            persist.reply_to,
+            user_message,
+            masked_reply.as_ref().unwrap_or(&reply),
+            &provenance,
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+        record_messages_created(&bear_llm_ai_handle.db, &persisted).await;
+    }
     Ok(reply)
 }
 
@@ -217,8 +1351,10 @@ pub async fn chat_completions_stream(
     model_id: i32,
     messages: Vec<MessageDTO>,
     options: GenericOptions,
+    request_id: Option<String>,
+    persist: Option<PersistOptions>,
     handle: AppHandle,
-) -> Result<(), String> {
+) -> Result<String, String> {
     let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
     let model = Db::get_model(&bear_llm_ai_handle.db, model_id)
         .await
@@ -226,34 +1362,177 @@ pub async fn chat_completions_stream(
     let proxy_setting = Db::get_proxy_setting(&bear_llm_ai_handle.db)
         .await
         .map_err(|err| err.to_string())?;
-    let client = LLMClient::new(model.into(), proxy_setting)?;
-    let global_settings = GlobalSettings {
-        max_tokens: settings::get_max_tokens(&bear_llm_ai_handle.db)
+    let client = LLMClient::new(model.clone().into(), proxy_setting)?;
+    let max_tokens = settings::get_max_tokens(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let context_strategy = settings::get_context_strategy(&bear_llm_ai_handle.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let user_message = last_user_message(&messages, persist.as_ref());
+    let (messages, mut redaction) = redact_history(
+        &bear_llm_ai_handle.db,
+        &bear_llm_ai_handle.encryption,
+        messages,
+        persist.as_ref(),
+    )
+    .await?;
+    let user_message = stored_user_message(user_message, &messages, persist.as_ref(), redaction.as_ref());
+    let provenance = provenance(&handle, &model, &client, &options, redaction.as_ref());
+    // register before connecting so a cancel issued while waiting for the
+    // first byte is not lost
+    let (request_id, mut cancel_rx) = bear_llm_ai_handle.streams.register(request_id)?;
+    let fitted = match &persist {
+        Some(persist) => {
+            summaries::fit_conversation_context(
+                &bear_llm_ai_handle.db,
+                &bear_llm_ai_handle.encryption,
+                &client,
+                persist.conversation_id,
+                messages,
+                context_strategy,
+                max_tokens,
+                redaction.as_mut().map(|redaction| &mut redaction.masking),
+            )
             .await
-            .map_err(|err| err.to_string())?,
+        }
+        None => client.fit_context(messages, context_strategy, max_tokens).await,
+    };
+    let fitted = match fitted {
+        Ok(fitted) => keep_pseudonyms(
+            &bear_llm_ai_handle.db,
+            &bear_llm_ai_handle.encryption,
+            persist.as_ref().map(|p| p.conversation_id),
+            redaction.as_ref(),
+        )
+        .await
+        .map(|_| fitted),
+        Err(err) => Err(err),
+    };
+    let (messages, context) = match fitted {
+        Ok(fitted) => fitted,
+        Err(err) => {
+            bear_llm_ai_handle.streams.finish(&request_id);
+            return Err(err);
+        }
+    };
+    let global_settings = GlobalSettings {
+        max_tokens,
+        context_length: Some(context.context_length),
+    };
+    let mut stream = match client.chat_stream(messages, options, global_settings).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            bear_llm_ai_handle.streams.finish(&request_id);
+            return Err(err);
+        }
     };
-    let mut stream = client.chat_stream(messages, options, global_settings).await?;
 
-    // Spawn a task to emit stream chunks as events
+    // Spawn a task to emit stream chunks as events, tagged with the request id
+    let task_request_id = request_id.clone();
     tauri::async_runtime::spawn(async move {
         use tokio_stream::StreamExt;
 
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(reply) => {
-                    // Emit each chunk to the frontend
-                    let _ = handle.emit("chat_stream_chunk", reply);
+        let request_id = task_request_id;
+        let mut assembled = BotReply::default();
+        // the reply as the model wrote it, kept only to be stored masked
+        let mut masked = redaction
+            .as_ref()
+            .filter(|redaction| redaction.store_masked)
+            .map(|_| BotReply::default());
+        let mut unmasker = redaction.map(|redaction| ReplyUnmasker::new(redaction.masking.pseudonyms));
+        let mut status = loop {
+            tokio::select! {
+                biased;
+                _ = &mut cancel_rx => break ChatStreamStatus::Cancelled,
+                next = stream.next() => match next {
+                    Some(Ok(mut reply)) => {
+                        if let Some(masked) = &mut masked {
+                            masked.merge(&reply);
+                        }
+                        if let Some(unmasker) = &mut unmasker {
+                            unmasker.push(&mut reply);
+                        }
+                        assembled.merge(&reply);
+                        // Emit each chunk to the frontend
+                        let _ = handle.emit(CHAT_STREAM_CHUNK_EVENT, ChatStreamChunk {
+                            request_id: request_id.clone(),
+                            reply,
+                        });
+                    }
+                    Some(Err(error)) => {
+                        // Emit error event
+                        let _ = handle.emit(CHAT_STREAM_ERROR_EVENT, ChatStreamError {
+                            request_id: request_id.clone(),
+                            error,
+                        });
+                        break ChatStreamStatus::Failed;
+                    }
+                    None => break ChatStreamStatus::Completed,
+                },
+            }
+        };
+        // dropping the stream closes the connection, which stops generation server-side
+        drop(stream);
+        if let Some(reply) = unmasker.as_mut().and_then(ReplyUnmasker::finish) {
+            assembled.merge(&reply);
+            let _ = handle.emit(CHAT_STREAM_CHUNK_EVENT, ChatStreamChunk {
+                request_id: request_id.clone(),
+                reply,
+            });
+        }
+        let bear_llm_ai_handle = handle.state::<BearLlmAiHandle>();
+
+        // Persist on the backend so a webview crash mid-stream does not lose
+        // the answer; a cancelled reply is kept if anything arrived
+        let mut persisted = Vec::new();
+        let keep = status == ChatStreamStatus::Completed
+            || (status == ChatStreamStatus::Cancelled && !assembled.message.is_empty());
+        if let (Some(persist), true) = (persist, keep) {
+            match Db::create_exchange(
+                &bear_llm_ai_handle.db,
+                &bear_llm_ai_handle.encryption,
+                persist.conversation_id,
+                persist.reply_to,
+                user_message,
+                masked.as_ref().unwrap_or(&assembled),
+                &provenance,
+            )
+            .await
+            {
+                Ok(messages) => {
+                    record_messages_created(&bear_llm_ai_handle.db, &messages).await;
+                    persisted = messages;
                 }
                 Err(err) => {
-                    // Emit error event
-                    let _ = handle.emit("chat_stream_error", err);
-                    break;
+                    log::error!("Failed to persist chat stream {}: {}", request_id, err);
+                    let _ = handle.emit(CHAT_STREAM_ERROR_EVENT, ChatStreamError {
+                        request_id: request_id.clone(),
+                        error: err.to_string(),
+                    });
+                    status = ChatStreamStatus::Failed;
                 }
             }
         }
+        // only now, so `lock_app` keeps the key until the reply is stored
+        bear_llm_ai_handle.streams.finish(&request_id);
         // Emit stream completion event
-        let _ = handle.emit("chat_stream_end", ());
+        let _ = handle.emit(CHAT_STREAM_END_EVENT, ChatStreamEnd {
+            request_id,
+            status,
+            messages: persisted,
+            context: Some(context),
+        });
     });
 
-    Ok(())
+    Ok(request_id)
+}
+
+#[tauri::command]
+pub async fn cancel_chat_stream(request_id: String, handle: AppHandle) -> Result<(), String> {
+    if handle.state::<BearLlmAiHandle>().streams.cancel(&request_id) {
+        Ok(())
+    } else {
+        Err(format!("No running chat stream with id {}", request_id))
+    }
 }
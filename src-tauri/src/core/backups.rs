@@ -0,0 +1,135 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! State of the scheduled backups, and recovery from a database found
+//! damaged at start-up: the damaged files are set aside, the app starts on a
+//! fresh database and the webview offers to restore the latest good
+//! snapshot.
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::NaiveDateTime;
+use sea_orm::Database;
+use serde::Serialize;
+
+use crate::services::{
+    backup::{self, BackupSnapshot},
+    db::Db,
+};
+use entity::entities::settings::{self, BackupSetting};
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recovery {
+    /// Where the damaged database was moved to
+    pub damaged_path: PathBuf,
+    pub problems: String,
+    /// Folder the snapshots to restore from are in, as set before the damage
+    pub folder: PathBuf,
+    /// Newest snapshot in `folder`, if any
+    pub latest: Option<BackupSnapshot>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupStatus {
+    pub enabled: bool,
+    pub folder: PathBuf,
+    pub interval_hours: u32,
+    pub last_success: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<NaiveDateTime>,
+    /// Snapshots in the folder, newest first
+    pub snapshots: Vec<BackupSnapshot>,
+    pub recovery: Option<Recovery>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    last_error: Option<(NaiveDateTime, String)>,
+    recovery: Option<Recovery>,
+}
+
+/// What the scheduler remembers between runs; the rest of the status is
+/// read from the setting and the backup folder
+#[derive(Debug, Default)]
+pub struct Backups(Mutex<State>);
+
+impl Backups {
+    pub fn with_recovery(recovery: Option<Recovery>) -> Self {
+        Self(Mutex::new(State {
+            recovery,
+            ..Default::default()
+        }))
+    }
+
+    pub fn succeeded(&self) {
+        self.0.lock().unwrap().last_error = None;
+    }
+
+    pub fn failed(&self, error: String) {
+        self.0.lock().unwrap().last_error = Some((chrono::Utc::now().naive_utc(), error));
+    }
+
+    pub fn recovery(&self) -> Option<Recovery> {
+        self.0.lock().unwrap().recovery.clone()
+    }
+
+    pub fn status(&self, app_data_dir: &Path, setting: &BackupSetting) -> BackupStatus {
+        let folder = backup::folder(app_data_dir, setting);
+        let state = self.0.lock().unwrap();
+        let (snapshots, listing_error) = match backup::list_snapshots(&folder) {
+            Ok(snapshots) => (snapshots, None),
+            Err(err) => (Vec::new(), Some(format!("Cannot read the backup folder: {}", err))),
+        };
+        BackupStatus {
+            enabled: setting.enabled,
+            interval_hours: setting.interval_hours,
+            last_success: snapshots.first().map(|snapshot| snapshot.created_at),
+            last_error: state
+                .last_error
+                .as_ref()
+                .map(|(_, error)| error.clone())
+                .or(listing_error),
+            last_error_at: state.last_error.as_ref().map(|(at, _)| *at),
+            snapshots,
+            folder,
+            recovery: state.recovery.clone(),
+        }
+    }
+}
+
+/// The backup setting of a database that failed its integrity check, if it
+/// can still be read
+async fn damaged_backup_setting(db_path: &Path) -> Option<BackupSetting> {
+    let db = Database::connect(format!("sqlite:{}?mode=ro", db_path.to_str()?)).await.ok()?;
+    let setting = settings::get_backup_setting(&db).await.ok();
+    let _ = db.close().await;
+    setting
+}
+
+/// Set the damaged database aside and open a fresh one in its place
+pub async fn recover_damaged(app_data_dir: &Path, problems: String) -> Result<(Db, Recovery), String> {
+    let files = Db::files(app_data_dir);
+    let setting = damaged_backup_setting(&files[0]).await.unwrap_or_default();
+    let folder = backup::folder(app_data_dir, &setting);
+    let damaged_path = backup::set_aside_damaged(&files, &chrono::Utc::now().naive_utc())
+        .map_err(|err| format!("Cannot move the damaged database aside: {}", err))?;
+    log::warn!("Moved the damaged database to {:?}", damaged_path);
+    let latest = backup::list_snapshots(&folder)
+        .unwrap_or_default()
+        .into_iter()
+        .next();
+    let db = Db::new(app_data_dir)
+        .await
+        .map_err(|e| format!("Failed to create a fresh database: {:?}", e))?;
+    Ok((
+        db,
+        Recovery {
+            damaged_path,
+            problems,
+            folder,
+            latest,
+        },
+    ))
+}
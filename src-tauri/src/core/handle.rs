@@ -1,6 +1,13 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
 use sea_orm::DatabaseConnection;
 
+use super::{backups::Backups, session::Session, streams::ChatStreams};
+use crate::services::encryption::Encryption;
+
 pub struct BearLlmAiHandle {
     pub db: DatabaseConnection,
-}
\ No newline at end of file
+    pub streams: ChatStreams,
+    pub encryption: Encryption,
+    pub session: Session,
+    pub backups: Backups,
+}
@@ -1,2 +1,6 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
-pub mod handle;
\ No newline at end of file
+pub mod backups;
+pub mod handle;
+pub mod session;
+pub mod streams;
+pub mod tasks;
\ No newline at end of file
@@ -0,0 +1,171 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Session lock for shared workstations.
+//!
+//! Every IPC command counts as activity. Once the app has been idle longer
+//! than the `autoLockMinutes` setting, or on `lock_app`, the data key is
+//! dropped and every command except the few needed to unlock is refused with
+//! `BearLlmAiError::Locked` until the passphrase is given again. Only an
+//! encrypted database can be locked, since unlocking needs its passphrase.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde_json::json;
+use tauri::{ipc::Invoke, AppHandle, Emitter, Manager, Runtime};
+
+use super::handle::BearLlmAiHandle;
+use crate::{
+    errors::BearLlmAiError,
+    services::{audit, encryption::Encryption, pii},
+};
+use entity::entities::{audit_log::AuditAction, settings};
+
+pub const APP_LOCKED_EVENT: &str = "app_locked";
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Commands that still run while the app is locked
+const ALLOWED_WHILE_LOCKED: &[&str] = &[
+    "get_encryption_status",
+    "unlock_database",
+    "lock_app",
+    // the lock screen needs the theme and language
+    "get_setting",
+    "cancel_chat_stream",
+    // a forgotten passphrase can only be recovered from by starting over,
+    // which needs `RESET_CONFIRMATION` typed on the lock screen
+    "reset_forgotten_passphrase",
+    "restart_app",
+];
+
+/// What the user types to confirm `reset_forgotten_passphrase`
+pub const RESET_CONFIRMATION: &str = "ERASE ALL DATA";
+
+#[derive(Debug)]
+pub struct Session {
+    last_activity: Mutex<Instant>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            last_activity: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl Session {
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.last_activity.lock().unwrap().elapsed()
+    }
+
+    /// Record `command` as activity and refuse it if the app is locked
+    pub fn check(&self, command: &str, encryption: &Encryption) -> Result<(), BearLlmAiError> {
+        let status = encryption.status();
+        if status.enabled && !status.unlocked && !ALLOWED_WHILE_LOCKED.contains(&command) {
+            return Err(BearLlmAiError::Locked);
+        }
+        self.touch();
+        Ok(())
+    }
+}
+
+/// Wrap the generated command handler so every command goes through
+/// `Session::check` first
+pub fn guard<R, H>(handler: H) -> impl Fn(Invoke<R>) -> bool + Send + Sync + 'static
+where
+    R: Runtime,
+    H: Fn(Invoke<R>) -> bool + Send + Sync + 'static,
+{
+    move |invoke: Invoke<R>| {
+        let webview = invoke.message.webview();
+        if let Some(handle) = webview.try_state::<BearLlmAiHandle>() {
+            if let Err(err) = handle.session.check(invoke.message.command(), &handle.encryption) {
+                invoke.resolver.reject(err);
+                return true;
+            }
+        }
+        handler(invoke)
+    }
+}
+
+/// Drop the data key and tell the webview to show the lock screen. Returns
+/// false if there was nothing to lock.
+pub fn lock<R: Runtime>(app: &AppHandle<R>) -> bool {
+    let handle = app.state::<BearLlmAiHandle>();
+    let status = handle.encryption.status();
+    if !(status.enabled && status.unlocked) {
+        return false;
+    }
+    handle.encryption.lock();
+    // names found in the history are as sensitive as the history
+    pii::ner::forget_tagged();
+    let _ = app.emit(APP_LOCKED_EVENT, ());
+    true
+}
+
+/// Lock the app once it has been idle longer than the auto-lock setting. A
+/// reply that is still streaming counts as activity, so it can be stored.
+pub fn spawn_idle_watcher<R: Runtime>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+            let handle = app.state::<BearLlmAiHandle>();
+            let minutes = match settings::get_auto_lock_minutes(&handle.db).await {
+                Ok(minutes) => minutes,
+                Err(err) => {
+                    log::warn!("Failed to read the auto-lock setting: {}", err);
+                    continue;
+                }
+            };
+            if minutes == 0 || !handle.streams.is_empty() {
+                continue;
+            }
+            if handle.session.idle_for() >= Duration::from_secs(minutes * 60) && lock(&app) {
+                log::info!("Locked after {} idle minutes", minutes);
+                audit::record(&handle.db, AuditAction::AppLocked, None, json!({ "reason": "idle" })).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked_session_refuses_data_commands() {
+        let session = Session::default();
+        let encryption = Encryption::new(true);
+        assert!(matches!(
+            session.check("get_conversations", &encryption),
+            Err(BearLlmAiError::Locked)
+        ));
+        assert!(session.check("unlock_database", &encryption).is_ok());
+        assert!(session.check("get_encryption_status", &encryption).is_ok());
+        assert!(matches!(
+            session.check("erase_all_data", &encryption),
+            Err(BearLlmAiError::Locked)
+        ));
+        assert!(session.check("reset_forgotten_passphrase", &encryption).is_ok());
+    }
+
+    #[test]
+    fn test_unencrypted_session_is_never_locked() {
+        let session = Session::default();
+        assert!(session.check("get_conversations", &Encryption::default()).is_ok());
+    }
+
+    #[test]
+    fn test_commands_reset_the_idle_time() {
+        let session = Session::default();
+        *session.last_activity.lock().unwrap() -= Duration::from_secs(60);
+        assert!(session.idle_for() >= Duration::from_secs(60));
+        session.check("get_prompts", &Encryption::default()).unwrap();
+        assert!(session.idle_for() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,126 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::sync::{oneshot, Notify};
+
+use crate::services::llm::{chat::BotReply, context::ContextReport};
+use entity::entities::messages::Message;
+
+pub const CHAT_STREAM_CHUNK_EVENT: &str = "chat_stream_chunk";
+pub const CHAT_STREAM_ERROR_EVENT: &str = "chat_stream_error";
+pub const CHAT_STREAM_END_EVENT: &str = "chat_stream_end";
+/// How long `lock_app` waits for cancelled replies to be stored
+pub const STREAMS_STORED_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatStreamChunk {
+    pub request_id: String,
+    pub reply: BotReply,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatStreamError {
+    pub request_id: String,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChatStreamStatus {
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatStreamEnd {
+    pub request_id: String,
+    pub status: ChatStreamStatus,
+    /// Rows written when the stream was started with `persist`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<Message>,
+    /// How the history was fitted into the context window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextReport>,
+}
+
+/// In-flight chat streams, keyed by request id, so they can be cancelled. A
+/// stream stays registered until its reply has been stored, cancelled or not.
+#[derive(Debug, Default)]
+pub struct ChatStreams {
+    next_id: AtomicU64,
+    /// The sender is taken once the stream has been told to stop
+    streams: Mutex<HashMap<String, Option<oneshot::Sender<()>>>>,
+    finished: Notify,
+}
+
+impl ChatStreams {
+    /// Register a new stream, generating an id if the caller did not supply one.
+    /// The returned receiver resolves once the stream is cancelled.
+    pub fn register(
+        &self,
+        request_id: Option<String>,
+    ) -> Result<(String, oneshot::Receiver<()>), String> {
+        let request_id = request_id.unwrap_or_else(|| {
+            format!("chat-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        });
+        let mut streams = self.streams.lock().unwrap();
+        if streams.contains_key(&request_id) {
+            return Err(format!("Chat stream {} is already running", request_id));
+        }
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        streams.insert(request_id.clone(), Some(cancel_tx));
+        Ok((request_id, cancel_rx))
+    }
+
+    /// Signal cancellation. Returns false if no such stream is running or it
+    /// has already been cancelled.
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.streams.lock().unwrap().get_mut(request_id).and_then(Option::take) {
+            Some(cancel_tx) => cancel_tx.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Signal cancellation to every running stream
+    pub fn cancel_all(&self) {
+        for cancel_tx in self.streams.lock().unwrap().values_mut().filter_map(Option::take) {
+            let _ = cancel_tx.send(());
+        }
+    }
+
+    pub fn finish(&self, request_id: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        streams.remove(request_id);
+        if streams.is_empty() {
+            self.finished.notify_waiters();
+        }
+    }
+
+    /// Resolves once every stream has finished, including storing its reply
+    pub async fn wait_finished(&self) {
+        loop {
+            // registered before the check so a `finish` in between is not missed
+            let finished = self.finished.notified();
+            if self.is_empty() {
+                return;
+            }
+            finished.await;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.lock().unwrap().is_empty()
+    }
+}
@@ -0,0 +1,120 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Background work started once from `init::init`.
+use std::time::Duration;
+
+use serde_json::json;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use super::handle::BearLlmAiHandle;
+use crate::{
+    errors::BearLlmAiError,
+    services::{
+        audit,
+        backup::{self, BackupSnapshot},
+        db::Db,
+    },
+};
+use entity::entities::{audit_log::AuditAction, retention_purges::RetentionPurge, settings};
+
+pub const CONVERSATIONS_PURGED_EVENT: &str = "conversations_purged";
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Apply the retention policy now and tell the webview what was purged
+pub async fn purge_expired<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<RetentionPurge>, BearLlmAiError> {
+    let handle = app.state::<BearLlmAiHandle>();
+    let global_days = settings::get_retention_days(&handle.db).await?;
+    let purges = Db::purge_expired_conversations(&handle.db, global_days, chrono::Utc::now().naive_utc()).await?;
+    for purge in &purges {
+        audit::record(
+            &handle.db,
+            AuditAction::ConversationPurged,
+            audit::target("conversation", purge.conversation_id),
+            json!({
+                "message_count": purge.message_count,
+                "retention_days": purge.retention_days,
+                "policy": purge.policy,
+            }),
+        )
+        .await;
+    }
+    if !purges.is_empty() {
+        Db::vacuum(&handle.db).await;
+        log::info!("Purged {} conversations past their retention period", purges.len());
+        let _ = app.emit(CONVERSATIONS_PURGED_EVENT, &purges);
+    }
+    Ok(purges)
+}
+
+/// Apply the retention policy at start-up and then every hour
+pub fn spawn_retention_task<R: Runtime>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(err) = purge_expired(&app).await {
+                log::warn!("Failed to apply the retention policy: {}", err);
+            }
+            tokio::time::sleep(RETENTION_INTERVAL).await;
+        }
+    });
+}
+
+/// Take a scheduled backup if one is due and rotate the older ones
+pub async fn backup_if_due<R: Runtime>(app: &AppHandle<R>) -> Result<Option<BackupSnapshot>, BearLlmAiError> {
+    let handle = app.state::<BearLlmAiHandle>();
+    // until the user has decided, the snapshots are what a restore needs
+    if handle.backups.recovery().is_some() {
+        return Ok(None);
+    }
+    let setting = settings::get_backup_setting(&handle.db).await?;
+    if !setting.enabled {
+        return Ok(None);
+    }
+    let app_data_dir = app.path().app_data_dir()?;
+    let folder = backup::folder(&app_data_dir, &setting);
+    let now = chrono::Utc::now().naive_utc();
+    let interval = chrono::Duration::hours(setting.interval_hours.max(1) as i64);
+    if let Some(latest) = backup::list_snapshots(&folder)?.first() {
+        if now - latest.created_at < interval {
+            return Ok(None);
+        }
+    }
+    let snapshot = backup::write_snapshot(
+        &handle.db,
+        &app_data_dir,
+        &folder,
+        &app.package_info().version.to_string(),
+    )
+    .await?;
+    let expired = backup::expired(&backup::list_snapshots(&folder)?, setting.keep_daily, setting.keep_weekly);
+    for path in &expired {
+        if let Err(err) = std::fs::remove_file(path) {
+            log::warn!("Failed to remove old backup {:?}: {}", path, err);
+        }
+    }
+    audit::record(
+        &handle.db,
+        AuditAction::BackupCreated,
+        None,
+        json!({ "scheduled": true, "removed": expired.len() }),
+    )
+    .await;
+    log::info!("Wrote scheduled backup {:?}", snapshot.path);
+    Ok(Some(snapshot))
+}
+
+/// Check for a due backup at start-up and then every 15 minutes
+pub fn spawn_backup_task<R: Runtime>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            match backup_if_due(&app).await {
+                Ok(Some(_)) => app.state::<BearLlmAiHandle>().backups.succeeded(),
+                Ok(None) => {}
+                Err(err) => {
+                    log::warn!("Scheduled backup failed: {}", err);
+                    app.state::<BearLlmAiHandle>().backups.failed(err.to_string());
+                }
+            }
+            tokio::time::sleep(BACKUP_CHECK_INTERVAL).await;
+        }
+    });
+}
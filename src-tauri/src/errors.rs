@@ -1,14 +1,29 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
 use sea_orm::DbErr;
-use serde::{ser::Serializer, Serialize};
+use serde::{
+    ser::{SerializeStruct, Serializer},
+    Serialize,
+};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum BearLlmAiError {
     #[error("Database error: {0}")]
     DbErr(#[from] DbErr),
+    #[error("The app is locked, unlock it with the passphrase")]
+    Locked,
+    #[error("Encryption error: {0}")]
+    Encryption(String),
     #[error("Tauri error: {0}")]
     TauriErr(#[from] tauri::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The database is damaged: {0}")]
+    Corrupted(String),
+    #[error("The project is locked, unlock it with its passphrase")]
+    ProjectLocked(i32),
+    #[error("{0}")]
+    Invalid(String),
 }
 
 // we must manually implement serde::Serialize
@@ -17,6 +32,23 @@ impl Serialize for BearLlmAiError {
     where
         S: Serializer,
     {
-        serializer.serialize_str(self.to_string().as_ref())
+        match self {
+            // an object rather than a message, so the webview can tell it
+            // apart and show the lock screen
+            BearLlmAiError::Locked => {
+                let mut state = serializer.serialize_struct("BearLlmAiError", 2)?;
+                state.serialize_field("kind", "locked")?;
+                state.serialize_field("message", &self.to_string())?;
+                state.end()
+            }
+            BearLlmAiError::ProjectLocked(project_id) => {
+                let mut state = serializer.serialize_struct("BearLlmAiError", 3)?;
+                state.serialize_field("kind", "projectLocked")?;
+                state.serialize_field("projectId", project_id)?;
+                state.serialize_field("message", &self.to_string())?;
+                state.end()
+            }
+            _ => serializer.serialize_str(self.to_string().as_ref()),
+        }
     }
 }
\ No newline at end of file
@@ -1,8 +1,9 @@
 // This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
 // BEAR LLM AI changes - Added Db import
 // MIT License Copyright (c) 2024-present Frank Zhang
-use crate::core::handle::BearLlmAiHandle;
-use crate::services::db::Db;
+use crate::core::{backups::{self, Backups}, handle::BearLlmAiHandle, session::{self, Session}, streams::ChatStreams, tasks};
+use crate::services::{backup, db::Db, encryption::Encryption};
+use crate::errors::BearLlmAiError;
 use crate::crash_handler;
 use tauri::{
     App,
@@ -14,7 +15,7 @@ pub fn init(app: &mut App<Wry>) -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Starting Tauri application initialization...");
     let handle = app.handle();
 
-    let db = tauri::async_runtime::block_on(async {
+    let (db, encrypted, encrypted_projects, recovery) = tauri::async_runtime::block_on(async {
         log::info!("Starting async initialization block...");
 
         // Get app data directory with better error handling
@@ -77,21 +78,63 @@ pub fn init(app: &mut App<Wry>) -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        // A backup or restore cut short leaves a full copy of the database
+        match backup::remove_leftovers(&app_data_dir) {
+            Ok(erased) if !erased.is_empty() => log::warn!("Erased leftover database copies: {:?}", erased),
+            Ok(_) => {}
+            Err(err) => log::warn!("Failed to erase leftover database copies: {}", err),
+        }
+
         // Initialize database
         log::info!("Initializing database...");
-        let db_wrapper = Db::new(&app_data_dir)
-            .await
-            .map_err(|e| {
-                log::error!("Database initialization failed: {:?}", e);
-                format!("Failed to initialize database: {:?}", e)
-            })?;
+        let (db_wrapper, recovery) = match Db::new(&app_data_dir).await {
+            Err(e) if matches!(e.downcast_ref::<BearLlmAiError>(), Some(BearLlmAiError::Corrupted(_))) => {
+                log::error!("Database is damaged, setting it aside: {}", e);
+                let (db_wrapper, recovery) = backups::recover_damaged(&app_data_dir, e.to_string()).await?;
+                (db_wrapper, Some(recovery))
+            }
+            res => {
+                let db_wrapper = res.map_err(|e| {
+                    log::error!("Database initialization failed: {:?}", e);
+                    format!("Failed to initialize database: {:?}", e)
+                })?;
+                (db_wrapper, None)
+            }
+        };
 
         log::info!("Database initialization complete");
-        Ok::<_, String>(db_wrapper.0)
+        let encrypted = Db::get_encryption_setting(&db_wrapper.0)
+            .await
+            .map_err(|e| format!("Failed to read encryption setting: {:?}", e))?
+            .is_some();
+        if encrypted {
+            log::info!("Database is encrypted, waiting for the passphrase");
+        }
+        let encrypted_projects: Vec<i32> = Db::get_projects(&db_wrapper.0)
+            .await
+            .map_err(|e| format!("Failed to read projects: {:?}", e))?
+            .into_iter()
+            .filter(|project| project.encryption.is_some())
+            .map(|project| project.id)
+            .collect();
+        Ok::<_, String>((db_wrapper.0, encrypted, encrypted_projects, recovery))
     })?;
 
+    let encryption = Encryption::new(encrypted);
+    for project_id in encrypted_projects {
+        encryption.register_project(project_id);
+    }
     log::info!("Managing application state...");
-    handle.manage(BearLlmAiHandle { db });
+    handle.manage(BearLlmAiHandle {
+        db,
+        streams: ChatStreams::default(),
+        encryption,
+        session: Session::default(),
+        backups: Backups::with_recovery(recovery),
+    });
+    session::spawn_idle_watcher(handle.clone());
+    tasks::spawn_retention_task(handle.clone());
+    tasks::spawn_backup_task(handle.clone());
     log::info!("Tauri application initialization complete");
 
     // Show the main window now that initialization is complete
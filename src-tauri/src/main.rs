@@ -121,6 +121,14 @@ fn main() {
 
             let webview2_dir = log_dir.join("WebView2");
 
+            // Finish an erase_all_data that could not remove the folder
+            // while the webview was using it
+            match bear_llm_ai_lib::services::erasure::remove_pending(&webview2_dir) {
+                Ok(true) => log_msg("✓ Removed WebView2 folder left behind by data erasure"),
+                Ok(false) => {}
+                Err(e) => log_msg(&format!("✗ Failed to remove WebView2 folder left behind by data erasure: {:?}", e)),
+            }
+
             // Check existing WebView2 folder for corruption or permission issues
             if webview2_dir.exists() {
                 log_msg("Existing WebView2 folder detected, verifying integrity...");
@@ -217,29 +225,77 @@ fn main() {
                 }
             }
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(bear_llm_ai_lib::core::session::guard::<tauri::Wry, _>(tauri::generate_handler![
             bear_llm_ai_lib::commands::get_settings,
             bear_llm_ai_lib::commands::update_settings,
             bear_llm_ai_lib::commands::get_setting,
+            bear_llm_ai_lib::commands::get_encryption_status,
+            bear_llm_ai_lib::commands::enable_encryption,
+            bear_llm_ai_lib::commands::unlock_database,
+            bear_llm_ai_lib::commands::lock_app,
+            bear_llm_ai_lib::commands::erase_all_data,
+            bear_llm_ai_lib::commands::reset_forgotten_passphrase,
+            bear_llm_ai_lib::commands::restart_app,
+            bear_llm_ai_lib::commands::get_audit_log,
+            bear_llm_ai_lib::commands::verify_audit_log,
+            bear_llm_ai_lib::commands::export_audit_log,
             bear_llm_ai_lib::commands::get_models,
             bear_llm_ai_lib::commands::create_model,
             bear_llm_ai_lib::commands::update_model,
             bear_llm_ai_lib::commands::delete_model,
             bear_llm_ai_lib::commands::get_providers,
             bear_llm_ai_lib::commands::list_remote_models,
+            bear_llm_ai_lib::commands::get_projects,
+            bear_llm_ai_lib::commands::create_project,
+            bear_llm_ai_lib::commands::update_project,
+            bear_llm_ai_lib::commands::delete_project,
+            bear_llm_ai_lib::commands::unlock_project,
+            bear_llm_ai_lib::commands::lock_project,
             bear_llm_ai_lib::commands::get_conversations,
+            bear_llm_ai_lib::commands::get_conversations_page,
+            bear_llm_ai_lib::commands::get_project_conversations,
             bear_llm_ai_lib::commands::create_conversation,
             bear_llm_ai_lib::commands::update_conversation,
+            bear_llm_ai_lib::commands::move_conversation,
             bear_llm_ai_lib::commands::delete_conversation,
+            bear_llm_ai_lib::commands::update_conversation_retention,
+            bear_llm_ai_lib::commands::purge_expired_conversations,
+            bear_llm_ai_lib::commands::get_retention_purges,
+            bear_llm_ai_lib::commands::get_pii_vault,
+            bear_llm_ai_lib::commands::purge_pii_vault,
             bear_llm_ai_lib::commands::get_conversation_messages,
+            bear_llm_ai_lib::commands::get_conversation_messages_page,
+            bear_llm_ai_lib::commands::get_conversation_summaries,
+            bear_llm_ai_lib::commands::regenerate_conversation_summary,
             bear_llm_ai_lib::commands::create_messages,
+            bear_llm_ai_lib::commands::get_active_path,
+            bear_llm_ai_lib::commands::edit_message,
+            bear_llm_ai_lib::commands::get_message_branches,
+            bear_llm_ai_lib::commands::switch_branch,
+            bear_llm_ai_lib::commands::delete_message,
+            bear_llm_ai_lib::commands::search_messages,
+            bear_llm_ai_lib::commands::search_project_messages,
             bear_llm_ai_lib::commands::get_prompts,
+            bear_llm_ai_lib::commands::get_project_prompts,
             bear_llm_ai_lib::commands::create_prompt,
             bear_llm_ai_lib::commands::update_prompt,
             bear_llm_ai_lib::commands::delete_prompt,
+            bear_llm_ai_lib::commands::get_documents,
+            bear_llm_ai_lib::commands::create_document,
+            bear_llm_ai_lib::commands::delete_document,
             bear_llm_ai_lib::commands::chat_completions,
-            bear_llm_ai_lib::commands::chat_completions_stream
-        ])
+            bear_llm_ai_lib::commands::chat_completions_stream,
+            bear_llm_ai_lib::commands::cancel_chat_stream,
+            bear_llm_ai_lib::commands::detect_pii,
+            bear_llm_ai_lib::commands::export_conversation,
+            bear_llm_ai_lib::commands::import_conversations,
+            bear_llm_ai_lib::commands::create_backup,
+            bear_llm_ai_lib::commands::inspect_backup,
+            bear_llm_ai_lib::commands::restore_backup,
+            bear_llm_ai_lib::commands::restore_latest_backup,
+            bear_llm_ai_lib::commands::get_backup_status,
+            bear_llm_ai_lib::commands::run_scheduled_backup
+        ]))
         .build(context);
 
     #[cfg(target_os = "windows")]
@@ -0,0 +1,167 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Append-only audit log of data-handling actions (GDPR Art. 30).
+//!
+//! Each entry stores the hash of the one before it, so editing, inserting
+//! or removing a row anywhere breaks the chain from that point on.
+use chrono::NaiveDateTime;
+use sea_orm::DatabaseConnection;
+use sha2::{Digest, Sha256};
+
+use crate::services::db::Db;
+use entity::entities::audit_log::{self, AuditAction, AuditVerification};
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const CSV_HEADER: &str = "id,created_at,action,target,details,prev_hash,hash";
+
+pub fn target(kind: &str, id: i32) -> Option<String> {
+    Some(format!("{}:{}", kind, id))
+}
+
+/// Hex SHA-256 over the previous hash and the entry's fields, one per line
+pub fn hash_entry(
+    prev_hash: &str,
+    created_at: &NaiveDateTime,
+    action: &str,
+    target: Option<&str>,
+    details: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for field in [
+        prev_hash,
+        &created_at.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+        action,
+        target.unwrap_or(""),
+        details,
+    ] {
+        hasher.update(field.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Check the chain over `entries`, oldest first
+pub fn verify(entries: &[audit_log::Model]) -> AuditVerification {
+    let mut prev_hash = GENESIS_HASH;
+    let first_invalid_id = entries
+        .iter()
+        .find(|entry| {
+            let expected = hash_entry(
+                prev_hash,
+                &entry.created_at,
+                &entry.action,
+                entry.target.as_deref(),
+                &entry.details,
+            );
+            let broken = entry.prev_hash != prev_hash || entry.hash != expected;
+            prev_hash = &entry.hash;
+            broken
+        })
+        .map(|entry| entry.id);
+    AuditVerification {
+        entries: entries.len(),
+        first_invalid_id,
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn to_csv(entries: &[audit_log::Model]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push('\n');
+    for entry in entries {
+        let row = [
+            entry.id.to_string(),
+            entry.created_at.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+            entry.action.clone(),
+            entry.target.clone().unwrap_or_default(),
+            entry.details.clone(),
+            entry.prev_hash.clone(),
+            entry.hash.clone(),
+        ];
+        let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Append an entry. A failure is logged rather than returned, since the
+/// action it describes has already happened.
+pub async fn record(
+    db: &DatabaseConnection,
+    action: AuditAction,
+    target: Option<String>,
+    details: serde_json::Value,
+) {
+    if let Err(err) = Db::append_audit_log(db, action, target, details).await {
+        log::error!("Failed to write audit log entry {}: {}", action, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(n: usize) -> Vec<audit_log::Model> {
+        let mut prev_hash = GENESIS_HASH.to_string();
+        (1..=n as i32)
+            .map(|id| {
+                let created_at = chrono::NaiveDate::from_ymd_opt(2026, 10, 17)
+                    .unwrap()
+                    .and_hms_opt(9, 0, id as u32)
+                    .unwrap();
+                let details = format!("{{\"n\":{}}}", id);
+                let hash = hash_entry(&prev_hash, &created_at, "message_deleted", Some("message:1"), &details);
+                let entry = audit_log::Model {
+                    id,
+                    created_at,
+                    action: "message_deleted".to_string(),
+                    target: Some("message:1".to_string()),
+                    details,
+                    prev_hash: prev_hash.clone(),
+                    hash: hash.clone(),
+                };
+                prev_hash = hash;
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_intact_chain_verifies() {
+        let verification = verify(&chain(3));
+        assert_eq!(verification.entries, 3);
+        assert_eq!(verification.first_invalid_id, None);
+        assert_eq!(verify(&[]).first_invalid_id, None);
+    }
+
+    #[test]
+    fn test_edited_or_removed_entries_break_the_chain() {
+        let mut edited = chain(3);
+        edited[1].details = "{\"n\":9}".to_string();
+        assert_eq!(verify(&edited).first_invalid_id, Some(2));
+
+        let mut removed = chain(3);
+        removed.remove(1);
+        assert_eq!(verify(&removed).first_invalid_id, Some(3));
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let csv = to_csv(&chain(1));
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert!(lines.next().unwrap().starts_with("1,2026-10-17T09:00:01.000000,message_deleted,message:1,\"{\"\"n\"\":1}\","));
+    }
+}
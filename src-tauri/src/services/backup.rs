@@ -0,0 +1,627 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Full backups of the database.
+//!
+//! A backup is a zip archive holding a consistent snapshot made with
+//! `VACUUM INTO`, so chats can go on while it is taken, and a manifest with
+//! the app and schema version and a SHA-256 of every other entry. With a
+//! passphrase the snapshot is sealed as a whole with a key derived the same
+//! way as for encryption at rest; the manifest then carries the key
+//! parameters and stays readable.
+//!
+//! Restoring stages the snapshot next to the live database and migrates and
+//! checks it there, so the live database is only replaced by one that opens.
+//! Both the snapshot and the staged copy hold the whole history, unsealed
+//! unless the history is encrypted, so they are erased rather than deleted
+//! and any left by a crash are erased at the next start.
+//!
+//! Scheduled backups are the same archives, unsealed, written to a folder
+//! under a dated name and rotated so the newest one per day and per week is
+//! kept for a configured number of days and weeks.
+use std::{
+    collections::HashSet,
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, NaiveDateTime, SubsecRound};
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement};
+use sea_orm_migration::MigratorTrait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    errors::BearLlmAiError,
+    services::{db::DB_NAME, encryption, erasure, integrity},
+};
+use entity::entities::settings::{BackupSetting, EncryptionSetting};
+use migration::Migrator;
+
+pub const BACKUP_FORMAT: &str = "bear-llm-ai/backup";
+pub const BACKUP_VERSION: u32 = 1;
+const MANIFEST: &str = "manifest.json";
+/// Snapshot entry when it is sealed with a passphrase
+const SEALED_SUFFIX: &str = ".sealed";
+/// Manifests are small; anything bigger is not one of ours
+const MANIFEST_LIMIT: u64 = 1024 * 1024;
+const SNAPSHOT_PREFIX: &str = "bear-llm-ai-";
+const SNAPSHOT_EXTENSION: &str = "zip";
+const SNAPSHOT_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// Prefix of database files set aside because they were damaged
+const DAMAGED_PREFIX: &str = "bear-llm-ai-damaged-";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    pub app_version: String,
+    /// Last migration applied to the snapshot
+    pub schema_version: String,
+    /// Key parameters when the snapshot is sealed with a passphrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionSetting>,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupFile {
+    pub name: String,
+    pub size: u64,
+    /// Hex SHA-256 of the entry as stored, so after sealing when sealed
+    pub sha256: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub manifest: BackupManifest,
+    /// Migrations run to bring the snapshot up to this version
+    pub migrations_applied: usize,
+    /// The running app still holds the closed connection, so it has to
+    /// restart to open the restored database
+    pub restart_required: bool,
+}
+
+/// A scheduled backup in the backup folder
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSnapshot {
+    pub path: PathBuf,
+    pub created_at: NaiveDateTime,
+}
+
+fn backup_error(message: impl Into<String>) -> BearLlmAiError {
+    BearLlmAiError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, message.into()))
+}
+
+fn zip_error(err: zip::result::ZipError) -> BearLlmAiError {
+    backup_error(format!("Not a readable backup: {}", err))
+}
+
+/// Run file and archive work off the async runtime; a database can be
+/// hundreds of megabytes
+async fn blocking<T, F>(f: F) -> Result<T, BearLlmAiError>
+where
+    F: FnOnce() -> Result<T, BearLlmAiError> + Send + 'static,
+    T: Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f).await?
+}
+
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Where a restored snapshot waits until it replaces the database; next to
+/// it, so it has the same protection
+pub fn staging_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(format!("{}.staged", DB_NAME))
+}
+
+fn snapshot_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(format!("{}.snapshot", DB_NAME))
+}
+
+/// The staged copy and the journal SQLite may leave next to it
+fn staged_files(app_data_dir: &Path) -> [PathBuf; 2] {
+    let staged = staging_path(app_data_dir);
+    let journal = PathBuf::from(format!("{}-journal", staged.to_string_lossy()));
+    [staged, journal]
+}
+
+/// Copies of the database made while backing up or restoring
+pub fn scratch_files(app_data_dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![snapshot_path(app_data_dir)];
+    files.extend(staged_files(app_data_dir));
+    files
+}
+
+/// Erase the copies a backup or restore interrupted by a crash left behind.
+/// Returns the paths erased.
+pub fn remove_leftovers(app_data_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut erased = Vec::new();
+    for path in scratch_files(app_data_dir) {
+        if path.exists() {
+            erasure::erase_file(&path)?;
+            erased.push(path);
+        }
+    }
+    Ok(erased)
+}
+
+/// Write a consistent copy of the database to `path`
+pub async fn snapshot(db: &DatabaseConnection, path: &Path) -> Result<(), BearLlmAiError> {
+    // VACUUM INTO refuses to overwrite
+    if path.exists() {
+        let path = path.to_path_buf();
+        blocking(move || Ok(erasure::erase_file(&path)?)).await?;
+    }
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        "VACUUM INTO ?",
+        [path.to_string_lossy().to_string().into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Name of the last migration applied to `db`
+pub async fn schema_version(db: &DatabaseConnection) -> Result<String, BearLlmAiError> {
+    let row = db
+        .query_one(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT version FROM seaql_migrations ORDER BY version DESC LIMIT 1",
+        ))
+        .await
+        .map_err(|_| backup_error("Not a BEAR LLM AI database"))?
+        .ok_or_else(|| backup_error("Not a BEAR LLM AI database"))?;
+    Ok(row.try_get_by_index(0)?)
+}
+
+/// Refuse a schema this version does not know; migration names start with
+/// their date, so an unknown one comes from a newer version
+fn check_schema(schema_version: &str) -> Result<(), BearLlmAiError> {
+    if Migrator::migrations().iter().any(|migration| migration.name() == schema_version) {
+        Ok(())
+    } else {
+        Err(backup_error(format!(
+            "The backup was made by a newer version of the app (schema {}); update the app to restore it",
+            schema_version
+        )))
+    }
+}
+
+/// Build the archive for a snapshot, sealed when a passphrase is given
+pub fn pack(
+    snapshot: &[u8],
+    app_version: &str,
+    schema_version: &str,
+    created_at: NaiveDateTime,
+    passphrase: Option<&str>,
+) -> Result<(BackupManifest, Vec<u8>), BearLlmAiError> {
+    let (name, data, encryption) = match passphrase {
+        Some(passphrase) => {
+            let (key, setting) = encryption::new_key(passphrase)?;
+            let sealed = encryption::seal_bytes(&key, snapshot)?;
+            (format!("{}{}", DB_NAME, SEALED_SUFFIX), sealed, Some(setting))
+        }
+        None => (DB_NAME.to_string(), snapshot.to_vec(), None),
+    };
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at,
+        app_version: app_version.to_string(),
+        schema_version: schema_version.to_string(),
+        encryption,
+        files: vec![BackupFile {
+            name: name.clone(),
+            size: data.len() as u64,
+            sha256: sha256(&data),
+        }],
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|err| backup_error(err.to_string()))?;
+    let archive = (|| -> zip::result::ZipResult<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(MANIFEST, options)?;
+        zip.write_all(&manifest_json)?;
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(&data)?;
+        Ok(zip.finish()?.into_inner())
+    })()
+    .map_err(|err| BearLlmAiError::Io(err.into()))?;
+    Ok((manifest, archive))
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str, limit: u64) -> Result<Vec<u8>, BearLlmAiError> {
+    let entry = archive.by_name(name).map_err(zip_error)?;
+    let mut data = Vec::new();
+    // the size in the zip header is not to be trusted either
+    entry.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(backup_error(format!("{} is larger than the manifest says", name)));
+    }
+    Ok(data)
+}
+
+/// Read the manifest alone, e.g. to ask for the passphrase only when needed
+pub fn read_manifest(archive: &[u8]) -> Result<BackupManifest, BearLlmAiError> {
+    let mut zip = ZipArchive::new(Cursor::new(archive)).map_err(zip_error)?;
+    let manifest: BackupManifest = serde_json::from_slice(&read_entry(&mut zip, MANIFEST, MANIFEST_LIMIT)?)
+        .map_err(|err| backup_error(format!("Not a readable backup manifest: {}", err)))?;
+    if manifest.format != BACKUP_FORMAT {
+        return Err(backup_error("Not a BEAR LLM AI backup"));
+    }
+    if manifest.version > BACKUP_VERSION {
+        return Err(backup_error(format!(
+            "The backup was made by a newer version of the app (backup format {})",
+            manifest.version
+        )));
+    }
+    check_schema(&manifest.schema_version)?;
+    Ok(manifest)
+}
+
+/// Snapshot the database and pack it into an archive
+pub async fn create(
+    db: &DatabaseConnection,
+    app_data_dir: &Path,
+    app_version: &str,
+    passphrase: Option<&str>,
+) -> Result<(BackupManifest, Vec<u8>), BearLlmAiError> {
+    let path = snapshot_path(app_data_dir);
+    let snapshotted = snapshot(db, &path).await;
+    let schema_version = schema_version(db).await;
+    let app_version = app_version.to_string();
+    let passphrase = passphrase.map(|passphrase| Zeroizing::new(passphrase.to_string()));
+    blocking(move || {
+        let snapshot = match snapshotted {
+            Ok(()) => std::fs::read(&path).map(Zeroizing::new).map_err(BearLlmAiError::from),
+            Err(err) => Err(err),
+        };
+        // a failed VACUUM INTO may still have written part of the file
+        if path.exists() {
+            erasure::erase_file(&path)?;
+        }
+        pack(
+            &snapshot?,
+            &app_version,
+            &schema_version?,
+            chrono::Utc::now().naive_utc(),
+            passphrase.as_deref().map(String::as_str),
+        )
+    })
+    .await
+}
+
+/// Check the archive against its manifest and return the snapshot
+pub fn unpack(archive: &[u8], passphrase: Option<&str>) -> Result<(BackupManifest, Zeroizing<Vec<u8>>), BearLlmAiError> {
+    let manifest = read_manifest(archive)?;
+    let mut zip = ZipArchive::new(Cursor::new(archive)).map_err(zip_error)?;
+    let file = manifest
+        .files
+        .iter()
+        .find(|file| file.name.starts_with(DB_NAME))
+        .ok_or_else(|| backup_error("The backup holds no database"))?;
+    let data = read_entry(&mut zip, &file.name, file.size)?;
+    if data.len() as u64 != file.size || sha256(&data) != file.sha256 {
+        return Err(backup_error(format!("{} does not match its checksum", file.name)));
+    }
+    let snapshot = match &manifest.encryption {
+        Some(setting) => {
+            let passphrase = passphrase.ok_or_else(|| backup_error("The backup is encrypted, enter its passphrase"))?;
+            let key = encryption::unlock_key(passphrase, setting)?;
+            Zeroizing::new(encryption::open_bytes(&key, &data)?)
+        }
+        None => Zeroizing::new(data),
+    };
+    Ok((manifest, snapshot))
+}
+
+/// Write `snapshot` to the staging path, bring it up to this version's
+/// schema and check that it is sound. Returns the number of migrations run.
+pub async fn stage(app_data_dir: &Path, snapshot: Zeroizing<Vec<u8>>) -> Result<usize, BearLlmAiError> {
+    let path = staging_path(app_data_dir);
+    let result = async {
+        let staged = path.clone();
+        blocking(move || Ok(std::fs::write(&staged, snapshot.as_slice())?)).await?;
+        let url = format!("sqlite:{}?mode=rw", path.to_string_lossy());
+        let db = Database::connect(&url).await?;
+        let checked = async {
+            let errors = integrity::integrity_errors(&db).await?;
+            if !errors.is_empty() {
+                return Err(backup_error(format!("The backup is damaged: {}", errors.join("; "))));
+            }
+            check_schema(&schema_version(&db).await?)?;
+            let pending = Migrator::get_pending_migrations(&db).await?.len();
+            Migrator::up(&db, None).await?;
+            Ok(pending)
+        }
+        .await;
+        db.close().await?;
+        checked
+    }
+    .await;
+    if result.is_err() {
+        let files = staged_files(app_data_dir);
+        let erased = blocking(move || {
+            for path in files.iter().filter(|path| path.exists()) {
+                if let Err(err) = erasure::erase_file(path) {
+                    log::warn!("Failed to erase {:?}: {}", path, err);
+                }
+            }
+            Ok(())
+        })
+        .await;
+        if let Err(err) = erased {
+            log::warn!("Failed to erase the staged backup: {}", err);
+        }
+    }
+    result
+}
+
+/// Replace the database files with the staged snapshot. The connection to
+/// the live database must be closed.
+pub fn swap_in(app_data_dir: &Path, db_files: &[PathBuf]) -> std::io::Result<()> {
+    let staged = staging_path(app_data_dir);
+    let (db_path, sidecars) = db_files.split_first().ok_or(std::io::ErrorKind::NotFound)?;
+    // the old WAL would be replayed into the restored database
+    for sidecar in sidecars {
+        if sidecar.exists() {
+            std::fs::remove_file(sidecar)?;
+        }
+    }
+    std::fs::rename(staged, db_path)
+}
+
+pub fn default_folder(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("backups")
+}
+
+pub fn folder(app_data_dir: &Path, setting: &BackupSetting) -> PathBuf {
+    match setting.folder.as_deref().map(str::trim) {
+        Some(folder) if !folder.is_empty() => PathBuf::from(folder),
+        _ => default_folder(app_data_dir),
+    }
+}
+
+fn snapshot_name(created_at: &NaiveDateTime) -> String {
+    format!(
+        "{}{}.{}",
+        SNAPSHOT_PREFIX,
+        created_at.format(SNAPSHOT_TIME_FORMAT),
+        SNAPSHOT_EXTENSION
+    )
+}
+
+fn parse_snapshot_name(name: &str) -> Option<NaiveDateTime> {
+    let time = name
+        .strip_prefix(SNAPSHOT_PREFIX)?
+        .strip_suffix(SNAPSHOT_EXTENSION)?
+        .strip_suffix('.')?;
+    NaiveDateTime::parse_from_str(time, SNAPSHOT_TIME_FORMAT).ok()
+}
+
+/// Scheduled backups in `folder`, newest first
+pub fn list_snapshots(folder: &Path) -> io::Result<Vec<BackupSnapshot>> {
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in std::fs::read_dir(folder)? {
+        let entry = entry?;
+        let created_at = entry.file_name().to_str().and_then(parse_snapshot_name);
+        if let Some(created_at) = created_at.filter(|_| entry.path().is_file()) {
+            snapshots.push(BackupSnapshot {
+                path: entry.path(),
+                created_at,
+            });
+        }
+    }
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+    Ok(snapshots)
+}
+
+/// Snapshots, newest first, that fall outside the newest one per day for
+/// `keep_daily` days and the newest one per ISO week for `keep_weekly`
+/// weeks. The newest snapshot is always kept.
+pub fn expired(snapshots: &[BackupSnapshot], keep_daily: u32, keep_weekly: u32) -> Vec<PathBuf> {
+    let mut days = Vec::new();
+    let mut weeks = Vec::new();
+    let mut kept = HashSet::new();
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        let day = snapshot.created_at.date();
+        let week = snapshot.created_at.iso_week();
+        let mut keep = i == 0;
+        if !days.contains(&day) && days.len() < keep_daily as usize {
+            days.push(day);
+            keep = true;
+        }
+        if !weeks.contains(&week) && weeks.len() < keep_weekly as usize {
+            weeks.push(week);
+            keep = true;
+        }
+        if keep {
+            kept.insert(&snapshot.path);
+        }
+    }
+    snapshots
+        .iter()
+        .filter(|snapshot| !kept.contains(&snapshot.path))
+        .map(|snapshot| snapshot.path.clone())
+        .collect()
+}
+
+/// Take a scheduled backup into `folder`. The archive only gets its dated
+/// name once it is complete, so a half-written one is never listed.
+pub async fn write_snapshot(
+    db: &DatabaseConnection,
+    app_data_dir: &Path,
+    folder: &Path,
+    app_version: &str,
+) -> Result<BackupSnapshot, BearLlmAiError> {
+    let (manifest, archive) = create(db, app_data_dir, app_version, None).await?;
+    let path = folder.join(snapshot_name(&manifest.created_at));
+    let (folder, written) = (folder.to_path_buf(), path.clone());
+    blocking(move || {
+        std::fs::create_dir_all(&folder)?;
+        let partial = written.with_extension("partial");
+        std::fs::write(&partial, archive)?;
+        std::fs::rename(&partial, &written)?;
+        Ok(())
+    })
+    .await?;
+    Ok(BackupSnapshot {
+        path,
+        // as the name has it
+        created_at: manifest.created_at.trunc_subsecs(0),
+    })
+}
+
+/// Move damaged database files aside under a dated name, keeping the WAL
+/// with its database, so a fresh database can be created in their place.
+/// Returns the new path of the database file.
+pub fn set_aside_damaged(db_files: &[PathBuf], at: &NaiveDateTime) -> io::Result<PathBuf> {
+    let (db_path, _) = db_files.split_first().ok_or(io::ErrorKind::NotFound)?;
+    let damaged = format!("{}{}.db", DAMAGED_PREFIX, at.format(SNAPSHOT_TIME_FORMAT));
+    let mut moved = None;
+    for path in db_files {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some(suffix) = name.strip_prefix(DB_NAME) else {
+            continue;
+        };
+        if path.exists() {
+            let target = path.with_file_name(format!("{}{}", damaged, suffix));
+            std::fs::rename(path, &target)?;
+            if path == db_path {
+                moved = Some(target);
+            }
+        }
+    }
+    moved.ok_or_else(|| io::ErrorKind::NotFound.into())
+}
+
+/// Damaged database files set aside in `app_data_dir`
+pub fn damaged_copies(app_data_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut copies = Vec::new();
+    for entry in std::fs::read_dir(app_data_dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(DAMAGED_PREFIX) {
+            copies.push(entry.path());
+        }
+    }
+    Ok(copies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2026, 10, 17)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap()
+    }
+
+    fn latest_schema() -> String {
+        Migrator::migrations().last().unwrap().name().to_string()
+    }
+
+    #[test]
+    fn test_round_trips_plain_and_sealed() {
+        let snapshot = b"SQLite format 3\0 and then some pages";
+        let (manifest, archive) = pack(snapshot, "0.0.22", &latest_schema(), at(), None).unwrap();
+        assert_eq!(manifest.files[0].name, DB_NAME);
+        let (read, unpacked) = unpack(&archive, None).unwrap();
+        assert_eq!(read, manifest);
+        assert_eq!(unpacked.as_slice(), snapshot);
+
+        let (manifest, archive) = pack(snapshot, "0.0.22", &latest_schema(), at(), Some("correct horse")).unwrap();
+        assert!(manifest.encryption.is_some());
+        assert!(!archive.windows(snapshot.len()).any(|window| window == snapshot));
+        assert!(unpack(&archive, None).unwrap_err().to_string().contains("passphrase"));
+        assert!(unpack(&archive, Some("wrong")).is_err());
+        let (_, unpacked) = unpack(&archive, Some("correct horse")).unwrap();
+        assert_eq!(unpacked.as_slice(), snapshot);
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let (_, archive) = pack(b"db", "9.9.9", "m20991231_000001_from_the_future", at(), None).unwrap();
+        let err = unpack(&archive, None).unwrap_err().to_string();
+        assert!(err.contains("newer version"), "{}", err);
+    }
+
+    #[test]
+    fn test_snapshot_names_round_trip() {
+        let name = snapshot_name(&at());
+        assert_eq!(name, "bear-llm-ai-20261017-090000.zip");
+        assert_eq!(parse_snapshot_name(&name), Some(at()));
+        assert_eq!(parse_snapshot_name("bear-llm-ai-20261017-090000.partial"), None);
+        assert_eq!(parse_snapshot_name("bear-llm-ai.db"), None);
+    }
+
+    #[test]
+    fn test_rotation_keeps_newest_per_day_and_week() {
+        // 2026-10-17 is a Saturday; two snapshots a day for three weeks
+        let snapshots: Vec<BackupSnapshot> = (0..42)
+            .map(|i| {
+                let created_at = at() - chrono::Duration::hours(12 * i);
+                BackupSnapshot {
+                    path: PathBuf::from(snapshot_name(&created_at)),
+                    created_at,
+                }
+            })
+            .collect();
+        let expired = expired(&snapshots, 3, 2);
+        let kept: Vec<String> = snapshots
+            .iter()
+            .filter(|snapshot| !expired.contains(&snapshot.path))
+            .map(|snapshot| snapshot.created_at.format("%m-%d %H").to_string())
+            .collect();
+        // three days, plus the newest of the week before
+        assert_eq!(kept, ["10-17 09", "10-16 21", "10-15 21", "10-11 21"]);
+        assert_eq!(expired.len(), 38);
+        assert_eq!(super::expired(&snapshots[..1], 0, 0), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn test_detects_tampering() {
+        let (manifest, _) = pack(b"original", "0.0.22", &latest_schema(), at(), None).unwrap();
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(MANIFEST, SimpleFileOptions::default()).unwrap();
+        zip.write_all(&serde_json::to_vec(&manifest).unwrap()).unwrap();
+        zip.start_file(DB_NAME, SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"modified").unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+        let err = unpack(&archive, None).unwrap_err().to_string();
+        assert!(err.contains("checksum"), "{}", err);
+        assert!(unpack(b"not a zip", None).is_err());
+    }
+
+    #[test]
+    fn test_leftover_copies_are_erased() {
+        let dir = std::env::temp_dir().join(format!("bear-llm-ai-backup-leftovers-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let live = dir.join(DB_NAME);
+        std::fs::write(&live, b"live").unwrap();
+        std::fs::write(snapshot_path(&dir), b"snapshot").unwrap();
+        std::fs::write(staging_path(&dir), b"staged").unwrap();
+
+        let erased = remove_leftovers(&dir).unwrap();
+        assert_eq!(erased, vec![snapshot_path(&dir), staging_path(&dir)]);
+        assert!(scratch_files(&dir).iter().all(|path| !path.exists()));
+        assert!(live.exists());
+        assert!(remove_leftovers(&dir).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
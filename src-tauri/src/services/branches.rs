@@ -0,0 +1,134 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Conversation trees. Every message points at its parent; editing a prompt or
+//! regenerating a reply adds a sibling instead of overwriting, and the
+//! conversation remembers which leaf is active.
+use std::collections::{HashMap, HashSet};
+
+use entity::entities::messages;
+
+/// Messages from the root down to `leaf`
+pub fn path_to(messages: &[messages::Model], leaf: i32) -> Vec<messages::Model> {
+    let by_id: HashMap<i32, &messages::Model> = messages.iter().map(|m| (m.id, m)).collect();
+    let mut path = Vec::new();
+    let mut seen = HashSet::new();
+    let mut next = Some(leaf);
+    // `seen` guards against a cycle in damaged data
+    while let Some(id) = next.filter(|id| seen.insert(*id)) {
+        let Some(message) = by_id.get(&id) else {
+            break;
+        };
+        path.push((*message).clone());
+        next = message.parent_id;
+    }
+    path.reverse();
+    path
+}
+
+/// Follow the newest child down from `from` until reaching a leaf
+pub fn newest_leaf(messages: &[messages::Model], from: i32) -> i32 {
+    let mut leaf = from;
+    while let Some(child) = messages
+        .iter()
+        .filter(|m| m.parent_id == Some(leaf))
+        .map(|m| m.id)
+        .max()
+    {
+        leaf = child;
+    }
+    leaf
+}
+
+/// `id` and every message below it
+pub fn subtree(messages: &[messages::Model], id: i32) -> Vec<i32> {
+    let mut ids = vec![id];
+    let mut i = 0;
+    while i < ids.len() {
+        let parent = ids[i];
+        ids.extend(messages.iter().filter(|m| m.parent_id == Some(parent)).map(|m| m.id));
+        i += 1;
+    }
+    ids
+}
+
+/// Messages sharing the parent of `message`, itself included, oldest first
+pub fn siblings(messages: &[messages::Model], message: &messages::Model) -> Vec<messages::Model> {
+    let mut res: Vec<messages::Model> = messages
+        .iter()
+        .filter(|m| m.parent_id == message.parent_id)
+        .cloned()
+        .collect();
+    res.sort_by_key(|m| m.id);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: i32, parent_id: Option<i32>) -> messages::Model {
+        messages::Model {
+            id,
+            conversation_id: 1,
+            role: "user".to_string(),
+            content: format!("message {}", id),
+            created_at: chrono::Utc::now().naive_utc(),
+            prompt_token: None,
+            completion_token: None,
+            reasoning_token: None,
+            reasoning: None,
+            parent_id,
+            model_id: None,
+            provider: None,
+            model_name: None,
+            generation_options: None,
+            redaction: None,
+            app_version: None,
+        }
+    }
+
+    //   1 ── 2 ── 3
+    //   │    └─── 5 ── 6
+    //   └─── 4
+    fn tree() -> Vec<messages::Model> {
+        vec![
+            message(1, None),
+            message(2, Some(1)),
+            message(3, Some(2)),
+            message(4, Some(1)),
+            message(5, Some(2)),
+            message(6, Some(5)),
+        ]
+    }
+
+    fn ids(messages: &[messages::Model]) -> Vec<i32> {
+        messages.iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_path_to_walks_up_to_the_root() {
+        assert_eq!(ids(&path_to(&tree(), 6)), vec![1, 2, 5, 6]);
+        assert_eq!(ids(&path_to(&tree(), 4)), vec![1, 4]);
+        assert!(path_to(&tree(), 99).is_empty());
+    }
+
+    #[test]
+    fn test_path_to_stops_on_cycles() {
+        let looped = vec![message(1, Some(2)), message(2, Some(1))];
+        assert_eq!(ids(&path_to(&looped, 2)), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_newest_leaf_prefers_latest_children() {
+        assert_eq!(newest_leaf(&tree(), 1), 4);
+        assert_eq!(newest_leaf(&tree(), 2), 6);
+        assert_eq!(newest_leaf(&tree(), 3), 3);
+    }
+
+    #[test]
+    fn test_subtree_and_siblings() {
+        let mut below = subtree(&tree(), 2);
+        below.sort();
+        assert_eq!(below, vec![2, 3, 5, 6]);
+        assert_eq!(ids(&siblings(&tree(), &message(5, Some(2)))), vec![3, 5]);
+    }
+}
@@ -51,4 +51,15 @@ pub fn set_prompts_to_cache(
         },
     );
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Tell every window to drop its cached prompts
+pub fn clear_prompts_cache(handle: &AppHandle<Wry>) {
+    let _ = handle.emit(
+        "prompts_cache_change",
+        CacheChangeEvent {
+            key: PROMPTS_CACHE_KEY.to_string(),
+            value: Vec::<Prompt>::new(),
+        },
+    );
+}
@@ -1,21 +1,296 @@
 // This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
 // BEAR LLM AI changes - Fixed MigratorTrait import and added ActiveModelTrait, QueryFilter, ColumnTrait
 // MIT License Copyright (c) 2024-present Frank Zhang
-use sea_orm::{Database, DatabaseConnection, EntityTrait, Set, ActiveModelTrait, QueryFilter, ColumnTrait};
+use sea_orm::{sea_query::{self, Expr}, ActiveModelBehavior, ConnectionTrait, IntoActiveModel, Database, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, Set, ActiveModelTrait, QueryFilter, QueryOrder, QuerySelect, ColumnTrait, Condition, Order, Select, Statement, TransactionTrait, Value};
+use chrono::SubsecRound;
+use once_cell::sync::Lazy;
 use sea_orm_migration::MigratorTrait;
 use std::path::Path;
 
-use crate::errors::BearLlmAiError;
+use crate::{
+    crash_handler,
+    errors::BearLlmAiError,
+    services::{
+        audit,
+        branches,
+        encryption::{self, DataKey, Encryption, Protected},
+        export::ExportedTokens,
+        import::ImportedConversation,
+        integrity,
+        llm::chat::BotReply,
+        retention,
+        pii::Pseudonym,
+        projects as project_rules,
+        search,
+    },
+};
 use entity::entities::{
+    audit_log::{self, AuditAction, AuditLogQuery},
+    contents,
+    conversation_summaries,
     conversations,
-    messages,
+    messages::{self, MessageDTO, MessageSearchHit, MessageSearchQuery, Provenance},
     models,
+    pagination::{Page, PageQuery, SortOrder},
+    pii_vault,
+    projects::{self, ProjectPayload},
     prompts,
-    settings::{self, Setting, SettingKey},
+    retention_purges,
+    settings::{self, EncryptionSetting, Setting, SettingKey, ENCRYPTION_SETTING_KEY},
 };
 use migration::Migrator;
 
-const DB_NAME: &str = "bear-llm-ai.db";
+pub const DB_NAME: &str = "bear-llm-ai.db";
+/// The FTS index would keep a plaintext copy of every message, so it is
+/// emptied and no longer maintained once the history is encrypted
+const DISABLE_MESSAGE_INDEX: &[&str] = &[
+    "DROP TRIGGER IF EXISTS messages_fts_ai",
+    "DROP TRIGGER IF EXISTS messages_fts_ad",
+    "DROP TRIGGER IF EXISTS messages_fts_au",
+    "INSERT INTO messages_fts(messages_fts) VALUES ('delete-all')",
+];
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const DEFAULT_AUDIT_LIMIT: u64 = 500;
+/// Held while appending so two writers cannot link to the same entry
+static AUDIT_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+const MAX_PAGE_SIZE: u64 = 500;
+
+/// Keyset paging over `(key, id)`, so rows sharing a timestamp are neither
+/// skipped nor repeated. `cursor` is the sort key and id of the cursor row.
+async fn paginate<E, C>(
+    db: &C,
+    select: Select<E>,
+    key: E::Column,
+    id: E::Column,
+    cursor: Option<(Value, i32)>,
+    query: &PageQuery,
+) -> Result<Page<E::Model>, BearLlmAiError>
+where
+    E: EntityTrait,
+    C: sea_orm::ConnectionTrait,
+{
+    let order = query.order.unwrap_or_default();
+    let backwards = query.before.is_some();
+    let direction = if backwards { order.reverse() } else { order };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let mut select = select;
+    if let Some((cursor_key, cursor_id)) = cursor {
+        let past_cursor = match direction {
+            SortOrder::Asc => Condition::any()
+                .add(key.gt(cursor_key.clone()))
+                .add(Condition::all().add(key.eq(cursor_key)).add(id.gt(cursor_id))),
+            SortOrder::Desc => Condition::any()
+                .add(key.lt(cursor_key.clone()))
+                .add(Condition::all().add(key.eq(cursor_key)).add(id.lt(cursor_id))),
+        };
+        select = select.filter(past_cursor);
+    }
+    let sql_order = match direction {
+        SortOrder::Asc => Order::Asc,
+        SortOrder::Desc => Order::Desc,
+    };
+    // one extra row tells whether there is another page
+    let mut items = select
+        .order_by(key, sql_order.clone())
+        .order_by(id, sql_order)
+        .limit(limit + 1)
+        .all(db)
+        .await?;
+    let has_more = items.len() as u64 > limit;
+    items.truncate(limit as usize);
+    if backwards {
+        items.reverse();
+    }
+    Ok(Page { items, has_more })
+}
+
+/// Active message of a conversation, or its newest message for
+/// conversations that never had one set
+async fn active_leaf<C>(db: &C, conversation_id: i32) -> Result<Option<i32>, BearLlmAiError>
+where
+    C: sea_orm::ConnectionTrait,
+{
+    let active = conversations::Entity::find_by_id(conversation_id)
+        .one(db)
+        .await?
+        .and_then(|c| c.active_message_id);
+    if active.is_some() {
+        return Ok(active);
+    }
+    let newest = messages::Entity::find()
+        .filter(messages::Column::ConversationId.eq(conversation_id))
+        .order_by_desc(messages::Column::Id)
+        .one(db)
+        .await?;
+    Ok(newest.map(|m| m.id))
+}
+
+async fn set_active_message<C>(
+    db: &C,
+    conversation_id: i32,
+    message_id: Option<i32>,
+) -> Result<(), BearLlmAiError>
+where
+    C: sea_orm::ConnectionTrait,
+{
+    conversations::Entity::update_many()
+        .col_expr(conversations::Column::ActiveMessageId, Expr::value(message_id))
+        .filter(conversations::Column::Id.eq(conversation_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+async fn find_message<C>(db: &C, conversation_id: i32, id: i32) -> Result<messages::Model, BearLlmAiError>
+where
+    C: sea_orm::ConnectionTrait,
+{
+    messages::Entity::find_by_id(id)
+        .filter(messages::Column::ConversationId.eq(conversation_id))
+        .one(db)
+        .await?
+        .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+            "Message not found".to_string(),
+        )))
+}
+
+/// Every message of a conversation, all branches included
+async fn conversation_tree<C>(db: &C, conversation_id: i32) -> Result<Vec<messages::Model>, BearLlmAiError>
+where
+    C: sea_orm::ConnectionTrait,
+{
+    let res = messages::Entity::find()
+        .filter(messages::Column::ConversationId.eq(conversation_id))
+        .order_by_asc(messages::Column::Id)
+        .all(db)
+        .await?;
+    Ok(res)
+}
+
+/// Project a conversation is filed under, which decides the key its content
+/// is sealed with
+async fn project_of<C>(db: &C, conversation_id: i32) -> Result<Option<i32>, BearLlmAiError>
+where
+    C: sea_orm::ConnectionTrait,
+{
+    let conversation = conversations::Entity::find_by_id(conversation_id)
+        .one(db)
+        .await?
+        .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+            "Conversation not found".to_string(),
+        )))?;
+    Ok(conversation.project_id)
+}
+
+/// Rows outside the projects that are still locked with their own
+/// passphrase, for listings that span projects
+fn outside_locked_projects(column: impl ColumnTrait, encryption: &Encryption) -> Condition {
+    Condition::any()
+        .add(column.is_null())
+        .add(column.is_not_in(encryption.locked_projects()))
+}
+
+/// `search_messages` for an encrypted history: decrypt the messages in scope
+/// and scan them, since there is no index to query. Messages are read newest
+/// first in batches of `search::SCAN_BATCH`, stopping once `limit` hits are
+/// found, so a large history is neither loaded nor decrypted at once.
+async fn scan_messages(
+    db: &DatabaseConnection,
+    encryption: &Encryption,
+    query: MessageSearchQuery,
+) -> Result<Vec<MessageSearchHit>, BearLlmAiError> {
+    let Some(scan) = search::TextScan::new(&query.query) else {
+        return Ok(Vec::new());
+    };
+    let limit = query.limit.unwrap_or(search::DEFAULT_LIMIT).min(search::MAX_LIMIT) as usize;
+    let mut select = messages::Entity::find().find_also_related(conversations::Entity);
+    if let Some(conversation_id) = query.conversation_id {
+        select = select.filter(messages::Column::ConversationId.eq(conversation_id));
+    }
+    select = match query.project_id {
+        Some(project_id) => select.filter(conversations::Column::ProjectId.eq(project_id)),
+        // a project with its own passphrase is only searched from within
+        None => select.filter(
+            Condition::any()
+                .add(conversations::Column::ProjectId.is_null())
+                .add(conversations::Column::ProjectId.is_not_in(encryption.encrypted_projects())),
+        ),
+    };
+    if let Some(from) = query.from {
+        select = select.filter(messages::Column::CreatedAt.gte(from));
+    }
+    if let Some(to) = query.to {
+        select = select.filter(messages::Column::CreatedAt.lte(to));
+    }
+    let mut hits = Vec::new();
+    let mut before: Option<i32> = None;
+    while hits.len() < limit {
+        let mut batch = select.clone();
+        if let Some(id) = before {
+            batch = batch.filter(messages::Column::Id.lt(id));
+        }
+        let rows = batch
+            .order_by_desc(messages::Column::Id)
+            .limit(search::SCAN_BATCH)
+            .all(db)
+            .await?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        before = Some(last.id);
+        let exhausted = (rows.len() as u64) < search::SCAN_BATCH;
+        for (message, conversation) in rows {
+            let content = encryption.open(message.content)?;
+            if let Some((snippet, score)) = scan.scan(&content) {
+                hits.push(MessageSearchHit {
+                    message_id: message.id,
+                    conversation_id: message.conversation_id,
+                    conversation_name: conversation.map(|c| c.name).unwrap_or_default(),
+                    role: message.role,
+                    snippet,
+                    created_at: message.created_at,
+                    score,
+                });
+                if hits.len() == limit {
+                    break;
+                }
+            }
+        }
+        if exhausted {
+            break;
+        }
+    }
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.created_at.cmp(&a.created_at)));
+    Ok(hits)
+}
+
+/// Open a row read from the database and seal it again for `project_id`,
+/// for the store in the transaction that moves it
+fn reseal<T: Protected>(encryption: &Encryption, model: T, project_id: Option<i32>) -> Result<T, BearLlmAiError> {
+    encryption
+        .reveal(model)?
+        .map_protected(|text| encryption.seal_in(project_id, text))
+}
+
+/// Seal the protected fields of every row of a table that are still plaintext
+async fn seal_table<A, C>(db: &C, key: &DataKey) -> Result<(), BearLlmAiError>
+where
+    A: ActiveModelTrait + ActiveModelBehavior + Send,
+    <A::Entity as EntityTrait>::Model: Protected + IntoActiveModel<A>,
+    C: sea_orm::ConnectionTrait,
+{
+    for model in A::Entity::find().all(db).await? {
+        let sealed = model.map_protected(|text| {
+            if encryption::is_sealed(&text) {
+                Ok(text)
+            } else {
+                encryption::seal_with(key, &text)
+            }
+        })?;
+        sealed.into_active_model().reset_all().update(db).await?;
+    }
+    Ok(())
+}
 
 #[derive(Debug, Clone)]
 pub struct Db(pub DatabaseConnection);
@@ -42,12 +317,36 @@ impl Db {
         let db_url = format!("sqlite:{}?mode=rwc", db_path.to_str().ok_or("Invalid database path")?);
         log::info!("Connecting to database at: {}", db_url);
 
-        let conn = Database::connect(&db_url)
-            .await
-            .map_err(|e| {
+        let conn = match Database::connect(&db_url).await {
+            Ok(conn) => conn,
+            Err(e) if integrity::is_damage(&e) => {
+                log::error!("Database is damaged: {:?}", e);
+                return Err(Box::new(BearLlmAiError::Corrupted(e.to_string())));
+            }
+            Err(e) => {
                 log::error!("Failed to connect to database: {:?}", e);
-                format!("Database connection failed: {:?}", e)
-            })?;
+                return Err(format!("Database connection failed: {:?}", e).into());
+            }
+        };
+
+        // Migrations must not run on a damaged database; the caller can set
+        // it aside and restore a backup instead. A check that could not run
+        // for another reason, e.g. a busy or unreadable file, is an ordinary
+        // start-up failure and leaves the file where it is.
+        let problems = match integrity::integrity_errors(&conn).await {
+            Ok(problems) => problems,
+            Err(BearLlmAiError::DbErr(err)) if integrity::is_damage(&err) => vec![err.to_string()],
+            Err(err) => {
+                log::error!("Failed to check the database: {:?}", err);
+                let _ = conn.close().await;
+                return Err(format!("Database check failed: {}", err).into());
+            }
+        };
+        if !problems.is_empty() {
+            log::error!("Database integrity check failed: {:?}", problems);
+            let _ = conn.close().await;
+            return Err(Box::new(BearLlmAiError::Corrupted(problems.join("; "))));
+        }
 
         log::info!("Database connected successfully, running migrations...");
         Migrator::up(&conn, None)
@@ -57,10 +356,50 @@ impl Db {
                 format!("Database migration failed: {:?}", e)
             })?;
 
+        // Repairs are best-effort; a database that cannot be tidied up is
+        // still usable
+        match integrity::check_and_repair(&conn).await {
+            Ok(report) => {
+                crash_handler::write_diagnostic_info(&app_data_dir.to_path_buf(), &report.summary());
+                if report.repaired() {
+                    log::warn!("{}", report.summary());
+                    audit::record(&conn, AuditAction::DatabaseRepaired, None, serde_json::json!(report)).await;
+                }
+            }
+            Err(err) => {
+                log::error!("Database health check failed: {:?}", err);
+                crash_handler::write_diagnostic_info(
+                    &app_data_dir.to_path_buf(),
+                    &format!("Database health check failed: {}", err),
+                );
+            }
+        }
+
         log::info!("Database initialized successfully");
         Ok(Self(conn))
     }
 
+    /// The database file with its WAL, shared-memory and rollback journal
+    pub fn files(app_data_dir: &Path) -> Vec<std::path::PathBuf> {
+        ["", "-wal", "-shm", "-journal"]
+            .iter()
+            .map(|suffix| app_data_dir.join(format!("{}{}", DB_NAME, suffix)))
+            .collect()
+    }
+
+    /// Rewrite the database so deleted or replaced text does not stay behind
+    /// in free pages, and truncate the WAL that still holds the old pages.
+    /// Run once the change is committed and recorded, so a failure, e.g.
+    /// while another connection reads, is only logged.
+    pub async fn vacuum(db: &DatabaseConnection) {
+        for sql in ["VACUUM", "PRAGMA wal_checkpoint(TRUNCATE)"] {
+            if let Err(err) = db.execute_unprepared(sql).await {
+                log::warn!("Failed to vacuum the database: {}", err);
+                return;
+            }
+        }
+    }
+
     // --- Settings
     pub async fn get_settings(db: &DatabaseConnection) -> Result<serde_json::Value, BearLlmAiError> {
         let settings = settings::Entity::find().all(db).await?;
@@ -112,6 +451,51 @@ impl Db {
         }
     }
 
+    pub async fn get_encryption_setting(
+        db: &DatabaseConnection,
+    ) -> Result<Option<EncryptionSetting>, BearLlmAiError> {
+        let setting = settings::Entity::find_by_id(ENCRYPTION_SETTING_KEY.to_string()).one(db).await?;
+        match setting {
+            Some(s) => serde_json::from_str(&s.value)
+                .map(Some)
+                .map_err(|err| BearLlmAiError::Encryption(format!("Invalid encryption setting: {}", err))),
+            None => Ok(None),
+        }
+    }
+
+    /// Encrypt an existing plaintext database in place and keep it unlocked
+    /// with `key`. All rows are rewritten in one transaction; the caller runs
+    /// `vacuum` afterwards so no plaintext is left behind in free pages.
+    pub async fn enable_encryption(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        key: DataKey,
+        setting: EncryptionSetting,
+    ) -> Result<(), BearLlmAiError> {
+        if encryption.is_enabled() || Self::get_encryption_setting(db).await?.is_some() {
+            return Err(BearLlmAiError::Encryption("Encryption is already enabled".to_string()));
+        }
+        let txn = db.begin().await?;
+        for sql in DISABLE_MESSAGE_INDEX {
+            txn.execute_unprepared(sql).await?;
+        }
+        seal_table::<messages::ActiveModel, _>(&txn, &key).await?;
+        seal_table::<conversations::ActiveModel, _>(&txn, &key).await?;
+        seal_table::<prompts::ActiveModel, _>(&txn, &key).await?;
+        seal_table::<contents::ActiveModel, _>(&txn, &key).await?;
+        seal_table::<conversation_summaries::ActiveModel, _>(&txn, &key).await?;
+        seal_table::<pii_vault::ActiveModel, _>(&txn, &key).await?;
+        settings::ActiveModel {
+            key: Set(ENCRYPTION_SETTING_KEY.to_string()),
+            value: Set(serde_json::to_string(&setting).unwrap()),
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+        encryption.unlock(key);
+        Ok(())
+    }
+
     pub async fn get_proxy_setting(
         db: &DatabaseConnection,
     ) -> Result<Option<settings::ProxySetting>, BearLlmAiError> {
@@ -126,6 +510,71 @@ impl Db {
         }
     }
 
+    // --- Audit log
+    pub async fn append_audit_log(
+        db: &DatabaseConnection,
+        action: AuditAction,
+        target: Option<String>,
+        details: serde_json::Value,
+    ) -> Result<audit_log::Model, BearLlmAiError> {
+        let _guard = AUDIT_LOCK.lock().await;
+        let prev_hash = audit_log::Entity::find()
+            .order_by_desc(audit_log::Column::Id)
+            .one(db)
+            .await?
+            .map(|entry| entry.hash)
+            .unwrap_or_else(|| audit::GENESIS_HASH.to_string());
+        // the hash covers microseconds, which is what survives the round trip
+        let created_at = chrono::Utc::now().naive_utc().trunc_subsecs(6);
+        let action = action.to_string();
+        let details = details.to_string();
+        let hash = audit::hash_entry(&prev_hash, &created_at, &action, target.as_deref(), &details);
+        let res = audit_log::ActiveModel {
+            created_at: Set(created_at),
+            action: Set(action),
+            target: Set(target),
+            details: Set(details),
+            prev_hash: Set(prev_hash),
+            hash: Set(hash),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(res)
+    }
+
+    /// Matching entries, newest first
+    pub async fn get_audit_log(
+        db: &DatabaseConnection,
+        query: AuditLogQuery,
+    ) -> Result<Vec<audit_log::Model>, BearLlmAiError> {
+        let mut select = audit_log::Entity::find();
+        if let Some(action) = query.action {
+            select = select.filter(audit_log::Column::Action.eq(action.to_string()));
+        }
+        if let Some(from) = query.from {
+            select = select.filter(audit_log::Column::CreatedAt.gte(from));
+        }
+        if let Some(to) = query.to {
+            select = select.filter(audit_log::Column::CreatedAt.lte(to));
+        }
+        let res = select
+            .order_by_desc(audit_log::Column::Id)
+            .limit(query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT))
+            .all(db)
+            .await?;
+        Ok(res)
+    }
+
+    /// The whole log, oldest first, as needed to verify the chain
+    pub async fn get_audit_chain(db: &DatabaseConnection) -> Result<Vec<audit_log::Model>, BearLlmAiError> {
+        let res = audit_log::Entity::find()
+            .order_by_asc(audit_log::Column::Id)
+            .all(db)
+            .await?;
+        Ok(res)
+    }
+
     // --- Models
     pub async fn get_models(db: &DatabaseConnection) -> Result<Vec<models::Model>, BearLlmAiError> {
         let res = models::Entity::find().all(db).await?;
@@ -188,43 +637,250 @@ impl Db {
         Ok(res)
     }
 
+    // --- Projects
+    pub async fn get_projects(db: &DatabaseConnection) -> Result<Vec<projects::Model>, BearLlmAiError> {
+        let res = projects::Entity::find()
+            .order_by_asc(projects::Column::Name)
+            .order_by_asc(projects::Column::Id)
+            .all(db)
+            .await?;
+        Ok(res)
+    }
+
+    pub async fn get_project(db: &DatabaseConnection, id: i32) -> Result<projects::Model, BearLlmAiError> {
+        projects::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Project not found".to_string(),
+            )))
+    }
+
+    /// Key parameters of a project with a passphrase of its own
+    pub async fn get_project_encryption_setting(
+        db: &DatabaseConnection,
+        id: i32,
+    ) -> Result<Option<EncryptionSetting>, BearLlmAiError> {
+        match Self::get_project(db, id).await?.encryption {
+            Some(setting) => serde_json::from_str(&setting)
+                .map(Some)
+                .map_err(|err| BearLlmAiError::Encryption(format!("Invalid encryption setting: {}", err))),
+            None => Ok(None),
+        }
+    }
+
+    /// A reference number names one client or matter, so it cannot be
+    /// given to a second project
+    async fn check_reference_free(
+        db: &DatabaseConnection,
+        reference: Option<&str>,
+        id: Option<i32>,
+    ) -> Result<(), BearLlmAiError> {
+        let Some(reference) = reference else {
+            return Ok(());
+        };
+        let mut select = projects::Entity::find().filter(projects::Column::Reference.eq(reference));
+        if let Some(id) = id {
+            select = select.filter(projects::Column::Id.ne(id));
+        }
+        match select.one(db).await? {
+            Some(other) => Err(BearLlmAiError::Invalid(format!(
+                "Reference {} is already used by {}",
+                reference, other.name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// `encryption` holds the key parameters when the project gets a
+    /// passphrase of its own; that can only be chosen when it is created
+    pub async fn create_project(
+        db: &DatabaseConnection,
+        payload: ProjectPayload,
+        encryption: Option<EncryptionSetting>,
+    ) -> Result<projects::Model, BearLlmAiError> {
+        let payload = project_rules::normalize(payload)?;
+        Self::check_reference_free(db, payload.reference.as_deref(), None).await?;
+        let now = chrono::Utc::now().naive_utc();
+        let res = projects::ActiveModel {
+            name: Set(payload.name),
+            reference: Set(payload.reference),
+            colour: Set(payload.colour),
+            metadata: Set(payload.metadata.unwrap_or_else(|| "{}".to_string())),
+            retention_days: Set(payload.retention_days),
+            encryption: Set(encryption.map(|setting| serde_json::to_string(&setting).unwrap())),
+            created_at: Set(now),
+            updated_at: Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(res)
+    }
+
+    pub async fn update_project(
+        db: &DatabaseConnection,
+        id: i32,
+        payload: ProjectPayload,
+    ) -> Result<projects::Model, BearLlmAiError> {
+        let project = Self::get_project(db, id).await?;
+        let payload = project_rules::normalize(payload)?;
+        Self::check_reference_free(db, payload.reference.as_deref(), Some(id)).await?;
+        let mut active_model: projects::ActiveModel = project.into();
+        active_model.name = Set(payload.name);
+        active_model.reference = Set(payload.reference);
+        active_model.colour = Set(payload.colour);
+        active_model.metadata = Set(payload.metadata.unwrap_or_else(|| "{}".to_string()));
+        active_model.retention_days = Set(payload.retention_days);
+        active_model.updated_at = Set(chrono::Utc::now().naive_utc());
+        let res = active_model.update(db).await?;
+        Ok(res)
+    }
+
+    /// Delete a project with its conversations, prompts and documents.
+    /// Refused while one of its conversations is on legal hold. Returns the
+    /// number of conversations deleted; the caller runs `vacuum` afterwards
+    /// so the client's data does not stay in free pages.
+    pub async fn delete_project(db: &DatabaseConnection, id: i32) -> Result<u64, BearLlmAiError> {
+        let project = Self::get_project(db, id).await?;
+        let conversations = conversations::Entity::find()
+            .filter(conversations::Column::ProjectId.eq(id))
+            .all(db)
+            .await?;
+        if let Some(held) = conversations.iter().find(|c| c.legal_hold) {
+            return Err(BearLlmAiError::Invalid(format!(
+                "{} cannot be deleted, its conversation {} is on legal hold",
+                project.name, held.name
+            )));
+        }
+        let txn = db.begin().await?;
+        for conversation in &conversations {
+            Self::delete_conversation_rows(&txn, conversation.id).await?;
+        }
+        prompts::Entity::delete_many()
+            .filter(prompts::Column::ProjectId.eq(id))
+            .exec(&txn)
+            .await?;
+        contents::Entity::delete_many()
+            .filter(contents::Column::ProjectId.eq(id))
+            .exec(&txn)
+            .await?;
+        projects::Entity::delete_by_id(id).exec(&txn).await?;
+        txn.commit().await?;
+        Ok(conversations.len() as u64)
+    }
+
     // --- Conversations
     pub async fn get_conversations(
         db: &DatabaseConnection,
+        encryption: &Encryption,
     ) -> Result<Vec<conversations::Model>, BearLlmAiError> {
-        let res = conversations::Entity::find().all(db).await?;
-        Ok(res)
+        let res = conversations::Entity::find()
+            .filter(outside_locked_projects(conversations::Column::ProjectId, encryption))
+            .all(db)
+            .await?;
+        encryption.reveal_all(res)
+    }
+
+    /// The conversations of one project, most recent first
+    pub async fn get_project_conversations(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        project_id: i32,
+    ) -> Result<Vec<conversations::Model>, BearLlmAiError> {
+        Self::get_project(db, project_id).await?;
+        let res = conversations::Entity::find()
+            .filter(conversations::Column::ProjectId.eq(project_id))
+            .order_by_desc(conversations::Column::LastMessageAt)
+            .order_by_desc(conversations::Column::Id)
+            .all(db)
+            .await?;
+        encryption.reveal_all(res)
+    }
+
+    pub async fn get_conversation(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+    ) -> Result<conversations::Model, BearLlmAiError> {
+        let res = conversations::Entity::find_by_id(id).one(db).await?.ok_or(BearLlmAiError::DbErr(
+            sea_orm::DbErr::RecordNotFound("Conversation not found".to_string()),
+        ))?;
+        encryption.reveal(res)
+    }
+
+    /// Conversations by `last_message_at`, most recent first unless asked otherwise
+    pub async fn get_conversations_page(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        query: PageQuery,
+    ) -> Result<Page<conversations::Model>, BearLlmAiError> {
+        let cursor_id = query.before.or(query.after);
+        let cursor = match cursor_id {
+            Some(id) => {
+                let c = conversations::Entity::find_by_id(id).one(db).await?.ok_or(
+                    BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                        "Conversation not found".to_string(),
+                    )),
+                )?;
+                Some((c.last_message_at.into(), c.id))
+            }
+            None => None,
+        };
+        let mut page = paginate(
+            db,
+            conversations::Entity::find().filter(outside_locked_projects(conversations::Column::ProjectId, encryption)),
+            conversations::Column::LastMessageAt,
+            conversations::Column::Id,
+            cursor,
+            &query,
+        )
+        .await?;
+        page.items = encryption.reveal_all(page.items)?;
+        Ok(page)
     }
 
     pub async fn create_conversation(
         db: &DatabaseConnection,
+        encryption: &Encryption,
         payload: conversations::Model,
     ) -> Result<conversations::Model, BearLlmAiError> {
+        if let Some(project_id) = payload.project_id {
+            Self::get_project(db, project_id).await?;
+        }
         let new_conversation = conversations::ActiveModel {
             name: Set(payload.name.to_owned()),
             model_id: Set(payload.model_id.to_owned()),
-            system_message: Set(payload.system_message.to_owned()),
+            system_message: Set(encryption.seal_opt_in(payload.project_id, payload.system_message.to_owned())?),
             options: Set(payload.options.to_owned()),
+            last_message_at: Set(chrono::Utc::now().naive_utc()),
+            retention_days: Set(payload.retention_days),
+            pinned: Set(payload.pinned),
+            legal_hold: Set(payload.legal_hold),
+            project_id: Set(payload.project_id),
             ..Default::default()
         };
         let res = new_conversation.insert(db).await?;
-        Ok(res)
+        encryption.reveal(res)
     }
 
+    /// The project is left as it is; see `move_conversation`
     pub async fn update_conversation(
         db: &DatabaseConnection,
+        encryption: &Encryption,
         id: i32,
         payload: conversations::Model,
     ) -> Result<conversations::Model, BearLlmAiError> {
         let conversation = conversations::Entity::find_by_id(id).one(db).await?;
         if let Some(c) = conversation {
+            let project_id = c.project_id;
             let mut active_model: conversations::ActiveModel = c.into();
             active_model.name = Set(payload.name.to_owned());
             active_model.model_id = Set(payload.model_id.to_owned());
-            active_model.system_message = Set(payload.system_message.to_owned());
+            active_model.system_message = Set(encryption.seal_opt_in(project_id, payload.system_message.to_owned())?);
             active_model.options = Set(payload.options.to_owned());
             let res = active_model.update(db).await?;
-            Ok(res)
+            encryption.reveal(res)
         } else {
             Err(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
                 "Conversation not found".to_string(),
@@ -232,78 +888,774 @@ impl Db {
         }
     }
 
+    /// File a conversation under `project_id`, or under no project, sealing
+    /// its system message, messages, summaries and vault again for that
+    /// project. Both projects must be unlocked. Returns the conversation and
+    /// the project it came from. Moved into a project with a passphrase, the
+    /// caller runs `vacuum` afterwards so no other sealing of the text stays
+    /// in free pages.
+    pub async fn move_conversation(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+        project_id: Option<i32>,
+    ) -> Result<(conversations::Model, Option<i32>), BearLlmAiError> {
+        let conversation = conversations::Entity::find_by_id(id).one(db).await?.ok_or(
+            BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Conversation not found".to_string(),
+            )),
+        )?;
+        let from = conversation.project_id;
+        if from == project_id {
+            return Ok((encryption.reveal(conversation)?, from));
+        }
+        if let Some(project_id) = project_id {
+            Self::get_project(db, project_id).await?;
+        }
+        let txn = db.begin().await?;
+        for message in messages::Entity::find()
+            .filter(messages::Column::ConversationId.eq(id))
+            .all(&txn)
+            .await?
+        {
+            reseal(encryption, message, project_id)?.into_active_model().reset_all().update(&txn).await?;
+        }
+        for summary in conversation_summaries::Entity::find()
+            .filter(conversation_summaries::Column::ConversationId.eq(id))
+            .all(&txn)
+            .await?
+        {
+            reseal(encryption, summary, project_id)?.into_active_model().reset_all().update(&txn).await?;
+        }
+        for entry in pii_vault::Entity::find()
+            .filter(pii_vault::Column::ConversationId.eq(id))
+            .all(&txn)
+            .await?
+        {
+            reseal(encryption, entry, project_id)?.into_active_model().reset_all().update(&txn).await?;
+        }
+        let mut moved = reseal(encryption, conversation, project_id)?;
+        moved.project_id = project_id;
+        let res = moved.into_active_model().reset_all().update(&txn).await?;
+        txn.commit().await?;
+        Ok((encryption.reveal(res)?, from))
+    }
+
     pub async fn delete_conversation(db: &DatabaseConnection, id: i32) -> Result<(), BearLlmAiError> {
-        conversations::Entity::delete_by_id(id).exec(db).await?;
+        let txn = db.begin().await?;
+        Self::delete_conversation_rows(&txn, id).await?;
+        txn.commit().await?;
         Ok(())
     }
 
+    /// Delete a conversation together with everything that hangs off it; the
+    /// schema has no cascading foreign keys. Returns the number of messages
+    /// deleted.
+    async fn delete_conversation_rows<C: ConnectionTrait>(db: &C, id: i32) -> Result<u64, BearLlmAiError> {
+        conversation_summaries::Entity::delete_many()
+            .filter(conversation_summaries::Column::ConversationId.eq(id))
+            .exec(db)
+            .await?;
+        pii_vault::Entity::delete_many()
+            .filter(pii_vault::Column::ConversationId.eq(id))
+            .exec(db)
+            .await?;
+        let deleted = messages::Entity::delete_many()
+            .filter(messages::Column::ConversationId.eq(id))
+            .exec(db)
+            .await?;
+        conversations::Entity::delete_by_id(id).exec(db).await?;
+        Ok(deleted.rows_affected)
+    }
+
+    // --- Retention
+    pub async fn update_conversation_retention(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+        payload: conversations::RetentionOverride,
+    ) -> Result<conversations::Model, BearLlmAiError> {
+        let conversation = conversations::Entity::find_by_id(id).one(db).await?.ok_or(
+            BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Conversation not found".to_string(),
+            )),
+        )?;
+        let mut active_model: conversations::ActiveModel = conversation.into();
+        active_model.retention_days = Set(payload.retention_days);
+        active_model.pinned = Set(payload.pinned);
+        active_model.legal_hold = Set(payload.legal_hold);
+        let res = active_model.update(db).await?;
+        encryption.reveal(res)
+    }
+
+    /// Delete every conversation past its retention period, with its messages,
+    /// summaries and vault, and record each purge. Needs no key, so it also runs
+    /// while the app is locked. The caller runs `vacuum` afterwards so the
+    /// purged text does not stay in free pages.
+    pub async fn purge_expired_conversations(
+        db: &DatabaseConnection,
+        global_days: u64,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<retention_purges::Model>, BearLlmAiError> {
+        let candidates = conversations::Entity::find()
+            .filter(conversations::Column::Pinned.eq(false))
+            .filter(conversations::Column::LegalHold.eq(false))
+            .all(db)
+            .await?;
+        let project_days: std::collections::HashMap<i32, i32> = projects::Entity::find()
+            .filter(projects::Column::RetentionDays.is_not_null())
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|project| Some((project.id, project.retention_days?)))
+            .collect();
+        let mut purges = Vec::new();
+        for conversation in candidates {
+            let project_days = conversation.project_id.and_then(|id| project_days.get(&id).copied());
+            let Some(expiry) = retention::expiry(&conversation, project_days, global_days, now) else {
+                continue;
+            };
+            let txn = db.begin().await?;
+            let deleted = Self::delete_conversation_rows(&txn, conversation.id).await?;
+            let purge = retention_purges::ActiveModel {
+                conversation_id: Set(conversation.id),
+                message_count: Set(deleted as i32),
+                last_message_at: Set(conversation.last_message_at),
+                retention_days: Set(expiry.retention_days),
+                policy: Set(expiry.policy.to_string()),
+                purged_at: Set(now),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+            txn.commit().await?;
+            purges.push(purge);
+        }
+        Ok(purges)
+    }
+
+    pub async fn get_retention_purges(
+        db: &DatabaseConnection,
+    ) -> Result<Vec<retention_purges::Model>, BearLlmAiError> {
+        let res = retention_purges::Entity::find()
+            .order_by_desc(retention_purges::Column::PurgedAt)
+            .order_by_desc(retention_purges::Column::Id)
+            .all(db)
+            .await?;
+        Ok(res)
+    }
+
     // --- Messages
     pub async fn get_conversation_messages(
         db: &DatabaseConnection,
+        encryption: &Encryption,
         conversation_id: i32,
     ) -> Result<Vec<messages::Model>, BearLlmAiError> {
         let res = messages::Entity::find()
             .filter(messages::Column::ConversationId.eq(conversation_id))
             .all(db)
             .await?;
-        Ok(res)
+        encryption.reveal_all(res)
     }
 
+    /// Messages of a conversation by `created_at`, oldest first unless asked
+    /// otherwise; a chat view loads the newest page with `order: desc`
+    pub async fn get_conversation_messages_page(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+        mut query: PageQuery,
+    ) -> Result<Page<messages::Model>, BearLlmAiError> {
+        query.order = Some(query.order.unwrap_or(SortOrder::Asc));
+        let cursor_id = query.before.or(query.after);
+        let cursor = match cursor_id {
+            Some(id) => {
+                let m = messages::Entity::find_by_id(id)
+                    .filter(messages::Column::ConversationId.eq(conversation_id))
+                    .one(db)
+                    .await?
+                    .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                        "Message not found".to_string(),
+                    )))?;
+                Some((m.created_at.into(), m.id))
+            }
+            None => None,
+        };
+        let mut page = paginate(
+            db,
+            messages::Entity::find().filter(messages::Column::ConversationId.eq(conversation_id)),
+            messages::Column::CreatedAt,
+            messages::Column::Id,
+            cursor,
+            &query,
+        )
+        .await?;
+        page.items = encryption.reveal_all(page.items)?;
+        Ok(page)
+    }
+
+    /// Store messages, each following the previous one of its conversation.
+    /// A message without `parent_id` continues the active branch, and the last
+    /// one stored becomes its conversation's active message.
     pub async fn create_messages(
         db: &DatabaseConnection,
+        encryption: &Encryption,
         payload: Vec<messages::Model>,
     ) -> Result<Vec<messages::Model>, BearLlmAiError> {
-        let new_messages = payload
-            .into_iter()
-            .map(|m| messages::ActiveModel {
+        if payload.is_empty() {
+            return Ok(Vec::new());
+        }
+        let txn = db.begin().await?;
+        let now = chrono::Utc::now().naive_utc();
+        let mut res: Vec<messages::Model> = Vec::new();
+        let mut projects = std::collections::HashMap::new();
+        for m in payload {
+            let project_id = match projects.get(&m.conversation_id) {
+                Some(project_id) => *project_id,
+                None => {
+                    let project_id = project_of(&txn, m.conversation_id).await?;
+                    projects.insert(m.conversation_id, project_id);
+                    project_id
+                }
+            };
+            let parent_id = match m.parent_id {
+                Some(parent_id) => Some(parent_id),
+                None => match res.iter().rev().find(|r| r.conversation_id == m.conversation_id) {
+                    Some(previous) => Some(previous.id),
+                    None => active_leaf(&txn, m.conversation_id).await?,
+                },
+            };
+            let message = messages::ActiveModel {
                 conversation_id: Set(m.conversation_id.to_owned()),
                 role: Set(m.role.to_owned()),
-                content: Set(m.content.to_owned()),
+                content: Set(encryption.seal_in(project_id, m.content.to_owned())?),
+                created_at: Set(now),
+                prompt_token: Set(m.prompt_token),
+                completion_token: Set(m.completion_token),
+                reasoning_token: Set(m.reasoning_token),
+                reasoning: Set(encryption.seal_opt_in(project_id, m.reasoning.to_owned())?),
+                parent_id: Set(parent_id),
                 ..Default::default()
-            })
-            .collect::<Vec<messages::ActiveModel>>();
-        let res = messages::Entity::insert_many(new_messages)
-            .exec(db)
+            }
+            .insert(&txn)
+            .await?;
+            set_active_message(&txn, message.conversation_id, Some(message.id)).await?;
+            res.push(message);
+        }
+        txn.commit().await?;
+        encryption.reveal_all(res)
+    }
+
+    /// Store a finished exchange in one transaction: the prompt that was sent
+    /// (if any), the assistant reply with its reasoning, token usage and provenance, and
+    /// the conversation's `last_message_at`. The exchange hangs below
+    /// `reply_to`, or below the active message, and becomes the active branch.
+    pub async fn create_exchange(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+        reply_to: Option<i32>,
+        user_message: Option<MessageDTO>,
+        reply: &BotReply,
+        provenance: &Provenance,
+    ) -> Result<Vec<messages::Model>, BearLlmAiError> {
+        let txn = db.begin().await?;
+        let conversation = conversations::Entity::find_by_id(conversation_id)
+            .one(&txn)
+            .await?
+            .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Conversation not found".to_string(),
+            )))?;
+        let mut parent_id = match reply_to {
+            Some(reply_to) => Some(find_message(&txn, conversation_id, reply_to).await?.id),
+            None => active_leaf(&txn, conversation_id).await?,
+        };
+        let now = chrono::Utc::now().naive_utc();
+        let project_id = conversation.project_id;
+        let mut res = Vec::new();
+        if let Some(m) = user_message {
+            let user = messages::ActiveModel {
+                conversation_id: Set(conversation_id),
+                role: Set(m.role),
+                content: Set(encryption.seal_in(project_id, m.content)?),
+                created_at: Set(now),
+                parent_id: Set(parent_id),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+            parent_id = Some(user.id);
+            res.push(user);
+        }
+        let assistant = messages::ActiveModel {
+            conversation_id: Set(conversation_id),
+            role: Set("assistant".to_string()),
+            content: Set(encryption.seal_in(project_id, reply.message.to_owned())?),
+            created_at: Set(now),
+            prompt_token: Set(reply.prompt_token.map(|t| t as i32)),
+            completion_token: Set(reply.completion_token.map(|t| t as i32)),
+            reasoning_token: Set(reply.reasoning_token.map(|t| t as i32)),
+            reasoning: Set(encryption.seal_opt_in(project_id, reply.reasoning.to_owned())?),
+            parent_id: Set(parent_id),
+            model_id: Set(provenance.model_id),
+            provider: Set(provenance.provider.clone()),
+            model_name: Set(provenance.model_name.clone()),
+            generation_options: Set(provenance.generation_options.clone()),
+            redaction: Set(provenance.redaction.clone()),
+            app_version: Set(provenance.app_version.clone()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let mut active_conversation: conversations::ActiveModel = conversation.into();
+        active_conversation.last_message_at = Set(now);
+        active_conversation.active_message_id = Set(Some(assistant.id));
+        active_conversation.update(&txn).await?;
+        res.push(assistant);
+        txn.commit().await?;
+        encryption.reveal_all(res)
+    }
+
+    /// Store an imported conversation, bound to `model_id` and filed under
+    /// `project_id`, with its messages as a single branch
+    pub async fn import_conversation(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        model_id: i32,
+        project_id: Option<i32>,
+        imported: ImportedConversation,
+    ) -> Result<conversations::Model, BearLlmAiError> {
+        let ImportedConversation { conversation, messages } = imported;
+        let txn = db.begin().await?;
+        let created = conversations::ActiveModel {
+            name: Set(conversation.name),
+            model_id: Set(model_id),
+            system_message: Set(encryption.seal_opt_in(project_id, conversation.system_message)?),
+            options: Set(conversation.options.unwrap_or_else(|| "{}".to_string())),
+            last_message_at: Set(conversation.last_message_at),
+            retention_days: Set(None),
+            pinned: Set(false),
+            legal_hold: Set(false),
+            project_id: Set(project_id),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        let mut parent_id = None;
+        for message in messages {
+            let tokens = message.tokens.unwrap_or(ExportedTokens {
+                prompt: None,
+                completion: None,
+                reasoning: None,
+            });
+            let provenance = message.provenance.unwrap_or_default();
+            let inserted = messages::ActiveModel {
+                conversation_id: Set(created.id),
+                role: Set(message.role),
+                content: Set(encryption.seal_in(project_id, message.content)?),
+                created_at: Set(message.created_at),
+                prompt_token: Set(tokens.prompt),
+                completion_token: Set(tokens.completion),
+                reasoning_token: Set(tokens.reasoning),
+                reasoning: Set(encryption.seal_opt_in(project_id, message.reasoning)?),
+                parent_id: Set(parent_id),
+                // Ids from another installation mean nothing here
+                model_id: Set(None),
+                provider: Set(provenance.provider),
+                model_name: Set(provenance.model_name),
+                generation_options: Set(provenance.generation_options),
+                redaction: Set(provenance.redaction),
+                app_version: Set(provenance.app_version),
+                ..Default::default()
+            }
+            .insert(&txn)
+            .await?;
+            parent_id = Some(inserted.id);
+        }
+        let mut active_conversation: conversations::ActiveModel = created.into();
+        active_conversation.active_message_id = Set(parent_id);
+        let res = active_conversation.update(&txn).await?;
+        txn.commit().await?;
+        encryption.reveal(res)
+    }
+
+    /// Messages from the root of a conversation down to `leaf`, or down to the
+    /// active message when `leaf` is `None`
+    pub async fn get_message_path(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+        leaf: Option<i32>,
+    ) -> Result<Vec<messages::Model>, BearLlmAiError> {
+        let leaf = match leaf {
+            Some(leaf) => Some(leaf),
+            None => active_leaf(db, conversation_id).await?,
+        };
+        let Some(leaf) = leaf else {
+            return Ok(Vec::new());
+        };
+        let all = conversation_tree(db, conversation_id).await?;
+        encryption.reveal_all(branches::path_to(&all, leaf))
+    }
+
+    /// Store an edited copy of a message next to the original, so both drafts
+    /// stay available, and make the copy the active message. Answering an
+    /// edited prompt is a chat request with `reply_to` set to the copy.
+    pub async fn edit_message(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+        content: String,
+    ) -> Result<messages::Model, BearLlmAiError> {
+        let txn = db.begin().await?;
+        let original = messages::Entity::find_by_id(id)
+            .one(&txn)
+            .await?
+            .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Message not found".to_string(),
+            )))?;
+        let project_id = project_of(&txn, original.conversation_id).await?;
+        let edited = messages::ActiveModel {
+            conversation_id: Set(original.conversation_id),
+            role: Set(original.role),
+            content: Set(encryption.seal_in(project_id, content)?),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            parent_id: Set(original.parent_id),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        set_active_message(&txn, edited.conversation_id, Some(edited.id)).await?;
+        txn.commit().await?;
+        encryption.reveal(edited)
+    }
+
+    /// The alternatives to a message, i.e. all messages sharing its parent,
+    /// oldest first and including the message itself
+    pub async fn get_message_branches(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+    ) -> Result<Vec<messages::Model>, BearLlmAiError> {
+        let message = messages::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Message not found".to_string(),
+            )))?;
+        let all = conversation_tree(db, message.conversation_id).await?;
+        encryption.reveal_all(branches::siblings(&all, &message))
+    }
+
+    /// Make the branch through `id` active, continuing down its newest
+    /// replies, and return the new active path
+    pub async fn switch_branch(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+    ) -> Result<Vec<messages::Model>, BearLlmAiError> {
+        let message = messages::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Message not found".to_string(),
+            )))?;
+        let all = conversation_tree(db, message.conversation_id).await?;
+        let leaf = branches::newest_leaf(&all, message.id);
+        set_active_message(db, message.conversation_id, Some(leaf)).await?;
+        encryption.reveal_all(branches::path_to(&all, leaf))
+    }
+
+    /// Delete a message together with every reply below it. If the active
+    /// branch went with it, the newest remaining branch becomes active.
+    pub async fn delete_message(db: &DatabaseConnection, id: i32) -> Result<(), BearLlmAiError> {
+        let txn = db.begin().await?;
+        let message = messages::Entity::find_by_id(id)
+            .one(&txn)
+            .await?
+            .ok_or(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Message not found".to_string(),
+            )))?;
+        let mut all = conversation_tree(&txn, message.conversation_id).await?;
+        let removed = branches::subtree(&all, message.id);
+        messages::Entity::delete_many()
+            .filter(messages::Column::Id.is_in(removed.clone()))
+            .exec(&txn)
+            .await?;
+        let active = active_leaf(&txn, message.conversation_id).await?;
+        if active.map_or(true, |active| removed.contains(&active)) {
+            all.retain(|m| !removed.contains(&m.id));
+            let leaf = match message.parent_id {
+                Some(parent_id) if all.iter().any(|m| m.id == parent_id) => {
+                    Some(branches::newest_leaf(&all, parent_id))
+                }
+                _ => all.iter().map(|m| m.id).max(),
+            };
+            set_active_message(&txn, message.conversation_id, leaf).await?;
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Ranked full-text search over message content via `messages_fts`,
+    /// within one project when `project_id` is set
+    pub async fn search_messages(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        query: MessageSearchQuery,
+    ) -> Result<Vec<MessageSearchHit>, BearLlmAiError> {
+        let project_encrypted = query
+            .project_id
+            .is_some_and(|project_id| encryption.is_project_encrypted(project_id));
+        if encryption.is_enabled() || project_encrypted {
+            return scan_messages(db, encryption, query).await;
+        }
+        let Some(expression) = search::match_expression(&query.query) else {
+            return Ok(Vec::new());
+        };
+        let mut sql = format!(
+            "SELECT m.id AS message_id, m.conversation_id, c.name AS conversation_name, m.role, \
+             snippet(messages_fts, 0, '{}', '{}', '…', 24) AS snippet, m.created_at, \
+             -bm25(messages_fts) AS score \
+             FROM messages_fts \
+             JOIN messages m ON m.id = messages_fts.rowid \
+             JOIN conversations c ON c.id = m.conversation_id \
+             WHERE messages_fts MATCH ?",
+            search::SNIPPET_OPEN,
+            search::SNIPPET_CLOSE,
+        );
+        let mut values: Vec<sea_orm::Value> = vec![expression.into()];
+        if let Some(conversation_id) = query.conversation_id {
+            sql.push_str(" AND m.conversation_id = ?");
+            values.push(conversation_id.into());
+        }
+        match query.project_id {
+            Some(project_id) => {
+                sql.push_str(" AND c.project_id = ?");
+                values.push(project_id.into());
+            }
+            None => {
+                // the index holds sealed text for these anyway
+                let encrypted = encryption.encrypted_projects();
+                if !encrypted.is_empty() {
+                    sql.push_str(&format!(
+                        " AND (c.project_id IS NULL OR c.project_id NOT IN ({}))",
+                        vec!["?"; encrypted.len()].join(", ")
+                    ));
+                    values.extend(encrypted.into_iter().map(sea_orm::Value::from));
+                }
+            }
+        }
+        if let Some(from) = query.from {
+            sql.push_str(" AND m.created_at >= ?");
+            values.push(from.into());
+        }
+        if let Some(to) = query.to {
+            sql.push_str(" AND m.created_at <= ?");
+            values.push(to.into());
+        }
+        sql.push_str(" ORDER BY score DESC, m.created_at DESC LIMIT ?");
+        let limit = query.limit.unwrap_or(search::DEFAULT_LIMIT).min(search::MAX_LIMIT);
+        values.push((limit as i64).into());
+        let res = MessageSearchHit::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            sql,
+            values,
+        ))
+        .all(db)
+        .await?;
+        Ok(res)
+    }
+
+    // --- Conversation summaries
+    pub async fn get_conversation_summaries(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+    ) -> Result<Vec<conversation_summaries::Model>, BearLlmAiError> {
+        let res = conversation_summaries::Entity::find()
+            .filter(conversation_summaries::Column::ConversationId.eq(conversation_id))
+            .order_by_asc(conversation_summaries::Column::Id)
+            .all(db)
+            .await?;
+        encryption.reveal_all(res)
+    }
+
+    pub async fn get_latest_conversation_summary(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+    ) -> Result<Option<conversation_summaries::Model>, BearLlmAiError> {
+        let res = conversation_summaries::Entity::find()
+            .filter(conversation_summaries::Column::ConversationId.eq(conversation_id))
+            .order_by_desc(conversation_summaries::Column::Id)
+            .one(db)
             .await?;
-        let last_insert_id = res.last_insert_id;
-        let messages = messages::Entity::find()
-            .filter(messages::Column::Id.gte(last_insert_id))
+        res.map(|summary| encryption.reveal(summary)).transpose()
+    }
+
+    /// Record a new summary. Older summaries are kept for reference and the
+    /// summarised messages are never touched.
+    pub async fn create_conversation_summary(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+        content: String,
+        first_message_id: i32,
+        last_message_id: i32,
+    ) -> Result<conversation_summaries::Model, BearLlmAiError> {
+        let project_id = project_of(db, conversation_id).await?;
+        let new_summary = conversation_summaries::ActiveModel {
+            conversation_id: Set(conversation_id),
+            content: Set(encryption.seal_in(project_id, content)?),
+            first_message_id: Set(first_message_id),
+            last_message_id: Set(last_message_id),
+            created_at: Set(chrono::Utc::now().naive_utc()),
+            ..Default::default()
+        };
+        let res = new_summary.insert(db).await?;
+        encryption.reveal(res)
+    }
+
+    // --- PII vault
+    pub async fn get_pii_vault(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+    ) -> Result<Vec<pii_vault::Model>, BearLlmAiError> {
+        let res = pii_vault::Entity::find()
+            .filter(pii_vault::Column::ConversationId.eq(conversation_id))
+            .order_by_asc(pii_vault::Column::Id)
             .all(db)
             .await?;
-        Ok(messages)
+        encryption.reveal_all(res)
+    }
+
+    /// Whether the vault of a conversation can be kept: its values are the
+    /// very data redaction hides, so they are only stored sealed, with the
+    /// app passphrase or that of the conversation's project
+    pub async fn can_keep_pii_vault(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+    ) -> Result<bool, BearLlmAiError> {
+        Ok(encryption.seals_in(project_of(db, conversation_id).await?))
+    }
+
+    /// Store new placeholders for a conversation. One already taken, by a
+    /// request running alongside, keeps its first value. Refused unless the
+    /// values can be sealed, see `can_keep_pii_vault`.
+    pub async fn add_to_pii_vault(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        conversation_id: i32,
+        pseudonyms: &[Pseudonym],
+    ) -> Result<(), BearLlmAiError> {
+        if pseudonyms.is_empty() {
+            return Ok(());
+        }
+        let project_id = project_of(db, conversation_id).await?;
+        if !encryption.seals_in(project_id) {
+            return Err(BearLlmAiError::Encryption(
+                "Set a passphrase before placeholders are kept with a conversation".to_string(),
+            ));
+        }
+        let now = chrono::Utc::now().naive_utc();
+        let mut entries = Vec::with_capacity(pseudonyms.len());
+        for pseudonym in pseudonyms {
+            entries.push(pii_vault::ActiveModel {
+                conversation_id: Set(conversation_id),
+                placeholder: Set(pseudonym.placeholder.clone()),
+                label: Set(pseudonym.label.clone()),
+                value: Set(encryption.seal_in(project_id, pseudonym.value.clone())?),
+                created_at: Set(now),
+                ..Default::default()
+            });
+        }
+        pii_vault::Entity::insert_many(entries)
+            .on_conflict(
+                sea_query::OnConflict::columns([pii_vault::Column::ConversationId, pii_vault::Column::Placeholder])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget what the placeholders of a conversation stand for. Messages
+    /// stored masked can no longer be unmasked afterwards. The caller runs
+    /// `vacuum` afterwards so the values do not stay in free pages.
+    pub async fn purge_pii_vault(db: &DatabaseConnection, conversation_id: i32) -> Result<u64, BearLlmAiError> {
+        let res = pii_vault::Entity::delete_many()
+            .filter(pii_vault::Column::ConversationId.eq(conversation_id))
+            .exec(db)
+            .await?;
+        Ok(res.rows_affected)
     }
 
     // --- Prompts
-    pub async fn get_prompts(db: &DatabaseConnection) -> Result<Vec<prompts::Model>, BearLlmAiError> {
-        let res = prompts::Entity::find().all(db).await?;
-        Ok(res)
+    pub async fn get_prompts(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+    ) -> Result<Vec<prompts::Model>, BearLlmAiError> {
+        let res = prompts::Entity::find()
+            .filter(outside_locked_projects(prompts::Column::ProjectId, encryption))
+            .all(db)
+            .await?;
+        encryption.reveal_all(res)
+    }
+
+    /// The prompts of a project and the templates shared by all projects
+    pub async fn get_project_prompts(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        project_id: i32,
+    ) -> Result<Vec<prompts::Model>, BearLlmAiError> {
+        Self::get_project(db, project_id).await?;
+        let res = prompts::Entity::find()
+            .filter(
+                Condition::any()
+                    .add(prompts::Column::ProjectId.is_null())
+                    .add(prompts::Column::ProjectId.eq(project_id)),
+            )
+            .all(db)
+            .await?;
+        encryption.reveal_all(res)
     }
 
     pub async fn create_prompt(
         db: &DatabaseConnection,
+        encryption: &Encryption,
         payload: prompts::Model,
     ) -> Result<prompts::Model, BearLlmAiError> {
+        if let Some(project_id) = payload.project_id {
+            Self::get_project(db, project_id).await?;
+        }
         let new_prompt = prompts::ActiveModel {
             name: Set(payload.name.to_owned()),
-            content: Set(payload.content.to_owned()),
+            content: Set(encryption.seal_in(payload.project_id, payload.content.to_owned())?),
+            project_id: Set(payload.project_id),
             ..Default::default()
         };
         let res = new_prompt.insert(db).await?;
-        Ok(res)
+        encryption.reveal(res)
     }
 
     pub async fn update_prompt(
         db: &DatabaseConnection,
+        encryption: &Encryption,
         id: i32,
         payload: prompts::Model,
     ) -> Result<prompts::Model, BearLlmAiError> {
         let prompt = prompts::Entity::find_by_id(id).one(db).await?;
         if let Some(p) = prompt {
+            let project_id = p.project_id;
             let mut active_model: prompts::ActiveModel = p.into();
             active_model.name = Set(payload.name.to_owned());
-            active_model.content = Set(payload.content.to_owned());
+            active_model.content = Set(encryption.seal_in(project_id, payload.content.to_owned())?);
             let res = active_model.update(db).await?;
-            Ok(res)
+            encryption.reveal(res)
         } else {
             Err(BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
                 "Prompt not found".to_string(),
@@ -315,4 +1667,216 @@ impl Db {
         prompts::Entity::delete_by_id(id).exec(db).await?;
         Ok(())
     }
+
+    // --- Documents
+    /// Documents of a project, or of the general workspace without one
+    pub async fn get_documents(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        project_id: Option<i32>,
+    ) -> Result<Vec<contents::Model>, BearLlmAiError> {
+        let select = match project_id {
+            Some(project_id) => {
+                Self::get_project(db, project_id).await?;
+                contents::Entity::find().filter(contents::Column::ProjectId.eq(project_id))
+            }
+            None => contents::Entity::find().filter(contents::Column::ProjectId.is_null()),
+        };
+        let res = select
+            .order_by_asc(contents::Column::Name)
+            .order_by_asc(contents::Column::Id)
+            .all(db)
+            .await?;
+        encryption.reveal_all(res)
+    }
+
+    pub async fn create_document(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        payload: contents::Model,
+    ) -> Result<contents::Model, BearLlmAiError> {
+        if let Some(project_id) = payload.project_id {
+            Self::get_project(db, project_id).await?;
+        }
+        let res = contents::ActiveModel {
+            name: Set(payload.name),
+            content: Set(encryption.seal_in(payload.project_id, payload.content)?),
+            project_id: Set(payload.project_id),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        encryption.reveal(res)
+    }
+
+    /// Refused while the document's project is locked. Returns the project
+    /// the document was in.
+    pub async fn delete_document(
+        db: &DatabaseConnection,
+        encryption: &Encryption,
+        id: i32,
+    ) -> Result<Option<i32>, BearLlmAiError> {
+        let document = contents::Entity::find_by_id(id).one(db).await?.ok_or(
+            BearLlmAiError::DbErr(sea_orm::DbErr::RecordNotFound(
+                "Document not found".to_string(),
+            )),
+        )?;
+        if let Some(project_id) = document.project_id.filter(|&project_id| encryption.is_project_locked(project_id)) {
+            return Err(BearLlmAiError::ProjectLocked(project_id));
+        }
+        contents::Entity::delete_by_id(id).exec(db).await?;
+        Ok(document.project_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn memory_db() -> DatabaseConnection {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        db
+    }
+
+    /// Cheap parameters so the test does not spend seconds in Argon2
+    fn test_key() -> DataKey {
+        let setting = EncryptionSetting {
+            salt: "c2FsdHNhbHRzYWx0c2FsdA==".to_string(),
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+            verifier: String::new(),
+        };
+        encryption::derive_key("correct horse", &setting).unwrap()
+    }
+
+    async fn conversation(db: &DatabaseConnection, encryption: &Encryption, project_id: Option<i32>) -> i32 {
+        let payload = conversations::Model {
+            id: 0,
+            name: "Acme".to_string(),
+            model_id: 1,
+            system_message: None,
+            options: "{}".to_string(),
+            last_message_at: chrono::Utc::now().naive_utc(),
+            active_message_id: None,
+            retention_days: None,
+            pinned: false,
+            legal_hold: false,
+            project_id,
+        };
+        Db::create_conversation(db, encryption, payload).await.unwrap().id
+    }
+
+    async fn stored_values(db: &DatabaseConnection) -> Vec<String> {
+        pii_vault::Entity::find()
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.value)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_pii_vault_is_never_stored_in_plaintext() {
+        let jan = [Pseudonym {
+            placeholder: "[PERSON_1]".to_string(),
+            label: "PERSON".to_string(),
+            value: "Jan de Vries".to_string(),
+        }];
+
+        // no passphrase at all: nothing is written
+        let db = memory_db().await;
+        let plain = Encryption::default();
+        let id = conversation(&db, &plain, None).await;
+        assert!(!Db::can_keep_pii_vault(&db, &plain, id).await.unwrap());
+        assert!(matches!(
+            Db::add_to_pii_vault(&db, &plain, id, &jan).await,
+            Err(BearLlmAiError::Encryption(_))
+        ));
+        assert!(stored_values(&db).await.is_empty());
+
+        // sealed with the app key
+        let app = Encryption::new(true);
+        app.unlock(test_key());
+        Db::add_to_pii_vault(&db, &app, id, &jan).await.unwrap();
+
+        // sealed with the key of a project that has a passphrase of its own
+        let project = Db::create_project(&db, ProjectPayload { name: "Beta".to_string(), ..Default::default() }, None)
+            .await
+            .unwrap();
+        let own = Encryption::default();
+        own.register_project(project.id);
+        own.unlock_project(project.id, test_key());
+        let in_project = conversation(&db, &own, Some(project.id)).await;
+        assert!(Db::can_keep_pii_vault(&db, &own, in_project).await.unwrap());
+        Db::add_to_pii_vault(&db, &own, in_project, &jan).await.unwrap();
+
+        let values = stored_values(&db).await;
+        assert_eq!(values.len(), 2);
+        for value in &values {
+            assert!(encryption::is_sealed(value), "{}", value);
+            assert!(!value.contains("Jan"), "{}", value);
+        }
+        assert_eq!(Db::get_pii_vault(&db, &own, in_project).await.unwrap()[0].value, "Jan de Vries");
+    }
+
+    #[tokio::test]
+    async fn test_moved_conversation_is_sealed_for_its_new_project() {
+        let db = memory_db().await;
+        let project = Db::create_project(&db, ProjectPayload { name: "Beta".to_string(), ..Default::default() }, None)
+            .await
+            .unwrap();
+        let encryption = Encryption::new(true);
+        encryption.unlock(test_key());
+        encryption.register_project(project.id);
+        encryption.unlock_project(project.id, test_key());
+        let payload = conversations::Model {
+            system_message: Some("Jan de Vries is the client".to_string()),
+            ..Db::get_conversation(&db, &encryption, conversation(&db, &encryption, None).await).await.unwrap()
+        };
+        let id = Db::update_conversation(&db, &encryption, payload.id, payload).await.unwrap().id;
+        let jan = [Pseudonym {
+            placeholder: "[PERSON_1]".to_string(),
+            label: "PERSON".to_string(),
+            value: "Jan de Vries".to_string(),
+        }];
+        Db::add_to_pii_vault(&db, &encryption, id, &jan).await.unwrap();
+
+        let (moved, from) = Db::move_conversation(&db, &encryption, id, Some(project.id)).await.unwrap();
+        assert_eq!(from, None);
+        assert_eq!(moved.project_id, Some(project.id));
+        assert_eq!(moved.system_message.as_deref(), Some("Jan de Vries is the client"));
+        let prefix = format!("enc:p{}:", project.id);
+        let stored = conversations::Entity::find_by_id(id).one(&db).await.unwrap().unwrap();
+        assert_eq!(stored.project_id, Some(project.id));
+        assert!(stored.system_message.unwrap().starts_with(&prefix));
+        for value in stored_values(&db).await {
+            assert!(value.starts_with(&prefix), "{}", value);
+        }
+        assert_eq!(Db::get_pii_vault(&db, &encryption, id).await.unwrap()[0].value, "Jan de Vries");
+
+        // documents are sealed with the key of their project and listed only there
+        let document = contents::Model {
+            id: 0,
+            name: "Brief".to_string(),
+            content: "Jan de Vries".to_string(),
+            project_id: Some(project.id),
+        };
+        let document = Db::create_document(&db, &encryption, document).await.unwrap();
+        let stored = contents::Entity::find().one(&db).await.unwrap().unwrap();
+        assert!(stored.content.starts_with(&format!("enc:p{}:", project.id)), "{}", stored.content);
+        assert_eq!(Db::get_documents(&db, &encryption, Some(project.id)).await.unwrap()[0].content, "Jan de Vries");
+        assert!(Db::get_documents(&db, &encryption, None).await.unwrap().is_empty());
+
+        // and cannot be deleted without the project's passphrase
+        encryption.lock_project(project.id);
+        assert!(matches!(
+            Db::delete_document(&db, &encryption, document.id).await,
+            Err(BearLlmAiError::ProjectLocked(_))
+        ));
+        encryption.unlock_project(project.id, test_key());
+        assert_eq!(Db::delete_document(&db, &encryption, document.id).await.unwrap(), Some(project.id));
+    }
 }
@@ -0,0 +1,541 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Field-level encryption at rest.
+//!
+//! Message content and reasoning, system messages, prompts, documents and
+//! conversation summaries are sealed with XChaCha20-Poly1305 before they reach SQLite.
+//! The key is derived from the user's passphrase with Argon2id and only held
+//! in memory; locking drops it, which zeroises it. Ids, names and timestamps
+//! stay readable so indexes and paging keep working.
+//!
+//! A project can have a passphrase of its own. Its conversations, messages,
+//! prompts and documents are then sealed with the project's key instead, tagged with
+//! the project id so they open with the right key, and stay unreadable
+//! while the project is locked even if the app is unlocked.
+use std::{collections::HashMap, sync::RwLock};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose, Engine as _};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::errors::BearLlmAiError;
+use entity::entities::{
+    contents, conversation_summaries, conversations, messages, pii_vault, prompts, settings::EncryptionSetting,
+};
+
+/// Marks sealed values, so rows written before encryption was enabled can be
+/// told apart while an existing database is converted
+const SEALED_PREFIX: &str = "enc:v1:";
+/// Followed by the project id and a colon
+const PROJECT_SEALED_PREFIX: &str = "enc:p";
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+// RFC 9106 second recommended option, with a single lane so unlocking does
+// not stall low-end workstations
+const MEMORY_KIB: u32 = 64 * 1024;
+const ITERATIONS: u32 = 3;
+const PARALLELISM: u32 = 1;
+const VERIFIER: &str = "bear-llm-ai";
+
+pub struct DataKey(Zeroizing<[u8; KEY_LEN]>);
+
+impl DataKey {
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.0.as_ref().into())
+    }
+}
+
+impl std::fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DataKey(..)")
+    }
+}
+
+fn encryption_error(message: impl Into<String>) -> BearLlmAiError {
+    BearLlmAiError::Encryption(message.into())
+}
+
+pub fn derive_key(passphrase: &str, setting: &EncryptionSetting) -> Result<DataKey, BearLlmAiError> {
+    if passphrase.is_empty() {
+        return Err(encryption_error("Passphrase must not be empty"));
+    }
+    let salt = general_purpose::STANDARD
+        .decode(&setting.salt)
+        .map_err(|err| encryption_error(format!("Invalid salt: {}", err)))?;
+    let params = Params::new(setting.memory_kib, setting.iterations, setting.parallelism, Some(KEY_LEN))
+        .map_err(|err| encryption_error(format!("Invalid key parameters: {}", err)))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+        .map_err(|err| encryption_error(format!("Key derivation failed: {}", err)))?;
+    Ok(DataKey(key))
+}
+
+/// Derive a key for a new passphrase with a fresh salt, returning the
+/// setting to store alongside the data
+pub fn new_key(passphrase: &str) -> Result<(DataKey, EncryptionSetting), BearLlmAiError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut setting = EncryptionSetting {
+        salt: general_purpose::STANDARD.encode(salt),
+        memory_kib: MEMORY_KIB,
+        iterations: ITERATIONS,
+        parallelism: PARALLELISM,
+        verifier: String::new(),
+    };
+    let key = derive_key(passphrase, &setting)?;
+    setting.verifier = seal_with(&key, VERIFIER)?;
+    Ok((key, setting))
+}
+
+/// Derive the key for `passphrase` and check it against the stored verifier
+pub fn unlock_key(passphrase: &str, setting: &EncryptionSetting) -> Result<DataKey, BearLlmAiError> {
+    let key = derive_key(passphrase, setting)?;
+    match open_with(&key, &setting.verifier) {
+        Ok(verifier) if verifier == VERIFIER => Ok(key),
+        _ => Err(encryption_error("Wrong passphrase")),
+    }
+}
+
+pub fn is_sealed(text: &str) -> bool {
+    text.starts_with(SEALED_PREFIX) || sealed_project(text).is_some()
+}
+
+/// Project whose key sealed `text`, if a project key did
+fn sealed_project(text: &str) -> Option<i32> {
+    let (id, _) = text.strip_prefix(PROJECT_SEALED_PREFIX)?.split_once(':')?;
+    id.parse().ok()
+}
+
+/// `enc:v1:` followed by the base64 of a random nonce and the ciphertext
+pub fn seal_with(key: &DataKey, text: &str) -> Result<String, BearLlmAiError> {
+    let sealed = seal_bytes(key, text.as_bytes())?;
+    Ok(format!("{}{}", SEALED_PREFIX, general_purpose::STANDARD.encode(sealed)))
+}
+
+/// Values without the sealed prefix are returned unchanged
+pub fn open_with(key: &DataKey, text: &str) -> Result<String, BearLlmAiError> {
+    let Some(encoded) = text.strip_prefix(SEALED_PREFIX) else {
+        return Ok(text.to_string());
+    };
+    let sealed = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| encryption_error("Corrupted encrypted value"))?;
+    let plaintext = open_bytes(key, &sealed)?;
+    String::from_utf8(plaintext).map_err(|_| encryption_error("Decrypted value is not UTF-8"))
+}
+
+/// `enc:p<id>:` followed by the base64 of a random nonce and the ciphertext
+pub fn seal_for_project(key: &DataKey, project_id: i32, text: &str) -> Result<String, BearLlmAiError> {
+    let sealed = seal_bytes(key, text.as_bytes())?;
+    Ok(format!("{}{}:{}", PROJECT_SEALED_PREFIX, project_id, general_purpose::STANDARD.encode(sealed)))
+}
+
+fn open_for_project(key: &DataKey, text: &str) -> Result<String, BearLlmAiError> {
+    let encoded = text
+        .strip_prefix(PROJECT_SEALED_PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .map(|(_, encoded)| encoded)
+        .ok_or_else(|| encryption_error("Corrupted encrypted value"))?;
+    let sealed = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|_| encryption_error("Corrupted encrypted value"))?;
+    let plaintext = open_bytes(key, &sealed)?;
+    String::from_utf8(plaintext).map_err(|_| encryption_error("Decrypted value is not UTF-8"))
+}
+
+/// A random nonce followed by the ciphertext
+pub fn seal_bytes(key: &DataKey, data: &[u8]) -> Result<Vec<u8>, BearLlmAiError> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key
+        .cipher()
+        .encrypt(&nonce, data)
+        .map_err(|_| encryption_error("Failed to encrypt"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+pub fn open_bytes(key: &DataKey, sealed: &[u8]) -> Result<Vec<u8>, BearLlmAiError> {
+    if sealed.len() < NONCE_LEN {
+        return Err(encryption_error("Corrupted encrypted value"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    key.cipher()
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| encryption_error("Failed to decrypt, the data or the key is wrong"))
+}
+
+/// Models with fields that are stored sealed
+pub trait Protected: Sized {
+    fn map_protected<F>(self, f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>;
+}
+
+fn map_opt<F>(value: Option<String>, f: F) -> Result<Option<String>, BearLlmAiError>
+where
+    F: FnMut(String) -> Result<String, BearLlmAiError>,
+{
+    value.map(f).transpose()
+}
+
+impl Protected for messages::Model {
+    fn map_protected<F>(mut self, mut f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>,
+    {
+        self.content = f(self.content)?;
+        self.reasoning = map_opt(self.reasoning, f)?;
+        Ok(self)
+    }
+}
+
+impl Protected for conversations::Model {
+    fn map_protected<F>(mut self, f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>,
+    {
+        self.system_message = map_opt(self.system_message, f)?;
+        Ok(self)
+    }
+}
+
+impl Protected for prompts::Model {
+    fn map_protected<F>(mut self, mut f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>,
+    {
+        self.content = f(self.content)?;
+        Ok(self)
+    }
+}
+
+impl Protected for contents::Model {
+    fn map_protected<F>(mut self, mut f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>,
+    {
+        self.content = f(self.content)?;
+        Ok(self)
+    }
+}
+
+impl Protected for conversation_summaries::Model {
+    fn map_protected<F>(mut self, mut f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>,
+    {
+        self.content = f(self.content)?;
+        Ok(self)
+    }
+}
+
+impl Protected for pii_vault::Model {
+    fn map_protected<F>(mut self, mut f: F) -> Result<Self, BearLlmAiError>
+    where
+        F: FnMut(String) -> Result<String, BearLlmAiError>,
+    {
+        self.value = f(self.value)?;
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Disabled,
+    Locked,
+    Unlocked(DataKey),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+}
+
+/// The in-memory keys, shared by every command through `BearLlmAiHandle`
+#[derive(Debug, Default)]
+pub struct Encryption {
+    state: RwLock<State>,
+    /// Projects with a passphrase of their own, and their key while unlocked
+    projects: RwLock<HashMap<i32, Option<DataKey>>>,
+}
+
+impl Encryption {
+    /// An encrypted database starts out locked
+    pub fn new(enabled: bool) -> Self {
+        let state = if enabled { State::Locked } else { State::Disabled };
+        Self {
+            state: RwLock::new(state),
+            ..Default::default()
+        }
+    }
+
+    pub fn status(&self) -> EncryptionStatus {
+        match *self.state.read().unwrap() {
+            State::Disabled => EncryptionStatus {
+                enabled: false,
+                unlocked: true,
+            },
+            State::Locked => EncryptionStatus {
+                enabled: true,
+                unlocked: false,
+            },
+            State::Unlocked(_) => EncryptionStatus {
+                enabled: true,
+                unlocked: true,
+            },
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.status().enabled
+    }
+
+    pub fn unlock(&self, key: DataKey) {
+        *self.state.write().unwrap() = State::Unlocked(key);
+    }
+
+    /// Drop the key, which zeroises it, and the keys of the projects.
+    /// Does nothing to the app itself without encryption.
+    pub fn lock(&self) {
+        let mut state = self.state.write().unwrap();
+        if matches!(*state, State::Unlocked(_)) {
+            *state = State::Locked;
+        }
+        for key in self.projects.write().unwrap().values_mut() {
+            *key = None;
+        }
+    }
+
+    /// Forget the key and the passphrase requirement, for a freshly created
+    /// database
+    pub fn disable(&self) {
+        *self.state.write().unwrap() = State::Disabled;
+        self.projects.write().unwrap().clear();
+    }
+
+    /// A project with a passphrase of its own, locked until it is given
+    pub fn register_project(&self, project_id: i32) {
+        self.projects.write().unwrap().entry(project_id).or_insert(None);
+    }
+
+    pub fn unlock_project(&self, project_id: i32, key: DataKey) {
+        self.projects.write().unwrap().insert(project_id, Some(key));
+    }
+
+    pub fn lock_project(&self, project_id: i32) {
+        if let Some(key) = self.projects.write().unwrap().get_mut(&project_id) {
+            *key = None;
+        }
+    }
+
+    /// For a deleted project
+    pub fn forget_project(&self, project_id: i32) {
+        self.projects.write().unwrap().remove(&project_id);
+    }
+
+    pub fn is_project_encrypted(&self, project_id: i32) -> bool {
+        self.projects.read().unwrap().contains_key(&project_id)
+    }
+
+    pub fn is_project_locked(&self, project_id: i32) -> bool {
+        matches!(self.projects.read().unwrap().get(&project_id), Some(None))
+    }
+
+    pub fn encrypted_projects(&self) -> Vec<i32> {
+        self.projects.read().unwrap().keys().copied().collect()
+    }
+
+    pub fn locked_projects(&self) -> Vec<i32> {
+        self.projects
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, key)| key.is_none())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Run `f` with the key, `None` meaning encryption is disabled
+    fn with_key<R>(&self, f: impl FnOnce(Option<&DataKey>) -> Result<R, BearLlmAiError>) -> Result<R, BearLlmAiError> {
+        match &*self.state.read().unwrap() {
+            State::Disabled => f(None),
+            State::Locked => Err(BearLlmAiError::Locked),
+            State::Unlocked(key) => f(Some(key)),
+        }
+    }
+
+    pub fn seal(&self, text: String) -> Result<String, BearLlmAiError> {
+        self.with_key(|key| match key {
+            Some(key) => seal_with(key, &text),
+            None => Ok(text),
+        })
+    }
+
+    pub fn seal_opt(&self, text: Option<String>) -> Result<Option<String>, BearLlmAiError> {
+        text.map(|text| self.seal(text)).transpose()
+    }
+
+    /// Seal text belonging to `project_id`, with the project's key when it
+    /// has a passphrase of its own
+    pub fn seal_in(&self, project_id: Option<i32>, text: String) -> Result<String, BearLlmAiError> {
+        let Some(project_id) = project_id else {
+            return self.seal(text);
+        };
+        // the project keys are released before `seal` takes the app key, so
+        // the locks are always taken app key first
+        let sealed = match self.projects.read().unwrap().get(&project_id) {
+            Some(Some(key)) => Some(seal_for_project(key, project_id, &text)),
+            Some(None) => return Err(BearLlmAiError::ProjectLocked(project_id)),
+            None => None,
+        };
+        sealed.unwrap_or_else(|| self.seal(text))
+    }
+
+    /// Whether `seal_in` encrypts for `project_id` rather than passing the
+    /// text through, locked or not
+    pub fn seals_in(&self, project_id: Option<i32>) -> bool {
+        self.is_enabled() || project_id.is_some_and(|project_id| self.is_project_encrypted(project_id))
+    }
+
+    pub fn seal_opt_in(&self, project_id: Option<i32>, text: Option<String>) -> Result<Option<String>, BearLlmAiError> {
+        text.map(|text| self.seal_in(project_id, text)).transpose()
+    }
+
+    /// Open a value with the app key, or with the key of the project that
+    /// sealed it
+    fn open_value(&self, key: Option<&DataKey>, text: String) -> Result<String, BearLlmAiError> {
+        if let Some(project_id) = sealed_project(&text) {
+            return match self.projects.read().unwrap().get(&project_id) {
+                Some(Some(key)) => open_for_project(key, &text),
+                _ => Err(BearLlmAiError::ProjectLocked(project_id)),
+            };
+        }
+        match key {
+            Some(key) => open_with(key, &text),
+            None => Ok(text),
+        }
+    }
+
+    pub fn open(&self, text: String) -> Result<String, BearLlmAiError> {
+        self.with_key(|key| self.open_value(key, text))
+    }
+
+    /// Decrypt the protected fields of a model read from the database
+    pub fn reveal<T: Protected>(&self, model: T) -> Result<T, BearLlmAiError> {
+        self.with_key(|key| model.map_protected(|text| self.open_value(key, text)))
+    }
+
+    pub fn reveal_all<T: Protected>(&self, models: Vec<T>) -> Result<Vec<T>, BearLlmAiError> {
+        self.with_key(|key| {
+            models
+                .into_iter()
+                .map(|model| model.map_protected(|text| self.open_value(key, text)))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters so the tests do not spend seconds in Argon2
+    fn test_key(passphrase: &str) -> (DataKey, EncryptionSetting) {
+        let mut setting = EncryptionSetting {
+            salt: general_purpose::STANDARD.encode([7u8; SALT_LEN]),
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+            verifier: String::new(),
+        };
+        let key = derive_key(passphrase, &setting).unwrap();
+        setting.verifier = seal_with(&key, VERIFIER).unwrap();
+        (key, setting)
+    }
+
+    #[test]
+    fn test_seal_round_trips_and_uses_fresh_nonces() {
+        let (key, _) = test_key("correct horse");
+        let a = seal_with(&key, "Geheimhaltung \u{e9}").unwrap();
+        let b = seal_with(&key, "Geheimhaltung \u{e9}").unwrap();
+        assert!(is_sealed(&a));
+        assert_ne!(a, b);
+        assert_eq!(open_with(&key, &a).unwrap(), "Geheimhaltung \u{e9}");
+    }
+
+    #[test]
+    fn test_plaintext_passes_through_open() {
+        let (key, _) = test_key("correct horse");
+        assert_eq!(open_with(&key, "written before encryption").unwrap(), "written before encryption");
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_rejected() {
+        let (_, setting) = test_key("correct horse");
+        assert!(unlock_key("correct horse", &setting).is_ok());
+        assert!(matches!(unlock_key("battery staple", &setting), Err(BearLlmAiError::Encryption(_))));
+        assert!(unlock_key("", &setting).is_err());
+    }
+
+    #[test]
+    fn test_tampered_value_fails_to_open() {
+        let (key, _) = test_key("correct horse");
+        let sealed = seal_with(&key, "privileged").unwrap();
+        let mut bytes = general_purpose::STANDARD.decode(&sealed[SEALED_PREFIX.len()..]).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let tampered = format!("{}{}", SEALED_PREFIX, general_purpose::STANDARD.encode(bytes));
+        assert!(open_with(&key, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_locked_state_refuses_to_seal_or_open() {
+        let encryption = Encryption::new(true);
+        assert!(matches!(encryption.seal("x".to_string()), Err(BearLlmAiError::Locked)));
+        let (key, _) = test_key("correct horse");
+        encryption.unlock(key);
+        let sealed = encryption.seal("x".to_string()).unwrap();
+        assert_eq!(encryption.open(sealed.clone()).unwrap(), "x");
+        encryption.lock();
+        assert!(matches!(encryption.open(sealed), Err(BearLlmAiError::Locked)));
+        assert_eq!(Encryption::default().seal("x".to_string()).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_project_values_need_the_project_key() {
+        let encryption = Encryption::default();
+        encryption.register_project(3);
+        assert!(matches!(
+            encryption.seal_in(Some(3), "x".to_string()),
+            Err(BearLlmAiError::ProjectLocked(3))
+        ));
+        let (key, _) = test_key("project passphrase");
+        encryption.unlock_project(3, key);
+        let sealed = encryption.seal_in(Some(3), "matter".to_string()).unwrap();
+        assert!(sealed.starts_with("enc:p3:"));
+        assert!(is_sealed(&sealed));
+        // projects without a passphrase use the app key, here none at all
+        assert_eq!(encryption.seal_in(Some(4), "x".to_string()).unwrap(), "x");
+        assert!(encryption.seals_in(Some(3)));
+        assert!(!encryption.seals_in(Some(4)));
+        assert!(!encryption.seals_in(None));
+        assert!(Encryption::new(true).seals_in(None));
+        assert_eq!(encryption.open(sealed.clone()).unwrap(), "matter");
+        assert_eq!(encryption.locked_projects(), Vec::<i32>::new());
+
+        encryption.lock();
+        assert!(encryption.is_project_locked(3));
+        assert!(matches!(encryption.open(sealed), Err(BearLlmAiError::ProjectLocked(3))));
+        assert_eq!(encryption.open("plain".to_string()).unwrap(), "plain");
+    }
+}
@@ -0,0 +1,199 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Erasure of everything the app keeps on this machine (GDPR Art. 17).
+//!
+//! Files are overwritten with zeros and synced before they are unlinked.
+//! SSDs and copy-on-write file systems may keep the old blocks anyway, which
+//! is why encrypting the history is still worth it. Every path is checked
+//! afterwards, so the report says what is really gone.
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+/// Written next to a WebView2 folder that was in use during erasure; the
+/// folder is removed at the next start, before the webview opens it
+pub const ERASE_PENDING_MARKER: &str = "erase-pending";
+const OVERWRITE_CHUNK: usize = 64 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeftBehind {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasureReport {
+    /// Paths that existed and are gone now
+    pub removed: Vec<PathBuf>,
+    pub left_behind: Vec<LeftBehind>,
+    /// Paths that did not exist to begin with
+    pub absent: Vec<PathBuf>,
+    pub database_recreated: bool,
+    /// The running app still holds the closed connection, so it has to
+    /// restart to open the fresh database
+    pub restart_required: bool,
+}
+
+impl ErasureReport {
+    /// Erase a file or a directory tree and record the outcome
+    pub fn erase(&mut self, path: &Path) {
+        if fs::symlink_metadata(path).is_err() {
+            self.absent.push(path.to_path_buf());
+            return;
+        }
+        let result = if path.is_dir() { erase_dir(path) } else { erase_file(path) };
+        // trust the file system rather than the result: a directory that was
+        // only partly removed is still left behind
+        if fs::symlink_metadata(path).is_err() {
+            self.removed.push(path.to_path_buf());
+        } else {
+            let reason = match result {
+                Err(err) => err.to_string(),
+                Ok(()) => "Still present after removal".to_string(),
+            };
+            self.left_behind.push(LeftBehind {
+                path: path.to_path_buf(),
+                reason,
+            });
+        }
+    }
+
+    /// Mark `path` for removal at the next start if it was left behind
+    pub fn schedule_pending(&mut self, path: &Path) {
+        let Some(entry) = self.left_behind.iter_mut().find(|e| e.path == path) else {
+            return;
+        };
+        let Some(parent) = path.parent() else {
+            return;
+        };
+        if fs::write(parent.join(ERASE_PENDING_MARKER), path.to_string_lossy().as_bytes()).is_ok() {
+            entry.reason = format!("{}; it will be removed at the next start", entry.reason);
+        }
+    }
+}
+
+/// Remove the WebView2 folder left behind by an earlier erasure. Must run
+/// before the webview is created.
+pub fn remove_pending(webview_dir: &Path) -> io::Result<bool> {
+    let Some(parent) = webview_dir.parent() else {
+        return Ok(false);
+    };
+    let marker = parent.join(ERASE_PENDING_MARKER);
+    if !marker.exists() {
+        return Ok(false);
+    }
+    if webview_dir.exists() {
+        erase_dir(webview_dir)?;
+    }
+    fs::remove_file(marker)?;
+    Ok(true)
+}
+
+fn overwrite(path: &Path) -> io::Result<()> {
+    let mut left = fs::metadata(path)?.len();
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; OVERWRITE_CHUNK];
+    while left > 0 {
+        let n = left.min(OVERWRITE_CHUNK as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        left -= n as u64;
+    }
+    file.sync_all()
+}
+
+/// Overwrite and unlink one file, e.g. a plaintext copy that is no longer
+/// needed
+pub fn erase_file(path: &Path) -> io::Result<()> {
+    overwrite(path)?;
+    fs::remove_file(path)
+}
+
+/// Erase every file below `path`, carrying on past files that fail, then
+/// remove the tree. Symlinks are removed, not followed.
+fn erase_dir(path: &Path) -> io::Result<()> {
+    let mut first_error = None;
+    for entry in fs::read_dir(path)? {
+        let result = entry.and_then(|entry| {
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                erase_dir(&entry.path())
+            } else if file_type.is_file() {
+                erase_file(&entry.path())
+            } else {
+                fs::remove_file(entry.path())
+            }
+        });
+        if let Err(err) = result {
+            first_error.get_or_insert(err);
+        }
+    }
+    match (fs::remove_dir_all(path), first_error) {
+        (Ok(()), _) => Ok(()),
+        (Err(_), Some(err)) | (Err(err), None) => Err(err),
+    }
+}
+
+/// Folder under `%LOCALAPPDATA%` holding `preinit.log` and the WebView2
+/// data, see `main.rs`
+pub fn local_app_dir() -> Option<PathBuf> {
+    std::env::var("LOCALAPPDATA")
+        .ok()
+        .map(|dir| Path::new(&dir).join("BEAR LLM AI"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bear-llm-ai-erasure-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_erase_reports_removed_and_absent_paths() {
+        let dir = scratch("files");
+        let db = dir.join("bear-llm-ai.db");
+        fs::write(&db, vec![7u8; OVERWRITE_CHUNK + 10]).unwrap();
+        let nested = dir.join("WebView2").join("Default");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("Cookies"), b"session").unwrap();
+
+        let mut report = ErasureReport::default();
+        report.erase(&db);
+        report.erase(&dir.join("bear-llm-ai.db-wal"));
+        report.erase(&dir.join("WebView2"));
+        assert_eq!(report.removed, vec![db, dir.join("WebView2")]);
+        assert_eq!(report.absent, vec![dir.join("bear-llm-ai.db-wal")]);
+        assert!(report.left_behind.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_pending_folder_is_removed_at_next_start() {
+        let dir = scratch("pending");
+        let webview = dir.join("WebView2");
+        fs::create_dir_all(&webview).unwrap();
+        fs::write(webview.join("Local State"), b"{}").unwrap();
+        assert!(!remove_pending(&webview).unwrap());
+
+        let mut report = ErasureReport::default();
+        report.left_behind.push(LeftBehind {
+            path: webview.clone(),
+            reason: "in use".to_string(),
+        });
+        report.schedule_pending(&webview);
+        assert!(report.left_behind[0].reason.ends_with("removed at the next start"));
+        assert!(remove_pending(&webview).unwrap());
+        assert!(!webview.exists());
+        assert!(!dir.join(ERASE_PENDING_MARKER).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
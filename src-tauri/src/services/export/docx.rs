@@ -0,0 +1,259 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! A minimal WordprocessingML package: one document part with direct
+//! formatting, so no styles part is needed, and a footer part for the notice.
+use std::io::{Cursor, Write};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use super::{message_meta, role_title, timestamp, ExportDocument};
+use crate::errors::BearLlmAiError;
+
+const W_NS: &str = "http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+const R_NS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n";
+const FOOTER_ID: &str = "rIdFooter";
+
+#[derive(Clone, Copy, Default)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    /// Font size in half-points
+    size: Option<u32>,
+    color: Option<&'static str>,
+}
+
+const TITLE: Format = Format {
+    bold: true,
+    italic: false,
+    size: Some(32),
+    color: None,
+};
+const HEADING: Format = Format {
+    bold: true,
+    italic: false,
+    size: Some(24),
+    color: None,
+};
+const BODY: Format = Format {
+    bold: false,
+    italic: false,
+    size: None,
+    color: None,
+};
+const REASONING: Format = Format {
+    bold: false,
+    italic: true,
+    size: None,
+    color: Some("656D76"),
+};
+const META: Format = Format {
+    bold: false,
+    italic: true,
+    size: Some(18),
+    color: Some("656D76"),
+};
+
+/// Escape for XML and drop the control characters XML 1.0 does not allow
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push(c),
+            c if c < ' ' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// One paragraph per line of `text`
+fn paragraphs(out: &mut String, text: &str, format: Format) {
+    let mut properties = String::new();
+    if format.bold {
+        properties.push_str("<w:b/>");
+    }
+    if format.italic {
+        properties.push_str("<w:i/>");
+    }
+    if let Some(color) = format.color {
+        properties.push_str(&format!("<w:color w:val=\"{}\"/>", color));
+    }
+    if let Some(size) = format.size {
+        properties.push_str(&format!("<w:sz w:val=\"{}\"/>", size));
+    }
+    for line in text.trim_end().split('\n') {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            out.push_str("<w:p/>");
+            continue;
+        }
+        out.push_str(&format!(
+            "<w:p><w:r><w:rPr>{}</w:rPr><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+            properties,
+            escape(line)
+        ));
+    }
+}
+
+fn document_xml(document: &ExportDocument) -> String {
+    let mut body = String::new();
+    paragraphs(&mut body, &document.conversation.name, TITLE);
+    if document.redacted {
+        paragraphs(&mut body, "Personal data in this export has been replaced by placeholders.", META);
+    }
+    if let Some(system_message) = &document.conversation.system_message {
+        paragraphs(&mut body, "System", HEADING);
+        paragraphs(&mut body, system_message, BODY);
+    }
+    for message in &document.messages {
+        let heading = format!("{} · {}", role_title(&message.role), timestamp(&message.created_at));
+        paragraphs(&mut body, &heading, HEADING);
+        if let Some(reasoning) = &message.reasoning {
+            paragraphs(&mut body, "Reasoning", Format { bold: true, ..REASONING });
+            paragraphs(&mut body, reasoning, REASONING);
+        }
+        paragraphs(&mut body, &message.content, BODY);
+        for line in message_meta(message) {
+            paragraphs(&mut body, &line, META);
+        }
+    }
+    let footer_reference = match document.notice {
+        Some(_) => format!("<w:footerReference w:type=\"default\" r:id=\"{}\"/>", FOOTER_ID),
+        None => String::new(),
+    };
+    format!(
+        "{}<w:document xmlns:w=\"{}\" xmlns:r=\"{}\"><w:body>{}<w:sectPr>{}\
+         <w:pgSz w:w=\"11906\" w:h=\"16838\"/>\
+         <w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"708\" w:footer=\"708\" w:gutter=\"0\"/>\
+         </w:sectPr></w:body></w:document>",
+        XML_DECLARATION, W_NS, R_NS, body, footer_reference
+    )
+}
+
+fn footer_xml(notice: &str) -> String {
+    let mut body = String::new();
+    paragraphs(&mut body, notice, META);
+    format!("{}<w:ftr xmlns:w=\"{}\" xmlns:r=\"{}\">{}</w:ftr>", XML_DECLARATION, W_NS, R_NS, body)
+}
+
+fn content_types(with_footer: bool) -> String {
+    let footer = if with_footer {
+        "<Override PartName=\"/word/footer1.xml\" \
+         ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.footer+xml\"/>"
+    } else {
+        ""
+    };
+    format!(
+        "{}<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+         <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+         <Default Extension=\"xml\" ContentType=\"application/xml\"/>\
+         <Override PartName=\"/word/document.xml\" \
+         ContentType=\"application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml\"/>\
+         <Override PartName=\"/docProps/core.xml\" \
+         ContentType=\"application/vnd.openxmlformats-package.core-properties+xml\"/>{}</Types>",
+        XML_DECLARATION, footer
+    )
+}
+
+fn package_rels() -> String {
+    format!(
+        "{}<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">\
+         <Relationship Id=\"rId1\" Type=\"{}/officeDocument\" Target=\"word/document.xml\"/>\
+         <Relationship Id=\"rId2\" \
+         Type=\"http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties\" \
+         Target=\"docProps/core.xml\"/></Relationships>",
+        XML_DECLARATION, R_NS
+    )
+}
+
+fn document_rels(with_footer: bool) -> String {
+    let footer = if with_footer {
+        format!("<Relationship Id=\"{}\" Type=\"{}/footer\" Target=\"footer1.xml\"/>", FOOTER_ID, R_NS)
+    } else {
+        String::new()
+    };
+    format!(
+        "{}<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">{}</Relationships>",
+        XML_DECLARATION, footer
+    )
+}
+
+fn core_properties(document: &ExportDocument) -> String {
+    format!(
+        "{}<cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:dcterms=\"http://purl.org/dc/terms/\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\
+         <dc:title>{}</dc:title><dc:creator>BEAR LLM AI {}</dc:creator>\
+         <dcterms:created xsi:type=\"dcterms:W3CDTF\">{}</dcterms:created></cp:coreProperties>",
+        XML_DECLARATION,
+        escape(&document.conversation.name),
+        escape(&document.app_version),
+        document.exported_at.format("%Y-%m-%dT%H:%M:%SZ")
+    )
+}
+
+/// Paths and contents of the package parts
+fn parts(document: &ExportDocument) -> Vec<(&'static str, String)> {
+    let with_footer = document.notice.is_some();
+    let mut parts = vec![
+        ("[Content_Types].xml", content_types(with_footer)),
+        ("_rels/.rels", package_rels()),
+        ("docProps/core.xml", core_properties(document)),
+        ("word/document.xml", document_xml(document)),
+        ("word/_rels/document.xml.rels", document_rels(with_footer)),
+    ];
+    if let Some(notice) = &document.notice {
+        parts.push(("word/footer1.xml", footer_xml(notice)));
+    }
+    parts
+}
+
+fn package(parts: Vec<(&'static str, String)>) -> zip::result::ZipResult<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, content) in parts {
+        zip.start_file(path, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+pub fn render(document: &ExportDocument) -> Result<Vec<u8>, BearLlmAiError> {
+    package(parts(document)).map_err(|err| BearLlmAiError::Io(err.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::export::tests::sample;
+    use entity::entities::conversations::ExportOptions;
+
+    #[test]
+    fn test_document_part_escapes_and_splits_lines() {
+        let xml = document_xml(&sample(&ExportOptions::default()));
+        assert!(xml.contains("<w:t xml:space=\"preserve\">Lease &lt;review&gt;</w:t>"));
+        assert!(xml.contains(">Clause 4 &amp; 5 look fine.</w:t></w:r></w:p><w:p><w:r>"));
+        assert!(!xml.contains("footerReference"));
+        assert_eq!(escape("a\u{1b}b\tc"), "ab\tc");
+    }
+
+    #[test]
+    fn test_footer_only_with_notice() {
+        let plain: Vec<&str> = parts(&sample(&ExportOptions::default())).iter().map(|(path, _)| *path).collect();
+        assert!(!plain.contains(&"word/footer1.xml"));
+
+        let labelled = parts(&sample(&ExportOptions {
+            include_system_message: false,
+            redact: false,
+            ai_label: true,
+        }));
+        let footer = labelled.iter().find(|(path, _)| *path == "word/footer1.xml").unwrap();
+        assert!(footer.1.contains("AI-generated content"));
+        let document = labelled.iter().find(|(path, _)| *path == "word/document.xml").unwrap();
+        assert!(document.1.contains(&format!("r:id=\"{}\"", FOOTER_ID)));
+    }
+}
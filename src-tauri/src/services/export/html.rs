@@ -0,0 +1,98 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! One self-contained file: styles are inline and nothing is loaded from
+//! elsewhere. Content is shown as written, with line breaks kept.
+use super::{message_meta, role_title, timestamp, ExportDocument};
+
+const STYLE: &str = "body{font-family:system-ui,-apple-system,'Segoe UI',sans-serif;max-width:48rem;\
+margin:2rem auto;padding:0 1rem;color:#1f2328;line-height:1.5}\
+h1{font-size:1.6rem}\
+section{border-top:1px solid #d0d7de;padding:.75rem 0}\
+h2{font-size:1rem;margin:0 0 .5rem}\
+h2 time{font-weight:normal;color:#656d76;margin-left:.5rem}\
+.content,.reasoning{white-space:pre-wrap;overflow-wrap:anywhere}\
+.reasoning{color:#656d76;border-left:3px solid #d0d7de;padding-left:.75rem}\
+.system{background:#f6f8fa;padding:.75rem}\
+.meta{font-size:.8rem;color:#656d76;margin:.5rem 0 0}\
+footer{border-top:1px solid #d0d7de;margin-top:1rem;padding-top:.75rem;font-size:.85rem;color:#656d76}";
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn render(document: &ExportDocument) -> String {
+    let name = escape(&document.conversation.name);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"generator\" content=\"BEAR LLM AI {}\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape(&document.app_version),
+        name,
+        STYLE,
+        name
+    );
+    if document.redacted {
+        out.push_str("<p class=\"meta\">Personal data in this export has been replaced by placeholders.</p>\n");
+    }
+    if let Some(system_message) = &document.conversation.system_message {
+        out.push_str(&format!(
+            "<section><h2>System</h2><div class=\"content system\">{}</div></section>\n",
+            escape(system_message)
+        ));
+    }
+    for message in &document.messages {
+        out.push_str(&format!(
+            "<section class=\"{}\"><h2>{}<time datetime=\"{}\">{}</time></h2>",
+            escape(&message.role),
+            escape(&role_title(&message.role)),
+            message.created_at.format("%Y-%m-%dT%H:%M:%S"),
+            timestamp(&message.created_at)
+        ));
+        if let Some(reasoning) = &message.reasoning {
+            out.push_str(&format!(
+                "<details><summary>Reasoning</summary><div class=\"reasoning\">{}</div></details>",
+                escape(reasoning)
+            ));
+        }
+        out.push_str(&format!("<div class=\"content\">{}</div>", escape(message.content.trim_end())));
+        for line in message_meta(message) {
+            out.push_str(&format!("<p class=\"meta\">{}</p>", escape(&line)));
+        }
+        out.push_str("</section>\n");
+    }
+    if let Some(notice) = &document.notice {
+        out.push_str(&format!("<footer>{}</footer>\n", escape(notice)));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::export::tests::sample;
+    use entity::entities::conversations::ExportOptions;
+
+    #[test]
+    fn test_escapes_and_stays_self_contained() {
+        let html = render(&sample(&ExportOptions {
+            include_system_message: false,
+            redact: false,
+            ai_label: true,
+        }));
+        assert!(html.contains("<title>Lease &lt;review&gt;</title>"));
+        assert!(html.contains("<div class=\"content\">Clause 4 &amp; 5 look fine.\nCheck the deposit.</div>"));
+        assert!(html.contains("<footer>This document contains AI-generated content."));
+        assert!(!html.contains("System</h2>"));
+        assert!(!html.contains("src=") && !html.contains("href="));
+    }
+}
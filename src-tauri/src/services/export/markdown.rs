@@ -0,0 +1,63 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Message content is Markdown already and goes in as written.
+use super::{message_meta, role_title, timestamp, ExportDocument};
+
+fn quote(text: &str) -> String {
+    text.lines()
+        .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn render(document: &ExportDocument) -> String {
+    let mut out = format!("# {}\n\n", document.conversation.name);
+    if document.redacted {
+        out.push_str("_Personal data in this export has been replaced by placeholders._\n\n");
+    }
+    if let Some(system_message) = &document.conversation.system_message {
+        out.push_str(&format!("## System\n\n{}\n\n", system_message));
+    }
+    for message in &document.messages {
+        out.push_str(&format!(
+            "## {} · {}\n\n",
+            role_title(&message.role),
+            timestamp(&message.created_at)
+        ));
+        if let Some(reasoning) = &message.reasoning {
+            out.push_str(&format!("> **Reasoning**\n>\n{}\n\n", quote(reasoning)));
+        }
+        out.push_str(message.content.trim_end());
+        out.push_str("\n\n");
+        let meta = message_meta(message);
+        for line in &meta {
+            out.push_str(&format!("_{}_  \n", line));
+        }
+        if !meta.is_empty() {
+            out.push('\n');
+        }
+    }
+    if let Some(notice) = &document.notice {
+        out.push_str(&format!("---\n\n_{}_\n", notice));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::export::tests::sample;
+    use entity::entities::conversations::ExportOptions;
+
+    #[test]
+    fn test_renders_messages_reasoning_and_notice() {
+        let markdown = render(&sample(&ExportOptions {
+            include_system_message: true,
+            redact: false,
+            ai_label: true,
+        }));
+        assert!(markdown.starts_with("# Lease <review>\n\n## System\n\nYou are a careful lawyer.\n\n"));
+        assert!(markdown.contains("## Assistant · 2026-10-17 09:01\n\n> **Reasoning**\n>\n> The tenant asked about clauses.\n\n"));
+        assert!(markdown.contains("_Tokens: 12 prompt, 80 completion_  \n"));
+        assert!(markdown.ends_with("Exported from BEAR LLM AI 0.0.20 on 2026-10-17._\n"));
+    }
+}
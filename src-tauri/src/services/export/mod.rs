@@ -0,0 +1,356 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Conversation export. A conversation's active branch is first turned into
+//! an `ExportDocument`, optionally redacted, and then rendered to one of the
+//! `ExportFormat`s. The JSON rendering is the document itself and is the
+//! format `import` reads back; fields may be added to it, but a change an
+//! older reader would misread needs a new `SCHEMA_VERSION`.
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::BearLlmAiError, services::pii::{PiiDetectors, Pseudonyms}};
+use entity::entities::{
+    conversations::{self, ExportFormat, ExportOptions},
+    messages::{self, Provenance},
+};
+
+mod docx;
+mod html;
+mod markdown;
+
+/// Identifies the JSON export
+pub const SCHEMA: &str = "bear-llm-ai/conversation";
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportDocument {
+    pub schema: String,
+    pub version: u32,
+    pub exported_at: NaiveDateTime,
+    pub app_version: String,
+    /// Personal data was masked with placeholders
+    #[serde(default)]
+    pub redacted: bool,
+    pub conversation: ExportedConversation,
+    pub messages: Vec<ExportedMessage>,
+    /// Statement that the document contains AI-generated content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notice: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedConversation {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_message: Option<String>,
+    /// `GenericOptions::options`, JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<String>,
+    pub last_message_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    pub created_at: NaiveDateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<ExportedTokens>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// "Generated by ..." line for an AI-generated reply, when asked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ai_label: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedTokens {
+    pub prompt: Option<i32>,
+    pub completion: Option<i32>,
+    pub reasoning: Option<i32>,
+}
+
+fn tokens(message: &messages::Model) -> Option<ExportedTokens> {
+    let tokens = ExportedTokens {
+        prompt: message.prompt_token,
+        completion: message.completion_token,
+        reasoning: message.reasoning_token,
+    };
+    (tokens.prompt.is_some() || tokens.completion.is_some() || tokens.reasoning.is_some()).then_some(tokens)
+}
+
+fn provenance(message: &messages::Model) -> Option<Provenance> {
+    let provenance = Provenance {
+        model_id: message.model_id,
+        provider: message.provider.clone(),
+        model_name: message.model_name.clone(),
+        generation_options: message.generation_options.clone(),
+        redaction: message.redaction.clone(),
+        app_version: message.app_version.clone(),
+    };
+    (provenance != Provenance::default()).then_some(provenance)
+}
+
+/// e.g. "Generated by AI model llama3 (ollama) on 2026-10-17"
+fn ai_label(message: &messages::Model) -> Option<String> {
+    if message.role != "assistant" {
+        return None;
+    }
+    let model = match (&message.model_name, &message.provider) {
+        (Some(name), Some(provider)) => format!("AI model {} ({})", name, provider),
+        (Some(name), None) => format!("AI model {}", name),
+        _ => "an AI model".to_string(),
+    };
+    Some(format!("Generated by {} on {}", model, message.created_at.format("%Y-%m-%d")))
+}
+
+fn notice(app_version: &str, exported_at: &NaiveDateTime) -> String {
+    format!(
+        "This document contains AI-generated content. Exported from BEAR LLM AI {} on {}.",
+        app_version,
+        exported_at.format("%Y-%m-%d")
+    )
+}
+
+/// The conversation and its active branch, ready to render
+pub fn document(
+    conversation: conversations::Model,
+    messages: Vec<messages::Model>,
+    options: &ExportOptions,
+    app_version: &str,
+    exported_at: NaiveDateTime,
+) -> ExportDocument {
+    let messages = messages
+        .into_iter()
+        .map(|message| ExportedMessage {
+            tokens: tokens(&message),
+            provenance: provenance(&message),
+            ai_label: if options.ai_label { ai_label(&message) } else { None },
+            role: message.role,
+            content: message.content,
+            reasoning: message.reasoning.filter(|reasoning| !reasoning.is_empty()),
+            created_at: message.created_at,
+        })
+        .collect();
+    ExportDocument {
+        schema: SCHEMA.to_string(),
+        version: SCHEMA_VERSION,
+        exported_at,
+        app_version: app_version.to_string(),
+        redacted: false,
+        conversation: ExportedConversation {
+            name: conversation.name,
+            system_message: conversation
+                .system_message
+                .filter(|system_message| options.include_system_message && !system_message.is_empty()),
+            options: Some(conversation.options),
+            last_message_at: conversation.last_message_at,
+        },
+        messages,
+        notice: options.ai_label.then(|| notice(app_version, &exported_at)),
+    }
+}
+
+/// Mask personal data in every text of `document`, with one set of
+/// placeholders for the whole document
+pub async fn redact(document: &mut ExportDocument, detectors: &PiiDetectors) -> Result<(), String> {
+    let mut pseudonyms = Pseudonyms::default();
+    async fn mask(text: &mut String, detectors: &PiiDetectors, pseudonyms: &mut Pseudonyms) -> Result<(), String> {
+        let spans = detectors.detect(text).await?;
+        *text = pseudonyms.mask(text, &spans);
+        Ok(())
+    }
+    mask(&mut document.conversation.name, detectors, &mut pseudonyms).await?;
+    if let Some(system_message) = &mut document.conversation.system_message {
+        mask(system_message, detectors, &mut pseudonyms).await?;
+    }
+    for message in &mut document.messages {
+        mask(&mut message.content, detectors, &mut pseudonyms).await?;
+        if let Some(reasoning) = &mut message.reasoning {
+            mask(reasoning, detectors, &mut pseudonyms).await?;
+        }
+    }
+    document.redacted = true;
+    Ok(())
+}
+
+pub fn render(document: &ExportDocument, format: ExportFormat) -> Result<Vec<u8>, BearLlmAiError> {
+    let res = match format {
+        ExportFormat::Markdown => markdown::render(document).into_bytes(),
+        ExportFormat::Json => serde_json::to_vec_pretty(document)
+            .map_err(|err| sea_orm::DbErr::Json(err.to_string()))?,
+        ExportFormat::Html => html::render(document).into_bytes(),
+        ExportFormat::Docx => docx::render(document)?,
+    };
+    Ok(res)
+}
+
+// Shared by the text renderers
+fn role_title(role: &str) -> String {
+    match role {
+        "user" => "User".to_string(),
+        "assistant" => "Assistant".to_string(),
+        "system" => "System".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn timestamp(time: &NaiveDateTime) -> String {
+    time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// e.g. "Tokens: 12 prompt, 80 completion"
+fn token_summary(tokens: &ExportedTokens) -> String {
+    let parts: Vec<String> = [
+        (tokens.prompt, "prompt"),
+        (tokens.completion, "completion"),
+        (tokens.reasoning, "reasoning"),
+    ]
+    .into_iter()
+    .filter_map(|(count, kind)| count.map(|count| format!("{} {}", count, kind)))
+    .collect();
+    format!("Tokens: {}", parts.join(", "))
+}
+
+/// e.g. "Model: llama3 (ollama), BEAR LLM AI 0.0.20, redacted"
+fn provenance_summary(provenance: &Provenance) -> String {
+    let mut parts = Vec::new();
+    match (&provenance.model_name, &provenance.provider) {
+        (Some(name), Some(provider)) => parts.push(format!("{} ({})", name, provider)),
+        (Some(name), None) => parts.push(name.clone()),
+        (None, Some(provider)) => parts.push(provider.clone()),
+        (None, None) => {}
+    }
+    if let Some(app_version) = &provenance.app_version {
+        parts.push(format!("BEAR LLM AI {}", app_version));
+    }
+    if provenance.redaction.is_some() {
+        parts.push("redacted".to_string());
+    }
+    format!("Model: {}", parts.join(", "))
+}
+
+/// Small print under a message: label, token counts and provenance
+fn message_meta(message: &ExportedMessage) -> Vec<String> {
+    let mut meta = Vec::new();
+    if let Some(label) = &message.ai_label {
+        meta.push(label.clone());
+    }
+    if let Some(tokens) = &message.tokens {
+        meta.push(token_summary(tokens));
+    }
+    if let Some(provenance) = &message.provenance {
+        meta.push(provenance_summary(provenance));
+    }
+    meta
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2026, 10, 17)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn stored(role: &str, content: &str) -> messages::Model {
+        messages::Model {
+            id: 1,
+            conversation_id: 1,
+            role: role.to_string(),
+            content: content.to_string(),
+            created_at: at(9, 0),
+            prompt_token: None,
+            completion_token: None,
+            reasoning_token: None,
+            reasoning: None,
+            parent_id: None,
+            model_id: None,
+            provider: None,
+            model_name: None,
+            generation_options: None,
+            redaction: None,
+            app_version: None,
+        }
+    }
+
+    /// A short exchange with a generated, reasoned reply
+    pub(crate) fn sample(options: &ExportOptions) -> ExportDocument {
+        let conversation = conversations::Model {
+            id: 1,
+            name: "Lease <review>".to_string(),
+            model_id: 1,
+            system_message: Some("You are a careful lawyer.".to_string()),
+            options: "{}".to_string(),
+            last_message_at: at(9, 1),
+            active_message_id: None,
+            retention_days: None,
+            pinned: false,
+            legal_hold: false,
+            project_id: None,
+        };
+        let mut reply = stored("assistant", "Clause 4 & 5 look fine.\nCheck the deposit.");
+        reply.created_at = at(9, 1);
+        reply.reasoning = Some("The tenant asked about clauses.".to_string());
+        reply.prompt_token = Some(12);
+        reply.completion_token = Some(80);
+        reply.model_id = Some(1);
+        reply.provider = Some("ollama".to_string());
+        reply.model_name = Some("llama3".to_string());
+        reply.app_version = Some("0.0.20".to_string());
+        let messages = vec![stored("user", "Mail jan@example.nl about the lease"), reply];
+        document(conversation, messages, options, "0.0.20", at(10, 0))
+    }
+
+    #[test]
+    fn test_document_follows_options() {
+        let plain = sample(&ExportOptions::default());
+        assert_eq!(plain.conversation.system_message, None);
+        assert_eq!(plain.notice, None);
+        assert!(plain.messages.iter().all(|m| m.ai_label.is_none()));
+        assert_eq!(plain.messages[0].provenance, None);
+        assert_eq!(plain.messages[1].provenance.as_ref().unwrap().model_name.as_deref(), Some("llama3"));
+
+        let labelled = sample(&ExportOptions {
+            include_system_message: true,
+            redact: false,
+            ai_label: true,
+        });
+        assert_eq!(labelled.conversation.system_message.as_deref(), Some("You are a careful lawyer."));
+        assert_eq!(labelled.messages[0].ai_label, None);
+        assert_eq!(
+            labelled.messages[1].ai_label.as_deref(),
+            Some("Generated by AI model llama3 (ollama) on 2026-10-17")
+        );
+        assert!(labelled.notice.unwrap().contains("AI-generated"));
+        assert_eq!(
+            message_meta(&labelled.messages[1])[1..],
+            ["Tokens: 12 prompt, 80 completion", "Model: llama3 (ollama), BEAR LLM AI 0.0.20"]
+        );
+    }
+
+    #[test]
+    fn test_json_round_trips() {
+        let document = sample(&ExportOptions::default());
+        let json = render(&document, ExportFormat::Json).unwrap();
+        let read: ExportDocument = serde_json::from_slice(&json).unwrap();
+        assert_eq!(read, document);
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value["schema"], SCHEMA);
+        assert_eq!(value["messages"][1]["tokens"]["completion"], 80);
+        assert!(value["messages"][0].get("reasoning").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redact_masks_every_text() {
+        let mut document = sample(&ExportOptions::default());
+        let detectors = PiiDetectors::new(&Default::default()).unwrap();
+        redact(&mut document, &detectors).await.unwrap();
+        assert!(document.redacted);
+        assert_eq!(document.messages[0].content, "Mail [EMAIL_1] about the lease");
+    }
+}
@@ -0,0 +1,239 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! `conversations.json` from a ChatGPT data export. Each conversation is a
+//! tree of nodes keyed by id; the branch the user last saw ends at
+//! `current_node` and is followed up through `parent` to the root.
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{from_unix, ImportedConversation, Skipped, UNTITLED};
+use crate::services::export::{ExportedConversation, ExportedMessage};
+use entity::entities::messages::Provenance;
+
+pub const PROVIDER: &str = "chatgpt";
+
+#[derive(Deserialize)]
+struct Conversation {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    update_time: Option<f64>,
+    #[serde(default)]
+    mapping: HashMap<String, Node>,
+    #[serde(default)]
+    current_node: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    #[serde(default)]
+    message: Option<Message>,
+    #[serde(default)]
+    parent: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    author: Author,
+    content: Content,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    role: String,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    content_type: String,
+    #[serde(default)]
+    parts: Vec<Value>,
+}
+
+#[derive(Default, Deserialize)]
+struct Metadata {
+    #[serde(default)]
+    model_slug: Option<String>,
+    #[serde(default)]
+    is_visually_hidden_from_conversation: Option<bool>,
+}
+
+pub fn parse(data: &[u8], skipped: &mut Skipped) -> Result<Vec<ImportedConversation>, String> {
+    let conversations: Vec<Value> =
+        serde_json::from_slice(data).map_err(|err| format!("Not a ChatGPT conversations.json: {}", err))?;
+    let mut res = Vec::with_capacity(conversations.len());
+    for value in conversations {
+        match serde_json::from_value::<Conversation>(value) {
+            Ok(conversation) => res.push(conversation_of(conversation, skipped)),
+            Err(_) => skipped.add(None, "Unreadable conversation"),
+        }
+    }
+    Ok(res)
+}
+
+/// Messages on the branch ending at `current_node`, root first
+fn branch(conversation: &Conversation) -> Vec<&Message> {
+    let mut messages = Vec::new();
+    let mut next = conversation.current_node.as_deref();
+    // Bounded, so a malformed mapping with a cycle cannot loop forever
+    for _ in 0..conversation.mapping.len() {
+        let Some(node) = next.and_then(|id| conversation.mapping.get(id)) else {
+            break;
+        };
+        messages.extend(node.message.as_ref());
+        next = node.parent.as_deref();
+    }
+    messages.reverse();
+    messages
+}
+
+fn conversation_of(conversation: Conversation, skipped: &mut Skipped) -> ImportedConversation {
+    let name = conversation
+        .title
+        .clone()
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| UNTITLED.to_string());
+    let fallback_time = conversation
+        .update_time
+        .or(conversation.create_time)
+        .and_then(from_unix)
+        .unwrap_or_else(|| chrono::Utc::now().naive_utc());
+    let mut messages = Vec::new();
+    for message in branch(&conversation) {
+        if message.metadata.is_visually_hidden_from_conversation == Some(true) {
+            // Context ChatGPT adds itself, such as an empty system prompt
+            continue;
+        }
+        if message.author.role == "tool" {
+            skipped.add(Some(&name), "Tool output");
+            continue;
+        }
+        if !matches!(message.content.content_type.as_str(), "text" | "multimodal_text") {
+            skipped.add(Some(&name), format!("Content of type {}", message.content.content_type));
+            continue;
+        }
+        let mut texts = Vec::new();
+        for part in &message.content.parts {
+            match part {
+                Value::String(text) => texts.push(text.as_str()),
+                _ => skipped.add(Some(&name), "Image or file attachment"),
+            }
+        }
+        let provenance = (message.author.role == "assistant").then(|| Provenance {
+            provider: Some(PROVIDER.to_string()),
+            model_name: message.metadata.model_slug.clone(),
+            ..Default::default()
+        });
+        messages.push(ExportedMessage {
+            role: message.author.role.clone(),
+            content: texts.join("\n"),
+            reasoning: None,
+            created_at: message.create_time.and_then(from_unix).unwrap_or(fallback_time),
+            tokens: None,
+            provenance,
+            ai_label: None,
+        });
+    }
+    ImportedConversation {
+        conversation: ExportedConversation {
+            name,
+            system_message: None,
+            options: None,
+            last_message_at: fallback_time,
+        },
+        messages,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::import::{parse as import, tidy};
+    use entity::entities::conversations::ImportFormat;
+    use serde_json::json;
+
+    fn node(id: &str, parent: Option<&str>, role: &str, content: Value, time: f64) -> (String, Value) {
+        (
+            id.to_string(),
+            json!({
+                "id": id,
+                "parent": parent,
+                "message": {
+                    "author": {"role": role},
+                    "content": content,
+                    "create_time": time,
+                    "metadata": {"model_slug": "gpt-4o"}
+                }
+            }),
+        )
+    }
+
+    fn export() -> Value {
+        let mut mapping: serde_json::Map<String, Value> = [
+            node("a", None, "system", json!({"content_type": "text", "parts": [""]}), 1.0),
+            node("b", Some("a"), "user", json!({"content_type": "text", "parts": ["Draft a letter"]}), 1_792_227_600.0),
+            node("c", Some("b"), "assistant", json!({"content_type": "text", "parts": ["Dear Sir,"]}), 1_792_227_660.0),
+            node("d", Some("b"), "assistant", json!({"content_type": "text", "parts": ["Hello,"]}), 1_792_227_700.0),
+            node(
+                "e",
+                Some("d"),
+                "user",
+                json!({"content_type": "multimodal_text", "parts": [{"asset_pointer": "file-1"}, "Use this logo"]}),
+                1_792_227_800.0,
+            ),
+            node("f", Some("e"), "tool", json!({"content_type": "text", "parts": ["done"]}), 1_792_227_810.0),
+            node("g", Some("f"), "assistant", json!({"content_type": "code", "text": "print(1)"}), 1_792_227_820.0),
+        ]
+        .into_iter()
+        .collect();
+        mapping.insert("root".to_string(), json!({"id": "root", "parent": null, "message": null}));
+        mapping["a"]["parent"] = json!("root");
+        json!([
+            {"title": "Letter", "create_time": 1_792_227_600.0, "update_time": 1_792_227_820.0,
+             "mapping": mapping, "current_node": "g"},
+            {"title": "Broken", "mapping": 3}
+        ])
+    }
+
+    #[test]
+    fn test_follows_current_branch() {
+        let mut skipped = Skipped::default();
+        let conversations = parse(&serde_json::to_vec(&export()).unwrap(), &mut skipped).unwrap();
+        let conversation = tidy(conversations.into_iter().next().unwrap(), &mut skipped).unwrap();
+        let contents: Vec<&str> = conversation.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["Draft a letter", "Hello,", "Use this logo"]);
+        assert_eq!(conversation.conversation.system_message, None);
+        let provenance = conversation.messages[1].provenance.as_ref().unwrap();
+        assert_eq!(provenance.provider.as_deref(), Some(PROVIDER));
+        assert_eq!(provenance.model_name.as_deref(), Some("gpt-4o"));
+        assert_eq!(conversation.conversation.last_message_at, from_unix(1_792_227_800.0).unwrap());
+    }
+
+    #[test]
+    fn test_reports_what_it_leaves_out() {
+        let imported = import(ImportFormat::Chatgpt, &serde_json::to_vec(&export()).unwrap()).unwrap();
+        assert_eq!(imported.conversations.len(), 1);
+        let reasons: Vec<(Option<&str>, &str, usize)> = imported
+            .skipped
+            .iter()
+            .map(|s| (s.conversation.as_deref(), s.reason.as_str(), s.count))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (None, "Unreadable conversation", 1),
+                (Some("Letter"), "Content of type code", 1),
+                (Some("Letter"), "Empty message", 1),
+                (Some("Letter"), "Image or file attachment", 1),
+                (Some("Letter"), "Tool output", 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Conversation import. Every supported format is read into the same shape
+//! the JSON export uses, cleaned up the same way, and only then stored. What
+//! could not be carried over is tallied rather than failing the import.
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDateTime};
+
+use crate::services::export::{ExportDocument, ExportedConversation, ExportedMessage, SCHEMA, SCHEMA_VERSION};
+use entity::entities::conversations::{ImportFormat, SkippedImport};
+
+mod chatgpt;
+mod openwebui;
+
+pub const UNTITLED: &str = "Imported conversation";
+
+/// A conversation ready to store, oldest message first
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportedConversation {
+    pub conversation: ExportedConversation,
+    pub messages: Vec<ExportedMessage>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Imported {
+    pub conversations: Vec<ImportedConversation>,
+    pub skipped: Vec<SkippedImport>,
+}
+
+/// Counts of skipped items per conversation and reason
+#[derive(Debug, Default)]
+pub struct Skipped(BTreeMap<(Option<String>, String), usize>);
+
+impl Skipped {
+    pub fn add(&mut self, conversation: Option<&str>, reason: impl Into<String>) {
+        *self
+            .0
+            .entry((conversation.map(str::to_string), reason.into()))
+            .or_default() += 1;
+    }
+
+    pub fn into_vec(self) -> Vec<SkippedImport> {
+        self.0
+            .into_iter()
+            .map(|((conversation, reason), count)| SkippedImport {
+                conversation,
+                reason,
+                count,
+            })
+            .collect()
+    }
+}
+
+/// Unix time in seconds or milliseconds, which other clients mix freely
+pub fn from_unix(time: f64) -> Option<NaiveDateTime> {
+    let seconds = if time > 1e11 { time / 1000.0 } else { time };
+    DateTime::from_timestamp_millis((seconds * 1000.0) as i64).map(|time| time.naive_utc())
+}
+
+pub fn parse(format: ImportFormat, data: &[u8]) -> Result<Imported, String> {
+    let mut skipped = Skipped::default();
+    let conversations = match format {
+        ImportFormat::Json => vec![parse_export(data)?],
+        ImportFormat::Chatgpt => chatgpt::parse(data, &mut skipped)?,
+        ImportFormat::Openwebui => openwebui::parse(data, &mut skipped)?,
+    };
+    let conversations = conversations
+        .into_iter()
+        .filter_map(|conversation| tidy(conversation, &mut skipped))
+        .collect();
+    Ok(Imported {
+        conversations,
+        skipped: skipped.into_vec(),
+    })
+}
+
+fn parse_export(data: &[u8]) -> Result<ImportedConversation, String> {
+    let document: ExportDocument = serde_json::from_slice(data).map_err(|err| format!("Not a conversation export: {}", err))?;
+    if document.schema != SCHEMA {
+        return Err(format!("Not a conversation export: unknown schema {}", document.schema));
+    }
+    if document.version > SCHEMA_VERSION {
+        return Err(format!(
+            "The export is from a newer version of the app (schema version {}, this version reads up to {})",
+            document.version, SCHEMA_VERSION
+        ));
+    }
+    Ok(ImportedConversation {
+        conversation: document.conversation,
+        messages: document.messages,
+    })
+}
+
+/// Keep the messages the app can show and send: a leading system message
+/// becomes the system message, empty messages and unknown roles are dropped,
+/// and a conversation left without messages is skipped as a whole
+fn tidy(mut imported: ImportedConversation, skipped: &mut Skipped) -> Option<ImportedConversation> {
+    let name = imported.conversation.name.trim();
+    imported.conversation.name = if name.is_empty() { UNTITLED.to_string() } else { name.to_string() };
+    let name = imported.conversation.name.clone();
+    let mut messages = Vec::with_capacity(imported.messages.len());
+    for mut message in imported.messages {
+        if message.content.trim().is_empty() {
+            skipped.add(Some(&name), "Empty message");
+            continue;
+        }
+        match message.role.as_str() {
+            "user" | "assistant" => {
+                // Labels are added again on export when asked for
+                message.ai_label = None;
+                messages.push(message);
+            }
+            "system" if messages.is_empty() && imported.conversation.system_message.is_none() => {
+                imported.conversation.system_message = Some(message.content);
+            }
+            "system" => skipped.add(Some(&name), "System message within the conversation"),
+            role => skipped.add(Some(&name), format!("Message with role {}", role)),
+        }
+    }
+    if messages.is_empty() {
+        skipped.add(Some(&name), "Conversation without messages to import");
+        return None;
+    }
+    imported.conversation.system_message = imported
+        .conversation
+        .system_message
+        .filter(|system_message| !system_message.trim().is_empty());
+    if let Some(last) = messages.iter().map(|message| message.created_at).max() {
+        imported.conversation.last_message_at = last;
+    }
+    imported.messages = messages;
+    Some(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::export::tests::{at, sample};
+    use entity::entities::conversations::ExportOptions;
+
+    #[test]
+    fn test_reads_back_own_export() {
+        let document = sample(&ExportOptions {
+            include_system_message: true,
+            redact: false,
+            ai_label: true,
+        });
+        let data = serde_json::to_vec(&document).unwrap();
+        let imported = parse(ImportFormat::Json, &data).unwrap();
+        assert!(imported.skipped.is_empty());
+        let conversation = &imported.conversations[0];
+        assert_eq!(conversation.conversation.system_message, document.conversation.system_message);
+        assert_eq!(conversation.messages.len(), document.messages.len());
+        assert_eq!(conversation.messages[1].provenance, document.messages[1].provenance);
+        assert!(conversation.messages.iter().all(|message| message.ai_label.is_none()));
+    }
+
+    #[test]
+    fn test_refuses_newer_or_foreign_documents() {
+        let mut document = sample(&ExportOptions::default());
+        document.version = SCHEMA_VERSION + 1;
+        let err = parse(ImportFormat::Json, &serde_json::to_vec(&document).unwrap()).unwrap_err();
+        assert!(err.contains("newer version"));
+        assert!(parse(ImportFormat::Json, b"{\"mapping\": {}}").is_err());
+    }
+
+    #[test]
+    fn test_tidy_moves_system_message_and_tallies_skips() {
+        let message = |role: &str, content: &str| ExportedMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+            reasoning: None,
+            created_at: at(9, 0),
+            tokens: None,
+            provenance: None,
+            ai_label: None,
+        };
+        let imported = ImportedConversation {
+            conversation: ExportedConversation {
+                name: " ".to_string(),
+                system_message: None,
+                options: None,
+                last_message_at: at(8, 0),
+            },
+            messages: vec![
+                message("system", "Be brief."),
+                message("user", "Hi"),
+                message("tool", "{}"),
+                message("assistant", ""),
+                message("assistant", "Hello"),
+                message("assistant", " "),
+            ],
+        };
+        let mut skipped = Skipped::default();
+        let tidied = tidy(imported, &mut skipped).unwrap();
+        assert_eq!(tidied.conversation.name, UNTITLED);
+        assert_eq!(tidied.conversation.system_message.as_deref(), Some("Be brief."));
+        assert_eq!(tidied.conversation.last_message_at, at(9, 0));
+        assert_eq!(tidied.messages.len(), 2);
+        let skipped = skipped.into_vec();
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0].reason, "Empty message");
+        assert_eq!(skipped[0].count, 2);
+        assert_eq!(skipped[1].reason, "Message with role tool");
+    }
+
+    #[test]
+    fn test_from_unix_accepts_seconds_and_millis() {
+        let time = from_unix(1_792_227_600.5).unwrap();
+        assert_eq!(from_unix(1_792_227_600_500.0), Some(time));
+        assert_eq!(time.format("%Y-%m-%d %H:%M:%S%.3f").to_string(), "2026-10-17 09:00:00.500");
+    }
+}
@@ -0,0 +1,257 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Chats exported from Open WebUI, either one chat or a list of them. Newer
+//! versions keep a message tree in `history` with the shown branch ending at
+//! `currentId`; older ones only have the flat `messages` list.
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{from_unix, ImportedConversation, Skipped, UNTITLED};
+use crate::services::export::{ExportedConversation, ExportedMessage, ExportedTokens};
+use entity::entities::messages::Provenance;
+
+pub const PROVIDER: &str = "open-webui";
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Many(Vec<Value>),
+    One(Value),
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    #[serde(default)]
+    title: Option<String>,
+    chat: Body,
+    #[serde(default)]
+    updated_at: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Body {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    system: Option<String>,
+    #[serde(default)]
+    params: Params,
+    #[serde(default)]
+    history: Option<History>,
+    #[serde(default)]
+    messages: Vec<Message>,
+    #[serde(default)]
+    timestamp: Option<f64>,
+}
+
+#[derive(Default, Deserialize)]
+struct Params {
+    #[serde(default)]
+    system: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct History {
+    #[serde(default)]
+    messages: HashMap<String, Message>,
+    #[serde(default, rename = "currentId")]
+    current_id: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+struct Message {
+    #[serde(default, rename = "parentId")]
+    parent_id: Option<String>,
+    role: String,
+    #[serde(default)]
+    content: Value,
+    #[serde(default)]
+    timestamp: Option<f64>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    files: Vec<Value>,
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+#[derive(Clone, Deserialize)]
+struct Usage {
+    #[serde(default)]
+    prompt_tokens: Option<i32>,
+    #[serde(default)]
+    completion_tokens: Option<i32>,
+}
+
+pub fn parse(data: &[u8], skipped: &mut Skipped) -> Result<Vec<ImportedConversation>, String> {
+    let chats = match serde_json::from_slice(data).map_err(|err| format!("Not an Open WebUI export: {}", err))? {
+        Export::Many(chats) => chats,
+        Export::One(chat) => vec![chat],
+    };
+    let mut res = Vec::with_capacity(chats.len());
+    for value in chats {
+        match serde_json::from_value::<Chat>(value) {
+            Ok(chat) => res.push(conversation_of(chat, skipped)),
+            Err(_) => skipped.add(None, "Unreadable conversation"),
+        }
+    }
+    Ok(res)
+}
+
+/// Messages on the branch ending at `currentId`, root first, or the flat list
+/// when there is no tree
+fn branch(body: &Body) -> Vec<Message> {
+    let Some(history) = body.history.as_ref().filter(|history| !history.messages.is_empty()) else {
+        return body.messages.clone();
+    };
+    let mut messages = Vec::new();
+    let mut next = history.current_id.as_deref();
+    // Bounded, so a malformed history with a cycle cannot loop forever
+    for _ in 0..history.messages.len() {
+        let Some(message) = next.and_then(|id| history.messages.get(id)) else {
+            break;
+        };
+        messages.push(message.clone());
+        next = message.parent_id.as_deref();
+    }
+    messages.reverse();
+    messages
+}
+
+/// Text of a message; content is a string, or a list of parts in
+/// multimodal messages
+fn text(content: &Value, name: &str, skipped: &mut Skipped) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => {
+            let mut texts = Vec::new();
+            for part in parts {
+                match part.get("text").and_then(Value::as_str) {
+                    Some(text) if part.get("type").and_then(Value::as_str) == Some("text") => texts.push(text),
+                    _ => skipped.add(Some(name), "Image or file attachment"),
+                }
+            }
+            texts.join("\n")
+        }
+        _ => String::new(),
+    }
+}
+
+fn conversation_of(chat: Chat, skipped: &mut Skipped) -> ImportedConversation {
+    let name = chat
+        .title
+        .clone()
+        .or(chat.chat.title.clone())
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or_else(|| UNTITLED.to_string());
+    let fallback_time = chat
+        .updated_at
+        .or(chat.chat.timestamp)
+        .and_then(from_unix)
+        .unwrap_or_else(|| chrono::Utc::now().naive_utc());
+    let mut messages = Vec::new();
+    for message in branch(&chat.chat) {
+        for _ in &message.files {
+            skipped.add(Some(&name), "Image or file attachment");
+        }
+        let content = text(&message.content, &name, skipped);
+        let assistant = message.role == "assistant";
+        messages.push(ExportedMessage {
+            content,
+            reasoning: None,
+            created_at: message.timestamp.and_then(from_unix).unwrap_or(fallback_time),
+            tokens: message.usage.filter(|_| assistant).map(|usage| ExportedTokens {
+                prompt: usage.prompt_tokens,
+                completion: usage.completion_tokens,
+                reasoning: None,
+            }),
+            provenance: assistant.then(|| Provenance {
+                provider: Some(PROVIDER.to_string()),
+                model_name: message.model.clone(),
+                ..Default::default()
+            }),
+            ai_label: None,
+            role: message.role,
+        });
+    }
+    ImportedConversation {
+        conversation: ExportedConversation {
+            name,
+            system_message: chat.chat.system.or(chat.chat.params.system),
+            options: None,
+            last_message_at: fallback_time,
+        },
+        messages,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::import::{parse as import, tidy};
+    use entity::entities::conversations::ImportFormat;
+    use serde_json::json;
+
+    fn chat() -> Value {
+        json!({
+            "id": "c1",
+            "title": "Contract questions",
+            "updated_at": 1_792_227_900,
+            "chat": {
+                "params": {"system": "Answer in Dutch."},
+                "history": {
+                    "currentId": "m4",
+                    "messages": {
+                        "m1": {"id": "m1", "parentId": null, "role": "user", "content": "What is a lease?",
+                               "timestamp": 1_792_227_600},
+                        "m2": {"id": "m2", "parentId": "m1", "role": "assistant", "content": "A contract.",
+                               "model": "llama3:8b", "timestamp": 1_792_227_660},
+                        "m3": {"id": "m3", "parentId": "m1", "role": "assistant", "content": "Een huurcontract.",
+                               "model": "llama3:8b", "timestamp": 1_792_227_700,
+                               "usage": {"prompt_tokens": 10, "completion_tokens": 5}},
+                        "m4": {"id": "m4", "parentId": "m3", "role": "user",
+                               "content": [{"type": "text", "text": "And this one?"}, {"type": "image_url"}],
+                               "files": [{"type": "file", "name": "lease.pdf"}],
+                               "timestamp": 1_792_227_800_000u64}
+                    }
+                },
+                "messages": []
+            }
+        })
+    }
+
+    #[test]
+    fn test_follows_current_branch() {
+        let mut skipped = Skipped::default();
+        let conversations = parse(&serde_json::to_vec(&chat()).unwrap(), &mut skipped).unwrap();
+        let conversation = tidy(conversations.into_iter().next().unwrap(), &mut skipped).unwrap();
+        let contents: Vec<&str> = conversation.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["What is a lease?", "Een huurcontract.", "And this one?"]);
+        assert_eq!(conversation.conversation.system_message.as_deref(), Some("Answer in Dutch."));
+        let reply = &conversation.messages[1];
+        assert_eq!(reply.provenance.as_ref().unwrap().model_name.as_deref(), Some("llama3:8b"));
+        assert_eq!(reply.tokens.as_ref().unwrap().completion, Some(5));
+        assert_eq!(conversation.messages[2].created_at, from_unix(1_792_227_800.0).unwrap());
+        let skipped = skipped.into_vec();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].reason, "Image or file attachment");
+        assert_eq!(skipped[0].count, 2);
+    }
+
+    #[test]
+    fn test_reads_flat_list_of_chats() {
+        let flat = json!([{
+            "title": "Old chat",
+            "chat": {"messages": [
+                {"role": "user", "content": "Hi", "timestamp": 1_792_227_600},
+                {"role": "assistant", "content": "Hello", "timestamp": 1_792_227_601}
+            ]}
+        }, chat()]);
+        let imported = import(ImportFormat::Openwebui, &serde_json::to_vec(&flat).unwrap()).unwrap();
+        assert_eq!(imported.conversations.len(), 2);
+        assert_eq!(imported.conversations[0].conversation.name, "Old chat");
+        assert_eq!(imported.conversations[0].messages.len(), 2);
+        assert!(import(ImportFormat::Openwebui, b"\"text\"").unwrap().conversations.is_empty());
+    }
+}
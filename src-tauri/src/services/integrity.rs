@@ -0,0 +1,198 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Database health, checked every time the database is opened.
+//!
+//! A database that fails `PRAGMA integrity_check`, or that SQLite reports as
+//! corrupt or as no database at all, is not opened, see `Db::new`. Any other
+//! error, such as a locked file or a failed read, is not taken for damage.
+//! One that passes gets WAL and foreign keys switched on and is then
+//! cleared of rows left behind by deletes from before deleting a
+//! conversation took its messages along: messages, summaries and vault
+//! entries of conversations that no longer exist are deleted, as their
+//! owner asked, and links to deleted messages are cut.
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbErr, RuntimeErr, Statement, TransactionTrait};
+use serde::Serialize;
+
+use crate::errors::BearLlmAiError;
+
+/// Primary result codes for a damaged file and for one that is not a
+/// database; extended codes carry them in the low byte
+const SQLITE_CORRUPT: i32 = 11;
+const SQLITE_NOTADB: i32 = 26;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityReport {
+    pub journal_mode: String,
+    pub foreign_keys: bool,
+    pub orphaned_messages: u64,
+    pub orphaned_summaries: u64,
+    pub orphaned_vault_entries: u64,
+    /// Messages whose parent no longer exists, now roots of their branch
+    pub dangling_parents: u64,
+    /// Conversations whose active message no longer exists, now showing
+    /// their newest message
+    pub dangling_active_messages: u64,
+    /// What `PRAGMA foreign_key_check` still reports after the repairs
+    pub foreign_key_violations: Vec<String>,
+}
+
+impl IntegrityReport {
+    pub fn repaired(&self) -> bool {
+        self.orphaned_messages
+            + self.orphaned_summaries
+            + self.orphaned_vault_entries
+            + self.dangling_parents
+            + self.dangling_active_messages
+            > 0
+    }
+
+    /// One line for the diagnostics log
+    pub fn summary(&self) -> String {
+        format!(
+            "Database check: journal mode {}, foreign keys {}, removed {} orphaned messages, {} summaries and {} vault \
+             entries, cut {} dangling parents and {} dangling active messages, {} foreign key violations left",
+            self.journal_mode,
+            if self.foreign_keys { "on" } else { "off" },
+            self.orphaned_messages,
+            self.orphaned_summaries,
+            self.orphaned_vault_entries,
+            self.dangling_parents,
+            self.dangling_active_messages,
+            self.foreign_key_violations.len()
+        )
+    }
+}
+
+/// Whether SQLite failed `err` because the file is damaged rather than for
+/// a passing reason such as SQLITE_BUSY, an I/O error or missing permissions
+pub fn is_damage(err: &DbErr) -> bool {
+    let (DbErr::Conn(RuntimeErr::SqlxError(sqlx::Error::Database(err)))
+    | DbErr::Exec(RuntimeErr::SqlxError(sqlx::Error::Database(err)))
+    | DbErr::Query(RuntimeErr::SqlxError(sqlx::Error::Database(err)))) = err
+    else {
+        return false;
+    };
+    err.code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, SQLITE_CORRUPT | SQLITE_NOTADB))
+}
+
+/// `PRAGMA integrity_check` reports a single "ok" row for a sound database
+pub async fn integrity_errors(db: &DatabaseConnection) -> Result<Vec<String>, BearLlmAiError> {
+    let rows = db
+        .query_all(Statement::from_string(DbBackend::Sqlite, "PRAGMA integrity_check"))
+        .await?;
+    let mut errors = Vec::new();
+    for row in rows {
+        let line: String = row.try_get_by_index(0)?;
+        if line != "ok" {
+            errors.push(line);
+        }
+    }
+    Ok(errors)
+}
+
+/// Switch to WAL, which is stored in the file, and make sure foreign keys
+/// are enforced; sqlx turns them on for every connection it opens
+async fn configure(db: &DatabaseConnection) -> Result<(String, bool), BearLlmAiError> {
+    let journal_mode: String = match db
+        .query_one(Statement::from_string(DbBackend::Sqlite, "PRAGMA journal_mode = WAL"))
+        .await?
+    {
+        Some(row) => row.try_get_by_index(0)?,
+        None => String::new(),
+    };
+    let foreign_keys: i32 = match db
+        .query_one(Statement::from_string(DbBackend::Sqlite, "PRAGMA foreign_keys"))
+        .await?
+    {
+        Some(row) => row.try_get_by_index(0)?,
+        None => 0,
+    };
+    Ok((journal_mode, foreign_keys == 1))
+}
+
+/// Rows that reference a missing parent, as "table rowid -> parent"
+async fn foreign_key_violations(db: &DatabaseConnection) -> Result<Vec<String>, BearLlmAiError> {
+    let rows = db
+        .query_all(Statement::from_string(DbBackend::Sqlite, "PRAGMA foreign_key_check"))
+        .await?;
+    let mut violations = Vec::new();
+    for row in rows {
+        let table: String = row.try_get_by_index(0)?;
+        let rowid: Option<i64> = row.try_get_by_index(1)?;
+        let parent: String = row.try_get_by_index(2)?;
+        violations.push(format!(
+            "{} {} -> {}",
+            table,
+            rowid.map(|rowid| rowid.to_string()).unwrap_or_default(),
+            parent
+        ));
+    }
+    Ok(violations)
+}
+
+/// Configure the connection, repair what can safely be repaired and report
+pub async fn check_and_repair(db: &DatabaseConnection) -> Result<IntegrityReport, BearLlmAiError> {
+    let (journal_mode, foreign_keys) = configure(db).await?;
+    let txn = db.begin().await?;
+    let mut counts = Vec::new();
+    for sql in [
+        "DELETE FROM conversation_summaries WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        "DELETE FROM pii_vault WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        "DELETE FROM messages WHERE conversation_id NOT IN (SELECT id FROM conversations)",
+        "UPDATE messages SET parent_id = NULL WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM messages)",
+        "UPDATE conversations SET active_message_id = \
+         (SELECT MAX(id) FROM messages WHERE messages.conversation_id = conversations.id) \
+         WHERE active_message_id IS NOT NULL AND active_message_id NOT IN (SELECT id FROM messages)",
+    ] {
+        counts.push(txn.execute_unprepared(sql).await?.rows_affected());
+    }
+    txn.commit().await?;
+    let report = IntegrityReport {
+        journal_mode,
+        foreign_keys,
+        orphaned_summaries: counts[0],
+        orphaned_vault_entries: counts[1],
+        orphaned_messages: counts[2],
+        dangling_parents: counts[3],
+        dangling_active_messages: counts[4],
+        foreign_key_violations: foreign_key_violations(db).await?,
+    };
+    if report.orphaned_messages + report.orphaned_summaries + report.orphaned_vault_entries > 0 {
+        // don't leave the deleted text in free pages
+        db.execute_unprepared("VACUUM").await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::Database;
+
+    #[tokio::test]
+    async fn test_only_corruption_counts_as_damage() {
+        let dir = std::env::temp_dir().join(format!("bear-llm-ai-integrity-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let garbage = dir.join("garbage.db");
+        std::fs::write(&garbage, vec![b'x'; 4096]).unwrap();
+        let url = format!("sqlite:{}?mode=rw", garbage.to_string_lossy());
+        let err = match Database::connect(&url).await {
+            Err(err) => err,
+            Ok(db) => match integrity_errors(&db).await {
+                Err(BearLlmAiError::DbErr(err)) => err,
+                other => panic!("{:?}", other),
+            },
+        };
+        assert!(is_damage(&err), "{:?}", err);
+
+        // SQLITE_CANTOPEN says nothing about the file
+        let missing = format!("sqlite:{}?mode=rw", dir.join("missing").join("x.db").to_string_lossy());
+        let err = Database::connect(&missing).await.unwrap_err();
+        assert!(!is_damage(&err), "{:?}", err);
+        assert!(!is_damage(&DbErr::Custom("database is locked".to_string())));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
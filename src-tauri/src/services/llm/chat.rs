@@ -5,7 +5,7 @@ use std::pin::Pin;
 
 use crate::log_utils::warn;
 use entity::entities::{
-    conversations::{GenericOptions, OllamaOptions},
+    conversations::{GenericOptions, OllamaOptions, OpenAIOptions},
     messages::MessageDTO,
 };
 use serde::Serialize;
@@ -15,10 +15,22 @@ use super::{
     providers::ollama::{
         chat::{
             OllamaChat, OllamaChatCompletionRequest,
-            OllamaMessage,
+            OllamaChatCompletionResponse, OllamaMessage,
+            OllamaOptions as OllamaRequestOptions,
         },
         config::OllamaConfig,
     },
+    providers::openai::{
+        chat::{
+            OpenAIChat, OpenAIChatCompletionRequest,
+            OpenAIMessage, OpenAIResponseMessage,
+        },
+        config::OpenAIConfig,
+    },
+    context::ContextReport,
+    providers::types::{ChatCompletionRequestCommon, ChatCompletionStreamOptions, Usage},
+    reasoning::{split_think_tags, ThinkTagParser},
+    stream::{decode_stream, NdjsonDecoder},
     utils::sum_option,
 };
 
@@ -41,16 +53,74 @@ pub struct BotReply {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(skip_deserializing)]
     pub total_token: Option<u32>,
+    /// How the history was fitted into the context window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextReport>,
+}
+
+impl BotReply {
+    /// Fold a streamed chunk into the reply assembled so far. Text is
+    /// appended; token counts are only reported on the last chunk.
+    pub fn merge(&mut self, chunk: &BotReply) {
+        self.message.push_str(&chunk.message);
+        if let Some(reasoning) = &chunk.reasoning {
+            self.reasoning
+                .get_or_insert_with(String::new)
+                .push_str(reasoning);
+        }
+        self.prompt_token = chunk.prompt_token.or(self.prompt_token);
+        self.completion_token = chunk.completion_token.or(self.completion_token);
+        self.reasoning_token = chunk.reasoning_token.or(self.reasoning_token);
+        self.total_token = chunk.total_token.or(self.total_token);
+    }
+
+    fn from_ollama(response: OllamaChatCompletionResponse, log_tag: &str) -> Self {
+        let (message, reasoning) = match response.message {
+            Some(message) if message.role == "assistant" => {
+                (message.content, message.thinking.filter(|t| !t.is_empty()))
+            }
+            Some(_) => {
+                warn(log_tag, "Ollama returned a non-assistant message");
+                (String::default(), None)
+            }
+            // normally the last message of the stream
+            None => (String::default(), None),
+        };
+        BotReply {
+            message,
+            reasoning,
+            prompt_token: response.prompt_eval_count,
+            completion_token: response.eval_count,
+            reasoning_token: None,
+            total_token: sum_option(response.prompt_eval_count, response.eval_count),
+            context: None,
+        }
+    }
+
+    fn from_openai(message: OpenAIResponseMessage, usage: Option<Usage>) -> Self {
+        BotReply {
+            message: message.content.unwrap_or_default(),
+            reasoning: message.reasoning_content,
+            prompt_token: usage.as_ref().map(|u| u.prompt_tokens),
+            completion_token: usage.as_ref().map(|u| u.completion_tokens),
+            reasoning_token: usage.as_ref().and_then(Usage::reasoning_token_count),
+            total_token: usage.as_ref().map(|u| u.total_tokens),
+            context: None,
+        }
+    }
 }
 
 pub type BotReplyStream = Pin<Box<dyn Stream<Item = Result<BotReply, String>> + Send>>;
 
 pub struct GlobalSettings {
     pub max_tokens: u32,
+    /// Context window the history was fitted into, sent to Ollama as `num_ctx`
+    pub context_length: Option<u32>,
 }
 
 pub enum ChatRequestExecutor {
     OllamaChatRequestExecutor(OllamaConfig, OllamaChatCompletionRequest),
+    OpenAIChatRequestExecutor(OpenAIConfig, OpenAIChatCompletionRequest),
 }
 
 impl ChatRequestExecutor {
@@ -58,7 +128,7 @@ impl ChatRequestExecutor {
         config: &OllamaConfig,
         messages: Vec<MessageDTO>,
         options: GenericOptions,
-        _global_settings: GlobalSettings,
+        global_settings: GlobalSettings,
         model: String,
     ) -> Result<ChatRequestExecutor, String> {
         let request: OllamaChatCompletionRequest;
@@ -73,6 +143,10 @@ impl ChatRequestExecutor {
         // build request
         // Stream must be set to false explictly for Ollama, or it will treat the request as a Stream request
         let stream = options.stream.clone().unwrap_or(false);
+        let think = options.think;
+        let mut options: OllamaRequestOptions = options.into();
+        // without num_ctx Ollama uses its own small default and silently cuts the prompt
+        options.num_ctx = global_settings.context_length;
         request = OllamaChatCompletionRequest {
             common: super::providers::types::ChatCompletionRequestCommon {
                 model: model.to_string(),
@@ -80,14 +154,52 @@ impl ChatRequestExecutor {
                 ..Default::default()
             },
             messages: req_messages,
-            options: Some(options.into()),
-            ..Default::default()
+            options: Some(options),
+            think,
         };
         Ok(ChatRequestExecutor::OllamaChatRequestExecutor(
             config.clone(), request,
         ))
     }
 
+    pub fn openai(
+        config: &OpenAIConfig,
+        messages: Vec<MessageDTO>,
+        options: GenericOptions,
+        global_settings: GlobalSettings,
+        model: String,
+    ) -> Result<ChatRequestExecutor, String> {
+        // set messages
+        let req_messages: Vec<OpenAIMessage> = messages
+            .into_iter()
+            .map(Into::<OpenAIMessage>::into)
+            .collect();
+        // set options
+        let options: OpenAIOptions = serde_json::from_str(&options.options)
+            .map_err(|_| format!("Failed to parse conversation options: {}", &options.options))?;
+        // fall back to the global max tokens setting, 0 means "let the server decide"
+        let max_tokens = options
+            .max_tokens
+            .or(Some(global_settings.max_tokens).filter(|max| *max > 0));
+        // build request
+        let request = OpenAIChatCompletionRequest {
+            common: ChatCompletionRequestCommon {
+                model,
+                stream: Some(options.stream.unwrap_or(false)),
+                temperature: options.temperature,
+                top_p: options.top_p,
+                max_tokens,
+                presence_penalty: options.presence_penalty,
+                frequency_penalty: options.frequency_penalty,
+                ..Default::default()
+            },
+            messages: req_messages,
+        };
+        Ok(ChatRequestExecutor::OpenAIChatRequestExecutor(
+            config.clone(), request,
+        ))
+    }
+
     pub async fn execute(&self) -> Result<BotReply, String> {
         let log_tag = "ChatRequest::execute";
         match self {
@@ -99,31 +211,28 @@ impl ChatRequestExecutor {
                         log::error!("execute ChatRequest::OllamaChatRequest: {:?}", err);
                         format!("Failed to get chat completion response: {}", err)
                     })?;
-                let message: String = match response.message {
-                    Some(response_message) => match response_message {
-                        OllamaMessage::Assistant(content) => content,
-                        _ => {
-                            warn(
-                                log_tag,
-                                "OllamaChat::create returned a non-assistant message",
-                            );
-                            String::default()
-                        }
-                    },
-                    _ => {
-                        warn(log_tag, "OllamaChat::create returned an empty message");
-                        String::default()
+                if response.message.is_none() {
+                    warn(log_tag, "OllamaChat::create returned an empty message");
+                }
+                // extract data & build reply
+                Ok(split_think_tags(BotReply::from_ollama(response, log_tag)))
+            }
+            ChatRequestExecutor::OpenAIChatRequestExecutor(config, request) => {
+                let response = OpenAIChat::new(config.clone())
+                    .create(request.clone())
+                    .await
+                    .map_err(|err| {
+                        log::error!("execute ChatRequest::OpenAIChatRequest: {:?}", err);
+                        format!("Failed to get chat completion response: {}", err)
+                    })?;
+                let message = match response.choices.into_iter().next() {
+                    Some(choice) => choice.message,
+                    None => {
+                        warn(log_tag, "OpenAIChat::create returned no choices");
+                        OpenAIResponseMessage::default()
                     }
                 };
-                // extract data & build reply
-                Ok(BotReply {
-                    message,
-                    reasoning: None,
-                    prompt_token: response.prompt_eval_count,
-                    completion_token: response.eval_count,
-                    reasoning_token: None,
-                    total_token: sum_option(response.prompt_eval_count, response.eval_count),
-                })
+                Ok(split_think_tags(BotReply::from_openai(message, response.common.usage)))
             }
         }
     }
@@ -137,68 +246,45 @@ impl ChatRequestExecutor {
                     .await
                     .map_err(|err| format!("Error creating stream: {}", err.to_string()))?;
 
-                // Parse the streaming response
-                let stream = response.bytes_stream();
-                let mut is_reasoning = false;
+                // Parse the streaming response, one JSON object per line
+                let stream = decode_stream(
+                    response.bytes_stream(),
+                    NdjsonDecoder::<OllamaChatCompletionResponse>::new(),
+                );
+                let mut think_tags = ThinkTagParser::new();
+
+                let result = stream.map(move |response_result| {
+                    response_result.map(|response| {
+                        let is_last = response.done;
+                        think_tags.apply(BotReply::from_ollama(response, log_tag), is_last)
+                    })
+                });
+                Ok(Box::pin(result))
+            }
+            ChatRequestExecutor::OpenAIChatRequestExecutor(config, request) => {
+                // the SSE endpoint is only used when streaming was requested,
+                // regardless of what the conversation options say
+                let mut request = request.clone();
+                request.common.stream = Some(true);
+                request.common.stream_options = Some(ChatCompletionStreamOptions {
+                    include_usage: true,
+                });
+                let stream = OpenAIChat::new(config.clone())
+                    .create_stream(request)
+                    .await
+                    .map_err(|err| format!("Error creating stream: {}", err))?;
+
+                let mut think_tags = ThinkTagParser::new();
 
                 let result = stream.map(move |chunk_result| {
-                    chunk_result
-                        .map_err(|e| format!("Stream error: {}", e))
-                        .and_then(|chunk| {
-                            let text = String::from_utf8_lossy(&chunk);
-                            serde_json::from_str::<super::providers::ollama::chat::OllamaChatCompletionResponse>(&text)
-                                .map_err(|e| format!("JSON parse error: {}", e))
-                        })
-                        .map(|response| {
-                            let content: String = match response.message {
-                                Some(response_message) => match response_message {
-                                    OllamaMessage::Assistant(content) => {
-                                        // check for reasoning content
-                                        // return empty content for <think> and </think>
-                                        if content.contains("<think>") {
-                                            is_reasoning = true;
-                                            String::default()
-                                        } else if content.contains("</think>") {
-                                            is_reasoning = false;
-                                            String::default()
-                                        } else {
-                                            content
-                                        }
-                                    }
-                                    _ => {
-                                        warn(
-                                            log_tag,
-                                            "OllamaChat::create_stream returned a non-assistant message",
-                                        );
-                                        String::default()
-                                    }
-                                },
-                                _ => {
-                                    // normally the last message of the stream
-                                    String::default()
-                                }
-                            };
-
-                            BotReply {
-                                message: if is_reasoning {
-                                    String::default()
-                                } else {
-                                    content.clone()
-                                },
-                                reasoning: if is_reasoning {
-                                    Some(content)
-                                } else {
-                                    None
-                                },
-                                prompt_token: response.prompt_eval_count,
-                                completion_token: response.eval_count,
-                                reasoning_token: None,
-                                total_token: sum_option(
-                                    response.prompt_eval_count,
-                                    response.eval_count,
-                                ),
-                            }
-                        })
+                    chunk_result.map(|chunk| {
+                        // the final usage chunk carries no choices
+                        let choice = chunk.choices.into_iter().next();
+                        let is_last = chunk.common.usage.is_some()
+                            || choice.as_ref().map_or(false, |c| c.finish_reason.is_some());
+                        let delta = choice.map(|c| c.delta).unwrap_or_default();
+                        think_tags.apply(BotReply::from_openai(delta, chunk.common.usage), is_last)
+                    })
                 });
                 Ok(Box::pin(result))
             }
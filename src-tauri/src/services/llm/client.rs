@@ -5,22 +5,36 @@ use entity::entities::{
     conversations::GenericOptions,
     messages::MessageDTO,
     models::{GenericConfig, Providers},
-    settings::ProxySetting,
+    settings::{ContextStrategy, ProxySetting},
 };
+use once_cell::sync::Lazy;
 use reqwest;
+use std::{collections::HashMap, sync::Mutex};
 
 use super::{
     chat::{BotReply, BotReplyStream, ChatRequestExecutor, GlobalSettings},
+    context::{
+        self, ContextBudget, ContextPlan, ContextReport, DEFAULT_CONTEXT_LENGTH,
+        MAX_DETECTED_CONTEXT_LENGTH,
+    },
     models::{ListModelsRequestExecutor, RemoteModel},
-    providers::ollama::config::OllamaConfig,
-    types::RawOllamaConfig,
-    utils::build_http_client,
+    providers::{
+        ollama::{config::OllamaConfig, models::OllamaModels},
+        openai::config::OpenAIConfig,
+    },
+    types::{RawOllamaConfig, RawOpenAIConfig},
 };
 
+/// Context lengths read from Ollama, by (api_base, model), so `/api/show`
+/// is asked once per model for the life of the process
+static DETECTED_CONTEXT_LENGTHS: Lazy<Mutex<HashMap<(String, String), u32>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
 /// LLM Client for various providers
 #[derive(Debug, Clone)]
 pub enum LLMClient {
     OllamaClient(OllamaConfig, Option<String>),
+    OpenAIClient(OpenAIConfig, Option<String>),
 }
 
 impl LLMClient {
@@ -29,7 +43,6 @@ impl LLMClient {
         config: GenericConfig,
         proxy_setting: Option<ProxySetting>,
     ) -> Result<Self, String> {
-        let _http_client: reqwest::Client = build_http_client(proxy_setting);
         let provider = config.provider.parse::<Providers>()
             .map_err(|_| format!("Unknown provider: {}", config.provider))?;
         match provider {
@@ -40,9 +53,42 @@ impl LLMClient {
                 let ollama_config: OllamaConfig = raw_config.into();
                 Ok(LLMClient::OllamaClient(ollama_config, model))
             }
+            Providers::OpenAICompatible => {
+                let raw_config: RawOpenAIConfig = serde_json::from_str(&config.config)
+                    .map_err(|_| format!("Failed to parse model config: {}", &config.config))?;
+                let model = raw_config.model.clone();
+                let openai_config = OpenAIConfig {
+                    proxy_setting,
+                    ..raw_config.into()
+                };
+                Ok(LLMClient::OpenAIClient(openai_config, model))
+            }
         }
     }
 
+    /// Name of the model on the provider's server
+    pub fn model_name(&self) -> Option<&str> {
+        match self {
+            LLMClient::OllamaClient(_, model) | LLMClient::OpenAIClient(_, model) => model.as_deref(),
+        }
+    }
+
+    /// Whether requests go to this machine only, judged by the API base
+    pub fn is_local(&self) -> bool {
+        let api_base = match self {
+            LLMClient::OllamaClient(config, _) => &config.api_base,
+            LLMClient::OpenAIClient(config, _) => &config.api_base,
+        };
+        let Some(host) = reqwest::Url::parse(api_base)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.trim_matches(['[', ']']).to_string()))
+        else {
+            return false;
+        };
+        host.eq_ignore_ascii_case("localhost")
+            || host.parse::<std::net::IpAddr>().is_ok_and(|ip| ip.is_loopback())
+    }
+
     pub async fn chat(
         &self,
         messages: Vec<MessageDTO>,
@@ -60,6 +106,16 @@ impl LLMClient {
                 }
                 None => Err(format!("Model not set for chat")),
             },
+            LLMClient::OpenAIClient(config, model) => match model {
+                Some(model_str) => {
+                    let reply =
+                        ChatRequestExecutor::openai(config, messages, options, global_settings, model_str.to_string())?
+                            .execute()
+                            .await?;
+                    Ok(reply)
+                }
+                None => Err(format!("Model not set for chat")),
+            },
         }
     }
 
@@ -80,6 +136,16 @@ impl LLMClient {
                 }
                 None => Err(format!("Model not set for chat")),
             },
+            LLMClient::OpenAIClient(config, model) => match model {
+                Some(model_str) => {
+                    let stream =
+                        ChatRequestExecutor::openai(config, messages, options, global_settings, model_str.to_string())?
+                            .execute_stream()
+                            .await?;
+                    Ok(stream)
+                }
+                None => Err(format!("Model not set for chat")),
+            },
         }
     }
 
@@ -89,6 +155,106 @@ impl LLMClient {
                 let result = ListModelsRequestExecutor::ollama(config).execute().await?;
                 Ok(result)
             }
+            LLMClient::OpenAIClient(config, _) => {
+                let result = ListModelsRequestExecutor::openai(config).execute().await?;
+                Ok(result)
+            }
+        }
+    }
+
+    /// Context window to fit history into: the model config wins, then what
+    /// Ollama reports for the model, then a conservative default
+    pub async fn context_length(&self) -> u32 {
+        match self {
+            LLMClient::OllamaClient(config, model) => {
+                if let Some(length) = config.context_length {
+                    return length;
+                }
+                let Some(model) = model else {
+                    return DEFAULT_CONTEXT_LENGTH;
+                };
+                let key = (config.api_base.clone(), model.clone());
+                if let Some(length) = DETECTED_CONTEXT_LENGTHS.lock().unwrap().get(&key) {
+                    return *length;
+                }
+                match OllamaModels::new(config.clone()).show(model).await {
+                    Ok(info) => {
+                        let length = info.num_ctx().unwrap_or_else(|| {
+                            info.trained_context_length()
+                                .map(|length| length.min(MAX_DETECTED_CONTEXT_LENGTH))
+                                .unwrap_or(DEFAULT_CONTEXT_LENGTH)
+                        });
+                        DETECTED_CONTEXT_LENGTHS.lock().unwrap().insert(key, length);
+                        length
+                    }
+                    // not cached, so the next request asks again
+                    Err(err) => {
+                        log::warn!("Failed to read context length of {}: {}", model, err);
+                        DEFAULT_CONTEXT_LENGTH
+                    }
+                }
+            }
+            LLMClient::OpenAIClient(config, _) => config.context_length.unwrap_or(DEFAULT_CONTEXT_LENGTH),
+        }
+    }
+
+    /// Budget for a request leaving room for a reply of `max_tokens`
+    pub async fn context_budget(&self, max_tokens: u32) -> ContextBudget {
+        ContextBudget::new(self.context_length().await, max_tokens)
+    }
+
+    /// Decide which messages fit without summarising anything yet
+    pub async fn plan_context(
+        &self,
+        messages: Vec<MessageDTO>,
+        strategy: ContextStrategy,
+        max_tokens: u32,
+    ) -> (ContextPlan, ContextBudget) {
+        let budget = self.context_budget(max_tokens).await;
+        let plan = context::plan(messages, &budget, strategy);
+        if plan.report.dropped_messages > 0 {
+            log::info!(
+                "Context window of {} tokens: dropped {} messages ({} tokens)",
+                budget.context_length, plan.report.dropped_messages, plan.report.dropped_tokens
+            );
+        }
+        (plan, budget)
+    }
+
+    /// Cut `messages` down to what fits the model's context window, leaving
+    /// room for a reply of `max_tokens`. Dropped turns are summarised on the
+    /// fly; see `services::summaries` for summaries kept in the database.
+    pub async fn fit_context(
+        &self,
+        messages: Vec<MessageDTO>,
+        strategy: ContextStrategy,
+        max_tokens: u32,
+    ) -> Result<(Vec<MessageDTO>, ContextReport), String> {
+        let (mut plan, budget) = self.plan_context(messages, strategy, max_tokens).await;
+        if strategy == ContextStrategy::SummariseOlderTurns && !plan.dropped.is_empty() {
+            // a failed summary must not fail the chat, the recent turns still fit
+            match self.summarise(&plan.dropped, &budget).await {
+                Ok(summary) => context::insert_summary(&mut plan, &summary),
+                Err(err) => log::warn!("Failed to summarise older turns: {}", err),
+            }
+        }
+        Ok((plan.kept, plan.report))
+    }
+
+    /// Ask the model itself to condense `messages`
+    pub async fn summarise(&self, messages: &[MessageDTO], budget: &ContextBudget) -> Result<String, String> {
+        let request = context::summary_request(messages, budget.context_length);
+        let options = GenericOptions {
+            options: "{}".to_string(),
+        };
+        let global_settings = GlobalSettings {
+            max_tokens: budget.summary_tokens(),
+            context_length: Some(budget.context_length),
+        };
+        let reply = self.chat(request, options, global_settings).await?;
+        if reply.message.trim().is_empty() {
+            return Err("Model returned an empty summary".to_string());
         }
+        Ok(reply.message)
     }
 }
@@ -0,0 +1,285 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Fits conversation history into a model's context window.
+//!
+//! Token counts are estimated, not computed with the model's tokenizer, so the
+//! budget keeps some slack: a reply reserve is subtracted up front and every
+//! message is charged a fixed overhead for its role markers.
+use entity::entities::{messages::MessageDTO, settings::ContextStrategy};
+use serde::Serialize;
+
+/// Used when neither the model config nor the server reports a size
+pub const DEFAULT_CONTEXT_LENGTH: u32 = 4096;
+/// Upper bound for sizes read from model metadata. Models advertise up to
+/// 128k tokens, which would make Ollama allocate far more memory than a
+/// workstation has, so only an explicit setting goes beyond this.
+pub const MAX_DETECTED_CONTEXT_LENGTH: u32 = 8192;
+/// Upper bound for the summary of dropped turns
+const MAX_SUMMARY_TOKENS: u32 = 512;
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+const CHARS_PER_TOKEN: u32 = 4;
+
+const SUMMARY_INSTRUCTION: &str = "Summarise the conversation below in a few short paragraphs. \
+Keep names, dates, figures, decisions and open questions. If it starts with an earlier summary, \
+fold the new turns into it. Reply with the summary only.";
+/// Role under which an earlier summary is passed back in for a rolling update
+pub const SUMMARY_ROLE: &str = "summary";
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+/// Roughly four characters per token holds well enough for English and
+/// the European languages our users write in
+pub fn estimate_tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(CHARS_PER_TOKEN)
+}
+
+pub fn estimate_message_tokens(message: &MessageDTO) -> u32 {
+    estimate_tokens(&message.content) + MESSAGE_OVERHEAD_TOKENS
+}
+
+fn estimate_messages_tokens(messages: &[MessageDTO]) -> u32 {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextBudget {
+    pub context_length: u32,
+    /// Room left for the model's answer
+    pub reply_tokens: u32,
+}
+
+impl ContextBudget {
+    /// `max_tokens` is the reply limit from settings, 0 meaning unset. It is
+    /// capped at half the window so a large limit cannot starve the history.
+    pub fn new(context_length: u32, max_tokens: u32) -> Self {
+        let reply_tokens = if max_tokens > 0 {
+            max_tokens.min(context_length / 2)
+        } else {
+            context_length / 4
+        };
+        Self {
+            context_length,
+            reply_tokens,
+        }
+    }
+
+    pub fn prompt_tokens(&self) -> u32 {
+        self.context_length.saturating_sub(self.reply_tokens)
+    }
+
+    /// Share of the prompt budget set aside for a summary of dropped turns
+    pub fn summary_tokens(&self) -> u32 {
+        (self.prompt_tokens() / 4).min(MAX_SUMMARY_TOKENS)
+    }
+}
+
+/// What the context manager did to the history, returned with the reply
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextReport {
+    pub strategy: ContextStrategy,
+    pub context_length: u32,
+    /// Estimated prompt size after fitting
+    pub estimated_tokens: u32,
+    pub dropped_messages: usize,
+    pub dropped_tokens: u32,
+    /// Whether the dropped turns were replaced by a summary
+    pub summarised: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextPlan {
+    pub kept: Vec<MessageDTO>,
+    /// Dropped messages, in their original order
+    pub dropped: Vec<MessageDTO>,
+    pub report: ContextReport,
+}
+
+/// Decide which messages fit. The last message, the prompt being answered,
+/// is always kept even if it alone exceeds the budget.
+pub fn plan(messages: Vec<MessageDTO>, budget: &ContextBudget, strategy: ContextStrategy) -> ContextPlan {
+    let total = estimate_messages_tokens(&messages);
+    let mut report = ContextReport {
+        strategy,
+        context_length: budget.context_length,
+        estimated_tokens: total,
+        ..Default::default()
+    };
+    if total <= budget.prompt_tokens() || messages.len() <= 1 {
+        return ContextPlan {
+            kept: messages,
+            dropped: Vec::new(),
+            report,
+        };
+    }
+
+    let last = messages.len() - 1;
+    let pinned = |i: usize, m: &MessageDTO| {
+        i == last || (strategy != ContextStrategy::DropOldest && m.role == "system")
+    };
+    let mut available = budget.prompt_tokens();
+    if strategy == ContextStrategy::SummariseOlderTurns {
+        available = available.saturating_sub(budget.summary_tokens());
+    }
+    for (i, m) in messages.iter().enumerate() {
+        if pinned(i, m) {
+            available = available.saturating_sub(estimate_message_tokens(m));
+        }
+    }
+    // walk back from the newest turn; stop at the first one that does not fit
+    // so the kept history stays contiguous
+    let mut keep = vec![false; messages.len()];
+    let mut filling = true;
+    for (i, m) in messages.iter().enumerate().rev() {
+        if pinned(i, m) {
+            keep[i] = true;
+            continue;
+        }
+        let tokens = estimate_message_tokens(m);
+        if filling && tokens <= available {
+            available -= tokens;
+            keep[i] = true;
+        } else {
+            filling = false;
+        }
+    }
+
+    let mut kept = Vec::new();
+    let mut dropped = Vec::new();
+    for (m, keep) in messages.into_iter().zip(keep) {
+        if keep {
+            kept.push(m);
+        } else {
+            dropped.push(m);
+        }
+    }
+    report.estimated_tokens = estimate_messages_tokens(&kept);
+    report.dropped_messages = dropped.len();
+    report.dropped_tokens = estimate_messages_tokens(&dropped);
+    ContextPlan {
+        kept,
+        dropped,
+        report,
+    }
+}
+
+/// Build the request asking the model to condense `messages`. The transcript
+/// is cut from the front if it does not fit `context_length` itself.
+pub fn summary_request(messages: &[MessageDTO], context_length: u32) -> Vec<MessageDTO> {
+    let budget = ContextBudget::new(context_length, MAX_SUMMARY_TOKENS);
+    let max_chars = (budget.prompt_tokens() as usize)
+        .saturating_sub(estimate_tokens(SUMMARY_INSTRUCTION) as usize + 2 * MESSAGE_OVERHEAD_TOKENS as usize)
+        * CHARS_PER_TOKEN as usize;
+    let transcript = messages
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let skip = transcript.chars().count().saturating_sub(max_chars);
+    let transcript = transcript.chars().skip(skip).collect::<String>();
+    vec![
+        MessageDTO {
+            role: "system".to_string(),
+            content: SUMMARY_INSTRUCTION.to_string(),
+        },
+        MessageDTO {
+            role: "user".to_string(),
+            content: transcript,
+        },
+    ]
+}
+
+/// Put the summary right after the leading system messages of a plan
+pub fn insert_summary(plan: &mut ContextPlan, summary: &str) {
+    let message = MessageDTO {
+        role: "system".to_string(),
+        content: format!("{}{}", SUMMARY_PREFIX, summary.trim()),
+    };
+    let at = plan.kept.iter().take_while(|m| m.role == "system").count();
+    plan.report.estimated_tokens += estimate_message_tokens(&message);
+    plan.report.summarised = true;
+    plan.kept.insert(at, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str, chars: usize) -> MessageDTO {
+        MessageDTO {
+            role: role.to_string(),
+            content: "x".repeat(chars),
+        }
+    }
+
+    fn history() -> Vec<MessageDTO> {
+        // 4 + 100 tokens each, 520 in total
+        vec![
+            msg("system", 400),
+            msg("user", 400),
+            msg("assistant", 400),
+            msg("user", 400),
+            msg("assistant", 400),
+        ]
+    }
+
+    #[test]
+    fn test_estimate_tokens_rounds_up_and_counts_chars() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("\u{e9}\u{e9}\u{e9}\u{e9}"), 1);
+    }
+
+    #[test]
+    fn test_history_within_budget_is_untouched() {
+        let budget = ContextBudget::new(1000, 100);
+        let plan = plan(history(), &budget, ContextStrategy::DropOldest);
+        assert_eq!(plan.kept.len(), 5);
+        assert!(plan.dropped.is_empty());
+        assert_eq!(plan.report.estimated_tokens, 520);
+    }
+
+    #[test]
+    fn test_drop_oldest_also_drops_the_system_message() {
+        let budget = ContextBudget::new(400, 100);
+        let plan = plan(history(), &budget, ContextStrategy::DropOldest);
+        assert_eq!(plan.kept.len(), 2);
+        assert_eq!(plan.dropped[0].role, "system");
+        assert_eq!(plan.report.dropped_messages, 3);
+        assert_eq!(plan.report.dropped_tokens, 312);
+    }
+
+    #[test]
+    fn test_keep_system_plus_recent_pins_the_system_message() {
+        let budget = ContextBudget::new(500, 100);
+        let plan = plan(history(), &budget, ContextStrategy::KeepSystemPlusRecent);
+        let roles: Vec<_> = plan.kept.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["system", "user", "assistant"]);
+        assert_eq!(plan.report.estimated_tokens, 312);
+    }
+
+    #[test]
+    fn test_last_message_is_kept_even_when_too_large() {
+        let budget = ContextBudget::new(100, 50);
+        let plan = plan(vec![msg("user", 40), msg("user", 4000)], &budget, ContextStrategy::DropOldest);
+        assert_eq!(plan.kept.len(), 1);
+        assert_eq!(plan.kept[0].content.len(), 4000);
+    }
+
+    #[test]
+    fn test_summary_is_inserted_after_system_messages() {
+        let budget = ContextBudget::new(500, 100);
+        let mut plan = plan(history(), &budget, ContextStrategy::SummariseOlderTurns);
+        insert_summary(&mut plan, " They agreed on the terms. ");
+        assert!(plan.report.summarised);
+        assert_eq!(plan.kept[0].role, "system");
+        assert_eq!(plan.kept[1].content, format!("{}They agreed on the terms.", SUMMARY_PREFIX));
+        assert!(plan.report.estimated_tokens <= budget.prompt_tokens());
+    }
+
+    #[test]
+    fn test_summary_request_trims_oldest_transcript_text() {
+        let request = summary_request(&[msg("user", 100_000), msg("assistant", 10)], 1024);
+        assert_eq!(request.len(), 2);
+        assert!(request[1].content.ends_with("assistant: xxxxxxxxxx"));
+        assert!(estimate_tokens(&request[1].content) < 1024);
+    }
+}
@@ -2,6 +2,9 @@
 pub mod providers;
 pub mod chat;
 pub mod client;
+pub mod context;
 pub mod models;
+pub mod reasoning;
+pub mod stream;
 pub mod types;
 pub mod utils;
\ No newline at end of file
@@ -1,7 +1,10 @@
 // This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
 // BEAR LLM AI changes - Removed async_openai dependency, using direct Ollama config
 // MIT License Copyright (c) 2024-present Frank Zhang
-use super::providers::ollama::{config::OllamaConfig, models::OllamaModels};
+use super::providers::{
+    ollama::{config::OllamaConfig, models::OllamaModels},
+    openai::{config::OpenAIConfig, models::OpenAIModels},
+};
 use serde::Serialize;
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize)]
@@ -12,6 +15,7 @@ pub struct RemoteModel {
 
 pub enum ListModelsRequestExecutor {
     OllamaListModelsRequestExecutor(OllamaConfig),
+    OpenAIListModelsRequestExecutor(OpenAIConfig),
 }
 
 impl ListModelsRequestExecutor {
@@ -19,6 +23,10 @@ impl ListModelsRequestExecutor {
         return ListModelsRequestExecutor::OllamaListModelsRequestExecutor(config.clone());
     }
 
+    pub fn openai(config: &OpenAIConfig) -> Self {
+        return ListModelsRequestExecutor::OpenAIListModelsRequestExecutor(config.clone());
+    }
+
     pub async fn execute(&self) -> Result<Vec<RemoteModel>, String> {
         match self {
             ListModelsRequestExecutor::OllamaListModelsRequestExecutor(config) => {
@@ -33,6 +41,18 @@ impl ListModelsRequestExecutor {
                     .collect();
                 Ok(result)
             }
+            ListModelsRequestExecutor::OpenAIListModelsRequestExecutor(config) => {
+                let response = OpenAIModels::new(config.clone()).list().await.map_err(|err| {
+                    log::error!("OpenAIListModelsRequestExecutor: {}", err);
+                    String::from("Failed to list models")
+                })?;
+                let result = response
+                    .data
+                    .iter()
+                    .map(|m| RemoteModel { id: m.id.clone() })
+                    .collect();
+                Ok(result)
+            }
         }
     }
 }
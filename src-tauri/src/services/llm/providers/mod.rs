@@ -1,3 +1,4 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
 pub mod types;
 pub mod ollama;
+pub mod openai;
@@ -20,15 +20,29 @@ pub struct OllamaChatCompletionRequest {
     pub messages: Vec<OllamaMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub options: Option<OllamaOptions>,
+    /// Ask thinking models to return their reasoning in `message.thinking`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub think: Option<bool>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct OllamaChatCompletionResponse {
     #[serde(flatten)]
     pub common: types::ChatCompletionResponseCommon,
-    pub message: Option<OllamaMessage>,
+    pub message: Option<OllamaResponseMessage>,
     pub prompt_eval_count: Option<u32>,
     pub eval_count: Option<u32>,
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// Message as returned by Ollama, which may carry the native `thinking` field
+#[derive(Deserialize, Debug, Clone)]
+pub struct OllamaResponseMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    pub thinking: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -41,6 +55,8 @@ pub struct OllamaOptions {
     pub top_k: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
 }
 
 impl From<entity::entities::conversations::OllamaOptions> for OllamaOptions {
@@ -50,6 +66,7 @@ impl From<entity::entities::conversations::OllamaOptions> for OllamaOptions {
             top_p: options.top_p,
             top_k: options.top_k,
             stream: options.stream,
+            num_ctx: None,
         }
     }
 }
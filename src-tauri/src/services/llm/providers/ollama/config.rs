@@ -8,12 +8,16 @@ const OLLAMA_API_BASE: &str = "http://localhost:11434";
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OllamaConfig {
     pub api_base: String,
+    /// Context window to request via `num_ctx`, overriding what the server reports
+    #[serde(default)]
+    pub context_length: Option<u32>,
 }
 
 impl Default for OllamaConfig {
     fn default() -> Self {
         Self {
             api_base: OLLAMA_API_BASE.to_string(),
+            context_length: None,
         }
     }
 }
@@ -22,12 +26,14 @@ impl Default for OllamaConfig {
 pub struct RawOllamaConfig {
     pub model: Option<String>,
     pub api_base: Option<String>,
+    pub context_length: Option<u32>,
 }
 
 impl From<RawOllamaConfig> for OllamaConfig {
     fn from(raw: RawOllamaConfig) -> Self {
         OllamaConfig {
             api_base: raw.api_base.unwrap_or(OLLAMA_API_BASE.to_string()),
+            context_length: raw.context_length,
         }
     }
 }
@@ -24,6 +24,40 @@ pub struct OllamaListModelsResponse {
     pub models: Vec<OllamaModel>,
 }
 
+/// Subset of `/api/show` used to find a model's context window
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct OllamaShowModelResponse {
+    /// Modelfile parameters, one `name value` pair per line
+    #[serde(default)]
+    pub parameters: Option<String>,
+    /// GGUF metadata, e.g. `llama.context_length`
+    #[serde(default)]
+    pub model_info: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl OllamaShowModelResponse {
+    /// `num_ctx` set in the Modelfile, which Ollama already runs with
+    pub fn num_ctx(&self) -> Option<u32> {
+        self.parameters.as_deref()?.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some("num_ctx"), Some(value)) => value.parse().ok(),
+                _ => None,
+            }
+        })
+    }
+
+    /// Context length the model was trained for
+    pub fn trained_context_length(&self) -> Option<u32> {
+        self.model_info
+            .as_ref()?
+            .iter()
+            .find(|(key, _)| key.ends_with(".context_length"))
+            .and_then(|(_, value)| value.as_u64())
+            .map(|value| value as u32)
+    }
+}
+
 impl OllamaModels {
     pub fn new(config: OllamaConfig) -> Self {
         Self {
@@ -42,4 +76,17 @@ impl OllamaModels {
             .await?;
         Ok(response)
     }
+
+    pub async fn show(&self, model: &str) -> Result<OllamaShowModelResponse, reqwest::Error> {
+        let url = format!("{}/api/show", self.config.api_base);
+        let response = self.client
+            .post(&url)
+            .json(&serde_json::json!({ "model": model }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
 }
@@ -0,0 +1,279 @@
+// This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
+// MIT License Copyright (c) 2024-present Frank Zhang
+use std::pin::Pin;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio_stream::{Stream, StreamExt};
+
+use super::config::OpenAIConfig;
+use crate::services::llm::{
+    providers::types,
+    stream::{decode_stream, SseDecoder},
+    utils::build_http_client,
+};
+
+/// Sentinel sent by OpenAI-compatible servers as the last SSE data line
+const STREAM_DONE: &str = "[DONE]";
+
+pub type OpenAIChatCompletionStream =
+    Pin<Box<dyn Stream<Item = Result<OpenAIChatCompletionChunk, String>> + Send>>;
+
+#[derive(Debug)]
+pub struct OpenAIChat {
+    client: Client,
+    config: OpenAIConfig,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct OpenAIChatCompletionRequest {
+    #[serde(flatten)]
+    pub common: types::ChatCompletionRequestCommon,
+    pub messages: Vec<OpenAIMessage>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OpenAIChatCompletionResponse {
+    #[serde(flatten)]
+    pub common: types::ChatCompletionResponseCommon,
+    #[serde(default)]
+    pub choices: Vec<OpenAIChoice>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OpenAIChoice {
+    pub message: OpenAIResponseMessage,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OpenAIChatCompletionChunk {
+    #[serde(flatten)]
+    pub common: types::ChatCompletionResponseCommon,
+    #[serde(default)]
+    pub choices: Vec<OpenAIChunkChoice>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OpenAIChunkChoice {
+    pub delta: OpenAIResponseMessage,
+    pub finish_reason: Option<String>,
+}
+
+/// Assistant message or stream delta. `reasoning_content` is the field
+/// llama.cpp and vLLM use for the output of reasoning models.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct OpenAIResponseMessage {
+    pub content: Option<String>,
+    pub reasoning_content: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "role", content = "content")]
+pub enum OpenAIMessage {
+    #[serde(rename = "system")]
+    System(String),
+    #[serde(rename = "user")]
+    User(String),
+    #[serde(rename = "assistant")]
+    Assistant(String),
+}
+
+impl From<entity::entities::messages::MessageDTO> for OpenAIMessage {
+    fn from(message: entity::entities::messages::MessageDTO) -> Self {
+        match message.role.as_str() {
+            "system" => Self::System(message.content),
+            "user" => Self::User(message.content),
+            "assistant" => Self::Assistant(message.content),
+            _ => Self::User(message.content),
+        }
+    }
+}
+
+impl OpenAIChat {
+    pub fn new(config: OpenAIConfig) -> Self {
+        Self {
+            client: build_http_client(config.proxy_setting.clone()),
+            config,
+        }
+    }
+
+    pub async fn create(
+        &self,
+        request: OpenAIChatCompletionRequest,
+    ) -> Result<OpenAIChatCompletionResponse, reqwest::Error> {
+        let url = format!("{}/chat/completions", self.config.api_base);
+        let response = self.config
+            .authorize(self.client.post(&url).json(&request))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn create_stream(
+        &self,
+        request: OpenAIChatCompletionRequest,
+    ) -> Result<OpenAIChatCompletionStream, reqwest::Error> {
+        let url = format!("{}/chat/completions", self.config.api_base);
+        let response = self.config
+            .authorize(self.client.post(&url).json(&request))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let stream = decode_stream(response.bytes_stream(), SseDecoder::new())
+            .take_while(|event| !matches!(event, Ok(data) if data.trim() == STREAM_DONE))
+            .map(|event| {
+                event.and_then(|data| {
+                    serde_json::from_str::<OpenAIChatCompletionChunk>(&data)
+                        .map_err(|e| format!("JSON parse error: {}", e))
+                })
+            });
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Read one HTTP/1.1 request (headers + Content-Length body) from the socket
+    async fn read_request(socket: &mut TcpStream) -> String {
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut chunk).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).to_string();
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        if name.eq_ignore_ascii_case("content-length") {
+                            value.trim().parse::<usize>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .unwrap_or(0);
+                if buf.len() >= header_end + 4 + content_length {
+                    break;
+                }
+            }
+        }
+        String::from_utf8_lossy(&buf).to_string()
+    }
+
+    /// Serve a single canned response and return the base URL plus the captured request
+    async fn mock_server(response: String) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await;
+            socket.write_all(response.as_bytes()).await.unwrap();
+            socket.shutdown().await.unwrap();
+            request
+        });
+        (format!("http://{}/v1", addr), handle)
+    }
+
+    fn request(stream: bool) -> OpenAIChatCompletionRequest {
+        OpenAIChatCompletionRequest {
+            common: types::ChatCompletionRequestCommon {
+                model: "local-model".to_string(),
+                stream: Some(stream),
+                ..Default::default()
+            },
+            messages: vec![OpenAIMessage::User("Hello".to_string())],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_sends_auth_and_parses_usage() {
+        let body = r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"Hi there"}}],"usage":{"prompt_tokens":5,"completion_tokens":2,"total_tokens":7}}"#;
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let (api_base, server) = mock_server(response).await;
+        let chat = OpenAIChat::new(OpenAIConfig {
+            api_base,
+            api_key: Some("secret".to_string()),
+            ..Default::default()
+        });
+
+        let result = chat.create(request(false)).await.unwrap();
+        let captured = server.await.unwrap();
+
+        assert!(captured.starts_with("POST /v1/chat/completions"));
+        assert!(captured.to_lowercase().contains("authorization: bearer secret"));
+        assert!(captured.contains(r#""model":"local-model""#));
+        assert_eq!(result.choices[0].message.content.as_deref(), Some("Hi there"));
+        assert_eq!(result.common.usage.unwrap().total_tokens, 7);
+    }
+
+    #[tokio::test]
+    async fn test_create_stream_yields_deltas_until_done() {
+        let events = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":"Hel"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo"}}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#,
+            STREAM_DONE,
+        ];
+        let mut response = String::from(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n",
+        );
+        for event in events {
+            response.push_str(&format!("data: {}\n\n", event));
+        }
+        let (api_base, server) = mock_server(response).await;
+        let chat = OpenAIChat::new(OpenAIConfig {
+            api_base,
+            ..Default::default()
+        });
+
+        let chunks: Vec<_> = chat
+            .create_stream(request(true))
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let captured = server.await.unwrap();
+
+        assert!(!captured.to_lowercase().contains("authorization:"));
+        assert_eq!(chunks.len(), 3);
+        let text: String = chunks
+            .iter()
+            .filter_map(|chunk| chunk.as_ref().unwrap().choices.first())
+            .filter_map(|choice| choice.delta.content.clone())
+            .collect();
+        assert_eq!(text, "Hello");
+        let usage = chunks[2].as_ref().unwrap().common.usage.clone().unwrap();
+        assert_eq!(usage.prompt_tokens, 3);
+    }
+
+    #[tokio::test]
+    async fn test_create_stream_reports_http_errors() {
+        let response = "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+        let (api_base, server) = mock_server(response).await;
+        let chat = OpenAIChat::new(OpenAIConfig {
+            api_base,
+            ..Default::default()
+        });
+
+        let result = chat.create_stream(request(true)).await;
+        server.await.unwrap();
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,42 @@
+// This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
+// MIT License Copyright (c) 2024-present Frank Zhang
+use entity::entities::settings::ProxySetting;
+use serde::{Deserialize, Serialize};
+
+pub const OPENAI_COMPATIBLE_API_BASE: &str = "http://localhost:8080/v1";
+
+/// Config for any server speaking the OpenAI chat-completions protocol
+/// (llama.cpp server, vLLM, LM Studio, ...)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OpenAIConfig {
+    pub api_base: String,
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    /// Context window of the served model; these servers do not report it
+    #[serde(default)]
+    pub context_length: Option<u32>,
+    /// Proxy from the app settings, not part of the stored model config
+    #[serde(skip)]
+    pub proxy_setting: Option<ProxySetting>,
+}
+
+impl Default for OpenAIConfig {
+    fn default() -> Self {
+        Self {
+            api_base: OPENAI_COMPATIBLE_API_BASE.to_string(),
+            api_key: None,
+            context_length: None,
+            proxy_setting: None,
+        }
+    }
+}
+
+impl OpenAIConfig {
+    /// Attach the bearer token, if any, to an outgoing request
+    pub fn authorize(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => builder.bearer_auth(key),
+            None => builder,
+        }
+    }
+}
@@ -0,0 +1,4 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+pub mod chat;
+pub mod config;
+pub mod models;
\ No newline at end of file
@@ -0,0 +1,44 @@
+// This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
+// MIT License Copyright (c) 2024-present Frank Zhang
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::config::OpenAIConfig;
+use crate::services::llm::utils::build_http_client;
+
+#[derive(Debug)]
+pub struct OpenAIModels {
+    client: Client,
+    config: OpenAIConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIModel {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpenAIListModelsResponse {
+    pub data: Vec<OpenAIModel>,
+}
+
+impl OpenAIModels {
+    pub fn new(config: OpenAIConfig) -> Self {
+        Self {
+            client: build_http_client(config.proxy_setting.clone()),
+            config,
+        }
+    }
+
+    pub async fn list(&self) -> Result<OpenAIListModelsResponse, reqwest::Error> {
+        let url = format!("{}/models", self.config.api_base);
+        let response = self.config
+            .authorize(self.client.get(&url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
+}
@@ -49,3 +49,13 @@ pub struct Usage {
 pub struct CompletionTokensDetails {
     pub reasoning_tokens: Option<u32>,
 }
+
+impl Usage {
+    /// Reasoning tokens, wherever the server chose to report them
+    pub fn reasoning_token_count(&self) -> Option<u32> {
+        self.completion_tokens_details
+            .as_ref()
+            .and_then(|details| details.reasoning_tokens)
+            .or(self.reasoning_tokens)
+    }
+}
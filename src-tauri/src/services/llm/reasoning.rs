@@ -0,0 +1,146 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Separates `<think>...</think>` blocks emitted by reasoning models from the
+//! answer itself.
+use super::chat::BotReply;
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// Incremental splitter. Tags may arrive cut across chunks, so a trailing
+/// fragment that could still become a tag is held back until the next push.
+#[derive(Debug, Default)]
+pub struct ThinkTagParser {
+    in_think: bool,
+    pending: String,
+}
+
+impl ThinkTagParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed more model output, returning `(content, reasoning)` that is now settled
+    pub fn push(&mut self, text: &str) -> (String, String) {
+        self.pending.push_str(text);
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        loop {
+            let tag = if self.in_think { THINK_CLOSE } else { THINK_OPEN };
+            let out = if self.in_think { &mut reasoning } else { &mut content };
+            if let Some(pos) = self.pending.find(tag) {
+                out.push_str(&self.pending[..pos]);
+                self.pending.drain(..pos + tag.len());
+                self.in_think = !self.in_think;
+                continue;
+            }
+            // keep back the longest suffix that is a prefix of the tag
+            let keep = (1..tag.len())
+                .rev()
+                .find(|k| self.pending.ends_with(&tag[..*k]))
+                .unwrap_or(0);
+            let settled = self.pending.len() - keep;
+            out.push_str(&self.pending[..settled]);
+            self.pending.drain(..settled);
+            return (content, reasoning);
+        }
+    }
+
+    /// Release anything still held back once the output has ended
+    pub fn finish(&mut self) -> (String, String) {
+        let rest = std::mem::take(&mut self.pending);
+        if self.in_think {
+            (String::new(), rest)
+        } else {
+            (rest, String::new())
+        }
+    }
+
+    /// Move think-tagged text of a streamed chunk into its `reasoning`
+    pub fn apply(&mut self, mut reply: BotReply, is_last: bool) -> BotReply {
+        let (mut content, mut reasoning) = self.push(&reply.message);
+        if is_last {
+            let (rest_content, rest_reasoning) = self.finish();
+            content.push_str(&rest_content);
+            reasoning.push_str(&rest_reasoning);
+        }
+        reply.message = content;
+        append_reasoning(&mut reply, &reasoning);
+        reply
+    }
+}
+
+/// Split a complete (non-streamed) reply
+pub fn split_think_tags(mut reply: BotReply) -> BotReply {
+    let mut parser = ThinkTagParser::new();
+    let (mut content, mut reasoning) = parser.push(&reply.message);
+    let (rest_content, rest_reasoning) = parser.finish();
+    content.push_str(&rest_content);
+    reasoning.push_str(&rest_reasoning);
+    reply.message = content.trim_start().to_string();
+    append_reasoning(&mut reply, reasoning.trim());
+    reply
+}
+
+fn append_reasoning(reply: &mut BotReply, reasoning: &str) {
+    if !reasoning.is_empty() {
+        reply.reasoning.get_or_insert_with(String::new).push_str(reasoning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(chunks: &[&str]) -> (String, String) {
+        let mut parser = ThinkTagParser::new();
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        for chunk in chunks {
+            let (c, r) = parser.push(chunk);
+            content.push_str(&c);
+            reasoning.push_str(&r);
+        }
+        let (c, r) = parser.finish();
+        content.push_str(&c);
+        reasoning.push_str(&r);
+        (content, reasoning)
+    }
+
+    #[test]
+    fn test_tags_inside_a_single_chunk() {
+        let (content, reasoning) = feed(&["<think>weigh art. 6</think>The clause is void."]);
+        assert_eq!(content, "The clause is void.");
+        assert_eq!(reasoning, "weigh art. 6");
+    }
+
+    #[test]
+    fn test_tags_split_across_chunks() {
+        let (content, reasoning) = feed(&["<th", "ink>step ", "one</thi", "nk>", "Answer"]);
+        assert_eq!(content, "Answer");
+        assert_eq!(reasoning, "step one");
+    }
+
+    #[test]
+    fn test_text_that_only_looks_like_a_tag_is_kept() {
+        let (content, reasoning) = feed(&["a <", "b and <thin", "g>"]);
+        assert_eq!(content, "a <b and <thing>");
+        assert_eq!(reasoning, "");
+    }
+
+    #[test]
+    fn test_unterminated_think_block_is_reasoning() {
+        let (content, reasoning) = feed(&["<think>still going"]);
+        assert_eq!(content, "");
+        assert_eq!(reasoning, "still going");
+    }
+
+    #[test]
+    fn test_split_think_tags_for_complete_reply() {
+        let reply = split_think_tags(BotReply {
+            message: "<think>\nconsider precedent\n</think>\n\nIt is enforceable.".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(reply.message, "It is enforceable.");
+        assert_eq!(reply.reasoning.as_deref(), Some("consider precedent"));
+    }
+}
@@ -0,0 +1,320 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Framing for streamed HTTP bodies.
+//!
+//! `bytes_stream()` hands out chunks exactly as they came off the socket, so a
+//! single chunk may hold half a record or several records at once. The
+//! decoders here buffer raw bytes and only emit complete records.
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use serde::de::DeserializeOwned;
+use tokio_stream::Stream;
+
+/// Turns a sequence of byte chunks into framed records
+pub trait FrameDecoder {
+    type Item;
+
+    /// Feed the next chunk and return every record it completed
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<Self::Item, String>>;
+
+    /// Flush whatever is left once the body has ended
+    fn finish(&mut self) -> Vec<Result<Self::Item, String>>;
+}
+
+/// Splits bytes on `\n`, tolerating `\r\n`, without ever cutting a
+/// multi-byte UTF-8 sequence in half.
+#[derive(Debug, Default)]
+pub struct LineDecoder {
+    buffer: Vec<u8>,
+}
+
+impl LineDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(pos) = self.buffer[start..].iter().position(|b| *b == b'\n') {
+            let end = start + pos;
+            lines.push(Self::to_line(&self.buffer[start..end]));
+            start = end + 1;
+        }
+        self.buffer.drain(..start);
+        lines
+    }
+
+    /// Return the unterminated tail, if any
+    pub fn finish(&mut self) -> Option<String> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line = Self::to_line(&self.buffer);
+        self.buffer.clear();
+        Some(line)
+    }
+
+    fn to_line(bytes: &[u8]) -> String {
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        String::from_utf8_lossy(bytes).to_string()
+    }
+}
+
+/// Newline-delimited JSON, as streamed by Ollama
+#[derive(Debug)]
+pub struct NdjsonDecoder<T> {
+    lines: LineDecoder,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for NdjsonDecoder<T> {
+    fn default() -> Self {
+        Self {
+            lines: LineDecoder::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> NdjsonDecoder<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn parse(line: String) -> Option<Result<T, String>> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some(serde_json::from_str::<T>(&line).map_err(|e| format!("JSON parse error: {}", e)))
+    }
+}
+
+impl<T: DeserializeOwned> FrameDecoder for NdjsonDecoder<T> {
+    type Item = T;
+
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<T, String>> {
+        self.lines.push(chunk).into_iter().filter_map(Self::parse).collect()
+    }
+
+    fn finish(&mut self) -> Vec<Result<T, String>> {
+        self.lines.finish().and_then(Self::parse).into_iter().collect()
+    }
+}
+
+/// Server-sent events, as streamed by OpenAI-compatible servers. Yields the
+/// `data` payload of each event; `event`, `id`, `retry` and comments are ignored.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    lines: LineDecoder,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn feed(&mut self, line: String, events: &mut Vec<Result<String, String>>) {
+        if line.is_empty() {
+            // a blank line dispatches the event
+            if !self.data.is_empty() {
+                events.push(Ok(self.data.join("\n")));
+                self.data.clear();
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+    }
+}
+
+impl FrameDecoder for SseDecoder {
+    type Item = String;
+
+    fn decode(&mut self, chunk: &[u8]) -> Vec<Result<String, String>> {
+        let mut events = Vec::new();
+        for line in self.lines.push(chunk) {
+            self.feed(line, &mut events);
+        }
+        events
+    }
+
+    fn finish(&mut self) -> Vec<Result<String, String>> {
+        let mut events = Vec::new();
+        if let Some(line) = self.lines.finish() {
+            self.feed(line, &mut events);
+        }
+        // servers that close without a trailing blank line still meant it
+        self.feed(String::new(), &mut events);
+        events
+    }
+}
+
+/// A byte stream run through a [`FrameDecoder`]. Transport errors are passed
+/// through as `Err` and end the stream.
+pub struct DecodedStream<S, D: FrameDecoder> {
+    inner: Pin<Box<S>>,
+    decoder: D,
+    pending: VecDeque<Result<D::Item, String>>,
+    finished: bool,
+}
+
+pub fn decode_stream<S, D: FrameDecoder>(inner: S, decoder: D) -> DecodedStream<S, D> {
+    DecodedStream {
+        inner: Box::pin(inner),
+        decoder,
+        pending: VecDeque::new(),
+        finished: false,
+    }
+}
+
+impl<S, B, E, D> Stream for DecodedStream<S, D>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: Display,
+    D: FrameDecoder + Unpin,
+    D::Item: Unpin,
+{
+    type Item = Result<D::Item, String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(item) = this.pending.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    let items = this.decoder.decode(chunk.as_ref());
+                    this.pending.extend(items);
+                }
+                Poll::Ready(Some(Err(err))) => {
+                    this.finished = true;
+                    return Poll::Ready(Some(Err(format!("Stream error: {}", err))));
+                }
+                Poll::Ready(None) => {
+                    this.finished = true;
+                    let items = this.decoder.finish();
+                    this.pending.extend(items);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use tokio_stream::StreamExt;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Record {
+        n: u32,
+        text: String,
+    }
+
+    fn ndjson(chunks: &[&str]) -> Vec<Result<Record, String>> {
+        let mut decoder = NdjsonDecoder::<Record>::new();
+        let mut records = Vec::new();
+        for chunk in chunks {
+            records.extend(decoder.decode(chunk.as_bytes()));
+        }
+        records.extend(decoder.finish());
+        records
+    }
+
+    #[test]
+    fn test_ndjson_reassembles_fragmented_objects() {
+        let records = ndjson(&["{\"n\":1,\"te", "xt\":\"a\"}\n{\"n\"", ":2,\"text\":\"b\"}\n"]);
+        assert_eq!(
+            records.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![
+                Record { n: 1, text: "a".to_string() },
+                Record { n: 2, text: "b".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ndjson_splits_coalesced_objects() {
+        let records = ndjson(&["{\"n\":1,\"text\":\"a\"}\n{\"n\":2,\"text\":\"b\"}\n{\"n\":3,\"text\":\"c\"}\n"]);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].as_ref().unwrap().n, 3);
+    }
+
+    #[test]
+    fn test_ndjson_keeps_multibyte_chars_split_across_chunks() {
+        let line = "{\"n\":1,\"text\":\"caf\u{e9} \u{1f43b}\"}\n".as_bytes();
+        // cut inside the 4-byte emoji
+        let cut = line.len() - 5;
+        let mut decoder = NdjsonDecoder::<Record>::new();
+        assert!(decoder.decode(&line[..cut]).is_empty());
+        let records = decoder.decode(&line[cut..]);
+        assert_eq!(records[0].as_ref().unwrap().text, "caf\u{e9} \u{1f43b}");
+    }
+
+    #[test]
+    fn test_ndjson_handles_crlf_blank_lines_and_missing_trailing_newline() {
+        let records = ndjson(&["{\"n\":1,\"text\":\"a\"}\r\n\r\n", "{\"n\":2,\"text\":\"b\"}"]);
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn test_ndjson_reports_malformed_lines_without_losing_the_rest() {
+        let records = ndjson(&["{\"n\":1,\"text\":\"a\"}\nnot json\n{\"n\":3,\"text\":\"c\"}\n"]);
+        assert!(records[0].is_ok());
+        assert!(records[1].is_err());
+        assert!(records[2].is_ok());
+    }
+
+    #[test]
+    fn test_sse_reassembles_events_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for chunk in [": keep-alive\n\nda", "ta: {\"a\":1}\n", "\ndata: [DONE]\n\n"] {
+            events.extend(decoder.decode(chunk.as_bytes()));
+        }
+        events.extend(decoder.finish());
+        assert_eq!(
+            events.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec!["{\"a\":1}".to_string(), "[DONE]".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_sse_flushes_final_event_without_blank_line() {
+        let mut decoder = SseDecoder::new();
+        let mut events = decoder.decode(b"event: message\ndata: last");
+        events.extend(decoder.finish());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_ref().unwrap(), "last");
+    }
+
+    #[tokio::test]
+    async fn test_decoded_stream_passes_records_and_transport_errors() {
+        let chunks: Vec<Result<&str, String>> = vec![
+            Ok("{\"n\":1,\"text\":\"a\"}\n{\"n\":2,"),
+            Ok("\"text\":\"b\"}\n"),
+            Err("connection reset".to_string()),
+            Ok("{\"n\":3,\"text\":\"c\"}\n"),
+        ];
+        let stream = decode_stream(tokio_stream::iter(chunks), NdjsonDecoder::<Record>::new());
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[1].as_ref().unwrap().n, 2);
+        assert_eq!(items[2].as_ref().unwrap_err(), "Stream error: connection reset");
+    }
+}
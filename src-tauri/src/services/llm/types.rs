@@ -1,7 +1,10 @@
 // This change is made under the BEAR AI SOFTWARE LICENSE AGREEMENT (Proprietary).
 // MIT License Copyright (c) 2024-present Frank Zhang
 use serde::{Deserialize, Serialize};
-use super::providers::ollama::config::OllamaConfig;
+use super::providers::{
+    ollama::config::OllamaConfig,
+    openai::config::{OpenAIConfig, OPENAI_COMPATIBLE_API_BASE},
+};
 
 const OLLAMA_API_BASE: &str = "http://localhost:11434";
 
@@ -9,12 +12,38 @@ const OLLAMA_API_BASE: &str = "http://localhost:11434";
 pub struct RawOllamaConfig {
     pub model: Option<String>,
     pub api_base: Option<String>,
+    pub context_length: Option<u32>,
 }
 
 impl From<RawOllamaConfig> for OllamaConfig {
     fn from(raw: RawOllamaConfig) -> Self {
         OllamaConfig {
             api_base: raw.api_base.unwrap_or(OLLAMA_API_BASE.to_string()),
+            context_length: raw.context_length,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawOpenAIConfig {
+    pub model: Option<String>,
+    pub api_base: Option<String>,
+    pub api_key: Option<String>,
+    pub context_length: Option<u32>,
+}
+
+impl From<RawOpenAIConfig> for OpenAIConfig {
+    fn from(raw: RawOpenAIConfig) -> Self {
+        OpenAIConfig {
+            api_base: raw
+                .api_base
+                .unwrap_or(OPENAI_COMPATIBLE_API_BASE.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            // an empty key means "no auth" for local servers such as llama.cpp
+            api_key: raw.api_key.filter(|key| !key.is_empty()),
+            context_length: raw.context_length,
+            proxy_setting: None,
         }
     }
 }
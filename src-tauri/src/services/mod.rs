@@ -1,4 +1,17 @@
 // MIT License Copyright (c) 2024-present Frank Zhang
+pub mod audit;
+pub mod backup;
+pub mod branches;
 pub mod cache;
 pub mod db;
-pub mod llm;
\ No newline at end of file
+pub mod encryption;
+pub mod erasure;
+pub mod export;
+pub mod import;
+pub mod integrity;
+pub mod llm;
+pub mod pii;
+pub mod projects;
+pub mod retention;
+pub mod search;
+pub mod summaries;
\ No newline at end of file
@@ -0,0 +1,761 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Detection and reversible masking of personal data in chat history before
+//! it leaves for the model.
+//!
+//! Each match is replaced by a placeholder such as `[EMAIL_1]`; a value
+//! seen twice gets the same placeholder, so the model can still refer to
+//! it. Placeholders in the reply are mapped back to the original values.
+//! Within a conversation the mapping is kept in the vault (`pii_vault`), so
+//! placeholders also hold across turns.
+//!
+//! Spans come from any number of `PiiDetector`s: the regex patterns below
+//! and, optionally, a local model tagging names (`ner`). Names already in
+//! the vault are masked wherever they appear, whether or not the model tags
+//! them again.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::services::llm::chat::BotReply;
+use entity::entities::{
+    messages::MessageDTO,
+    pii_vault,
+    settings::{NerThresholds, PiiKind, RedactionSetting},
+};
+
+pub mod ner;
+
+/// Longest placeholder the stream unmasker waits for before giving up
+const MAX_PLACEHOLDER_LEN: usize = 48;
+/// How far before a date to look for a word like "born"
+const DOB_CONTEXT_CHARS: usize = 40;
+const DOB_KEYWORDS: &[&str] = &[
+    "born",
+    "birth",
+    "dob",
+    "geboren",
+    "geboortedatum",
+    "geb.",
+    "geburtsdatum",
+    "geburtstag",
+];
+
+static EMAIL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b").unwrap());
+static PHONE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:\+|\b00)[1-9]\d{0,2}[\s./-]?(?:\(0\)[\s./-]?)?\d{1,4}(?:[\s./-]?\d{2,4}){1,4}\b|\b0\d{1,4}(?:[\s./-]?\d{2,4}){1,4}\b").unwrap()
+});
+static IBAN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b").unwrap());
+static BSN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(?:\d{9}|\d{4}\.\d{2}\.\d{3}|\d{3}[ .]\d{3}[ .]\d{3})\b").unwrap());
+static GERMAN_TAX_ID: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b[1-9]\d(?: ?\d{3}){3}\b").unwrap());
+/// Dutch `1234 AB`, or German five digits followed by a place name
+static POSTCODE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b(?P<pii>[1-9]\d{3} ?(?:[A-RT-Z][A-Z]|S[BCE-RT-Z]))\b|\b(?P<pii2>\d{5})\s+[A-ZÄÖÜ][a-zäöüß]+").unwrap()
+});
+static DATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b(?:(?:0?[1-9]|[12]\d|3[01])[./-](?:0?[1-9]|1[0-2])[./-](?:19|20)\d{2}|(?:19|20)\d{2}-(?:0[1-9]|1[0-2])-(?:0[1-9]|[12]\d|3[01]))\b").unwrap()
+});
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[[A-Z0-9_]+_\d+\]").unwrap());
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PiiSpan {
+    pub kind: PiiKind,
+    /// Placeholder prefix, e.g. `EMAIL`
+    pub label: String,
+    /// Byte offsets into the UTF-8 text
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Result of a dry run: what would be masked and how the text would read
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PiiPreview {
+    pub spans: Vec<PiiSpan>,
+    pub masked: String,
+}
+
+pub type DetectFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<PiiSpan>, String>> + Send + 'a>>;
+
+/// A source of spans to mask. Spans from several detectors are merged by
+/// `PiiDetectors`, so one need not know about the others.
+pub trait PiiDetector: Send + Sync {
+    fn detect<'a>(&'a self, text: &'a str) -> DetectFuture<'a>;
+}
+
+type Validator = fn(text: &str, start: usize, matched: &str) -> bool;
+
+struct Pattern {
+    kind: PiiKind,
+    label: String,
+    regex: Regex,
+    validate: Option<Validator>,
+}
+
+fn digits(value: &str) -> Vec<u32> {
+    value.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+/// ISO 13616: move the country and check digits to the end, read letters as
+/// 10..35, and the number must leave 1 modulo 97
+pub fn is_valid_iban(value: &str) -> bool {
+    let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    if !(15..=34).contains(&compact.len()) {
+        return false;
+    }
+    let (head, tail) = compact.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let Some(n) = c.to_digit(36) else {
+            return false;
+        };
+        remainder = if n < 10 { (remainder * 10 + n) % 97 } else { (remainder * 100 + n) % 97 };
+    }
+    remainder == 1
+}
+
+/// Eleven-check: weights 9 down to 2, and -1 for the last digit
+pub fn is_valid_bsn(value: &str) -> bool {
+    let d = digits(value);
+    if d.len() != 9 || d.iter().all(|&n| n == 0) {
+        return false;
+    }
+    let sum: i32 = d[..8]
+        .iter()
+        .zip((2..=9).rev())
+        .map(|(&n, w)| n as i32 * w)
+        .sum::<i32>()
+        - d[8] as i32;
+    sum % 11 == 0
+}
+
+/// Steuer-IdNr: 11 digits, no leading zero, exactly one digit repeated in
+/// the first ten, and an ISO 7064 MOD 11,10 check digit
+pub fn is_valid_german_tax_id(value: &str) -> bool {
+    let d = digits(value);
+    if d.len() != 11 || d[0] == 0 {
+        return false;
+    }
+    let mut counts = [0u8; 10];
+    for &n in &d[..10] {
+        counts[n as usize] += 1;
+    }
+    let repeated = counts.iter().filter(|&&c| c > 1).count();
+    if repeated != 1 || counts.iter().any(|&c| c > 3) {
+        return false;
+    }
+    let mut product = 10;
+    for &n in &d[..10] {
+        let mut sum = (n + product) % 10;
+        if sum == 0 {
+            sum = 10;
+        }
+        product = (2 * sum) % 11;
+    }
+    let check = (11 - product) % 10;
+    check == d[10]
+}
+
+fn is_phone_number(_: &str, _: usize, matched: &str) -> bool {
+    (9..=15).contains(&digits(matched).len())
+}
+
+fn is_birth_date(text: &str, start: usize, _: &str) -> bool {
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(DOB_CONTEXT_CHARS - 1)
+        .map_or(0, |(i, _)| i);
+    let before = text[from..start].to_lowercase();
+    DOB_KEYWORDS.iter().any(|keyword| before.contains(keyword))
+}
+
+fn builtin(kind: PiiKind) -> Option<Pattern> {
+    let (regex, validate, label): (&Lazy<Regex>, Option<Validator>, &str) = match kind {
+        PiiKind::Email => (&EMAIL, None, "EMAIL"),
+        PiiKind::Phone => (&PHONE, Some(is_phone_number), "PHONE"),
+        PiiKind::Iban => (&IBAN, Some(|_, _, m| is_valid_iban(m)), "IBAN"),
+        PiiKind::Bsn => (&BSN, Some(|_, _, m| is_valid_bsn(m)), "BSN"),
+        PiiKind::GermanTaxId => (&GERMAN_TAX_ID, Some(|_, _, m| is_valid_german_tax_id(m)), "TAX_ID"),
+        PiiKind::Postcode => (&POSTCODE, None, "POSTCODE"),
+        PiiKind::DateOfBirth => (&DATE, Some(is_birth_date), "DATE_OF_BIRTH"),
+        PiiKind::Custom | PiiKind::Person | PiiKind::Organisation | PiiKind::Location => return None,
+    };
+    Some(Pattern {
+        kind,
+        label: label.to_string(),
+        regex: Regex::clone(regex),
+        validate,
+    })
+}
+
+/// Upper-case a custom label and keep it to placeholder characters
+fn placeholder_label(label: &str) -> String {
+    let label: String = label
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    if label.is_empty() {
+        "CUSTOM".to_string()
+    } else {
+        label
+    }
+}
+
+/// The built-in and custom patterns selected in the settings
+pub struct RegexDetector(Vec<Pattern>);
+
+impl RegexDetector {
+    /// Fails on a custom pattern that is not a valid regex
+    pub fn new(setting: &RedactionSetting) -> Result<Self, String> {
+        let mut patterns: Vec<Pattern> = setting.detectors.iter().filter_map(|&kind| builtin(kind)).collect();
+        for custom in &setting.custom_patterns {
+            let regex = Regex::new(&custom.pattern)
+                .map_err(|err| format!("Invalid pattern for {}: {}", custom.label, err))?;
+            patterns.push(Pattern {
+                kind: PiiKind::Custom,
+                label: placeholder_label(&custom.label),
+                regex,
+                validate: None,
+            });
+        }
+        Ok(Self(patterns))
+    }
+
+    /// Matches in `text`, possibly overlapping
+    pub fn find(&self, text: &str) -> Vec<PiiSpan> {
+        let mut spans = Vec::new();
+        for pattern in &self.0 {
+            for captures in pattern.regex.captures_iter(text) {
+                // a named `pii` group narrows the match, e.g. to the digits
+                // of a postcode without the place name
+                let m = captures
+                    .name("pii")
+                    .or_else(|| captures.name("pii2"))
+                    .unwrap_or_else(|| captures.get(0).unwrap());
+                if pattern.validate.is_some_and(|validate| !validate(text, m.start(), m.as_str())) {
+                    continue;
+                }
+                spans.push(PiiSpan {
+                    kind: pattern.kind,
+                    label: pattern.label.clone(),
+                    start: m.start(),
+                    end: m.end(),
+                    text: m.as_str().to_string(),
+                });
+            }
+        }
+        spans
+    }
+}
+
+impl PiiDetector for RegexDetector {
+    fn detect<'a>(&'a self, text: &'a str) -> DetectFuture<'a> {
+        Box::pin(std::future::ready(Ok(self.find(text))))
+    }
+}
+
+pub struct PiiDetectors(Vec<Box<dyn PiiDetector>>);
+
+impl PiiDetectors {
+    /// The patterns selected in `setting`, whether or not redaction is on.
+    /// A NER detector needs a model and is added with `with`.
+    pub fn new(setting: &RedactionSetting) -> Result<Self, String> {
+        Ok(Self(vec![Box::new(RegexDetector::new(setting)?)]))
+    }
+
+    pub fn with(mut self, detector: impl PiiDetector + 'static) -> Self {
+        self.0.push(Box::new(detector));
+        self
+    }
+
+    /// Spans from all detectors in `text`, see `merge_spans`. Fails if any
+    /// detector fails, rather than let through what it would have caught.
+    pub async fn detect(&self, text: &str) -> Result<Vec<PiiSpan>, String> {
+        let mut spans = Vec::new();
+        for detector in &self.0 {
+            spans.extend(detector.detect(text).await?);
+        }
+        Ok(merge_spans(spans))
+    }
+}
+
+/// Sort by position and drop duplicates and overlaps. Where two overlap, the
+/// one starting first wins, then the longer one, then the one found first.
+pub fn merge_spans(mut spans: Vec<PiiSpan>) -> Vec<PiiSpan> {
+    // stable, so detectors listed first win ties
+    spans.sort_by(|a, b| a.start.cmp(&b.start).then((b.end - b.start).cmp(&(a.end - a.start))));
+    let mut res: Vec<PiiSpan> = Vec::with_capacity(spans.len());
+    for span in spans {
+        if res.last().map_or(true, |last| span.start >= last.end) {
+            res.push(span);
+        }
+    }
+    res
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pseudonym {
+    pub placeholder: String,
+    pub label: String,
+    pub value: String,
+}
+
+impl From<pii_vault::Model> for Pseudonym {
+    fn from(entry: pii_vault::Model) -> Self {
+        Self {
+            placeholder: entry.placeholder,
+            label: entry.label,
+            value: entry.value,
+        }
+    }
+}
+
+/// The `n` of `[LABEL_n]`
+fn placeholder_number(placeholder: &str) -> Option<usize> {
+    placeholder.strip_suffix(']')?.rsplit_once('_')?.1.parse().ok()
+}
+
+/// Placeholders known for a conversation, or handed out during one request,
+/// and the values behind them
+#[derive(Debug, Default)]
+pub struct Pseudonyms {
+    by_value: HashMap<(String, String), String>,
+    by_placeholder: HashMap<String, String>,
+    counters: HashMap<String, usize>,
+    /// Placeholders masked since `with_known`, and those of them that are new
+    used: HashSet<String>,
+    fresh: Vec<Pseudonym>,
+}
+
+impl Pseudonyms {
+    /// Start from the placeholders stored for a conversation; new values are
+    /// numbered after them
+    pub fn with_known(known: impl IntoIterator<Item = Pseudonym>) -> Self {
+        let mut pseudonyms = Self::default();
+        for pseudonym in known {
+            let n = pseudonyms.counters.entry(pseudonym.label.clone()).or_default();
+            *n = (*n).max(placeholder_number(&pseudonym.placeholder).unwrap_or(0));
+            pseudonyms
+                .by_value
+                .insert((pseudonym.label, pseudonym.value.clone()), pseudonym.placeholder.clone());
+            pseudonyms.by_placeholder.insert(pseudonym.placeholder, pseudonym.value);
+        }
+        pseudonyms
+    }
+
+    /// Whether nothing was masked
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// Distinct values masked per label, for the audit log
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for placeholder in &self.used {
+            let label = placeholder
+                .trim_start_matches('[')
+                .rsplit_once('_')
+                .map_or(placeholder.as_str(), |(label, _)| label);
+            *counts.entry(label.to_string()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Placeholders handed out for values not known before, to be stored
+    pub fn fresh(&self) -> &[Pseudonym] {
+        &self.fresh
+    }
+
+    /// Names tagged before, as certain entities to find again in any text
+    fn known_names(&self) -> Vec<ner::NerEntity> {
+        self.by_value
+            .keys()
+            .filter(|(label, _)| ner::entity_kind(label).is_some())
+            .map(|(label, value)| ner::NerEntity {
+                kind: label.clone(),
+                text: value.clone(),
+                confidence: 1.0,
+            })
+            .collect()
+    }
+
+    fn placeholder(&mut self, span: &PiiSpan) -> String {
+        let key = (span.label.clone(), span.text.clone());
+        let placeholder = match self.by_value.get(&key) {
+            Some(placeholder) => placeholder.clone(),
+            None => {
+                let n = self.counters.entry(span.label.clone()).or_default();
+                *n += 1;
+                let placeholder = format!("[{}_{}]", span.label, n);
+                self.by_value.insert(key, placeholder.clone());
+                self.by_placeholder.insert(placeholder.clone(), span.text.clone());
+                self.fresh.push(Pseudonym {
+                    placeholder: placeholder.clone(),
+                    label: span.label.clone(),
+                    value: span.text.clone(),
+                });
+                placeholder
+            }
+        };
+        self.used.insert(placeholder.clone());
+        placeholder
+    }
+
+    /// Replace `spans`, as returned by `PiiDetectors::detect` for `text`
+    pub fn mask(&mut self, text: &str, spans: &[PiiSpan]) -> String {
+        let mut masked = String::with_capacity(text.len());
+        let mut last = 0;
+        for span in spans {
+            masked.push_str(&text[last..span.start]);
+            masked.push_str(&self.placeholder(span));
+            last = span.end;
+        }
+        masked.push_str(&text[last..]);
+        masked
+    }
+
+    pub fn unmask_reply(&self, reply: &mut BotReply) {
+        reply.message = self.unmask(&reply.message);
+        reply.reasoning = reply.reasoning.as_deref().map(|reasoning| self.unmask(reasoning));
+    }
+
+    /// Put the original values back; unknown placeholders are left alone
+    pub fn unmask(&self, text: &str) -> String {
+        PLACEHOLDER
+            .replace_all(text, |captures: &regex::Captures| {
+                let placeholder = &captures[0];
+                self.by_placeholder
+                    .get(placeholder)
+                    .cloned()
+                    .unwrap_or_else(|| placeholder.to_string())
+            })
+            .into_owned()
+    }
+}
+
+/// Mask every message with one set of placeholders, including the names
+/// `pseudonyms` already knows
+pub async fn mask_messages(
+    detectors: &PiiDetectors,
+    messages: Vec<MessageDTO>,
+    pseudonyms: &mut Pseudonyms,
+) -> Result<Vec<MessageDTO>, String> {
+    let known = pseudonyms.known_names();
+    let mut masked = Vec::with_capacity(messages.len());
+    for message in messages {
+        let mut spans = detectors.detect(&message.content).await?;
+        spans.extend(ner::entity_spans(&message.content, &known, &NerThresholds::default()));
+        let spans = merge_spans(spans);
+        masked.push(MessageDTO {
+            content: pseudonyms.mask(&message.content, &spans),
+            role: message.role,
+        });
+    }
+    Ok(masked)
+}
+
+/// The detectors and placeholders of one request, for masking text that
+/// joins the history after `mask_messages`, such as a stored summary
+pub struct Masking {
+    pub detectors: PiiDetectors,
+    pub pseudonyms: Pseudonyms,
+}
+
+impl Masking {
+    pub async fn mask_messages(&mut self, messages: Vec<MessageDTO>) -> Result<Vec<MessageDTO>, String> {
+        mask_messages(&self.detectors, messages, &mut self.pseudonyms).await
+    }
+
+    pub async fn mask_text(&mut self, text: String) -> Result<String, String> {
+        let message = MessageDTO {
+            role: String::new(),
+            content: text,
+        };
+        let mut masked = self.mask_messages(vec![message]).await?;
+        Ok(masked.remove(0).content)
+    }
+}
+
+pub async fn preview(detectors: &PiiDetectors, text: &str) -> Result<PiiPreview, String> {
+    let spans = detectors.detect(text).await?;
+    let masked = Pseudonyms::default().mask(text, &spans);
+    Ok(PiiPreview { spans, masked })
+}
+
+/// Unmasks a streamed reply chunk by chunk, holding back a trailing `[...`
+/// until it is known whether it is a placeholder split across chunks
+#[derive(Debug, Default)]
+pub struct StreamUnmasker {
+    pending: String,
+}
+
+impl StreamUnmasker {
+    pub fn push(&mut self, chunk: &str, pseudonyms: &Pseudonyms) -> String {
+        self.pending.push_str(chunk);
+        let hold_from = match self.pending.rfind('[') {
+            Some(i) if !self.pending[i..].contains(']') && self.pending.len() - i < MAX_PLACEHOLDER_LEN => i,
+            _ => self.pending.len(),
+        };
+        let ready: String = self.pending.drain(..hold_from).collect();
+        pseudonyms.unmask(&ready)
+    }
+
+    pub fn finish(&mut self, pseudonyms: &Pseudonyms) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        pseudonyms.unmask(&rest)
+    }
+}
+
+/// `StreamUnmasker` for both the text and the reasoning of a streamed reply
+#[derive(Debug)]
+pub struct ReplyUnmasker {
+    pseudonyms: Pseudonyms,
+    message: StreamUnmasker,
+    reasoning: StreamUnmasker,
+}
+
+impl ReplyUnmasker {
+    pub fn new(pseudonyms: Pseudonyms) -> Self {
+        Self {
+            pseudonyms,
+            message: StreamUnmasker::default(),
+            reasoning: StreamUnmasker::default(),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &mut BotReply) {
+        chunk.message = self.message.push(&chunk.message, &self.pseudonyms);
+        if let Some(reasoning) = &chunk.reasoning {
+            chunk.reasoning = Some(self.reasoning.push(reasoning, &self.pseudonyms));
+        }
+    }
+
+    /// Whatever was held back, as a last chunk
+    pub fn finish(&mut self) -> Option<BotReply> {
+        let message = self.message.finish(&self.pseudonyms);
+        let reasoning = Some(self.reasoning.finish(&self.pseudonyms)).filter(|r| !r.is_empty());
+        if message.is_empty() && reasoning.is_none() {
+            return None;
+        }
+        Some(BotReply {
+            message,
+            reasoning,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_detectors() -> PiiDetectors {
+        PiiDetectors::new(&RedactionSetting::default()).unwrap()
+    }
+
+    fn kinds(text: &str) -> Vec<(PiiKind, String)> {
+        merge_spans(RegexDetector::new(&RedactionSetting::default()).unwrap().find(text))
+            .into_iter()
+            .map(|span| (span.kind, span.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert!(is_valid_iban("NL91 ABNA 0417 1643 00"));
+        assert!(is_valid_iban("DE89370400440532013000"));
+        assert!(!is_valid_iban("NL91ABNA0417164301"));
+        assert!(is_valid_bsn("111222333"));
+        assert!(is_valid_bsn("1234.56.782"));
+        assert!(!is_valid_bsn("123456789"));
+        assert!(!is_valid_bsn("000000000"));
+        assert!(is_valid_german_tax_id("86095742719"));
+        assert!(!is_valid_german_tax_id("86095742718"));
+        // every digit once, so no repeated digit
+        assert!(!is_valid_german_tax_id("12345678903"));
+    }
+
+    #[test]
+    fn test_detects_each_kind() {
+        let text = "Mail jan@example.nl or call +31 6 12345678. IBAN NL91 ABNA 0417 1643 00, \
+                    BSN 111222333, Steuer-ID 86 095 742 719, 1012 AB Amsterdam, 10115 Berlin, \
+                    geboren op 03-04-1985.";
+        assert_eq!(
+            kinds(text),
+            vec![
+                (PiiKind::Email, "jan@example.nl".to_string()),
+                (PiiKind::Phone, "+31 6 12345678".to_string()),
+                (PiiKind::Iban, "NL91 ABNA 0417 1643 00".to_string()),
+                (PiiKind::Bsn, "111222333".to_string()),
+                (PiiKind::GermanTaxId, "86 095 742 719".to_string()),
+                (PiiKind::Postcode, "1012 AB".to_string()),
+                (PiiKind::Postcode, "10115".to_string()),
+                (PiiKind::DateOfBirth, "03-04-1985".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_lookalikes() {
+        // fails the eleven-check, date without a birth keyword, short number
+        assert!(kinds("Invoice 123456789 due 03-04-2025, room 0123").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_custom_patterns() {
+        let mut setting = RedactionSetting::default();
+        setting.detectors.clear();
+        setting.custom_patterns.push(entity::entities::settings::CustomPiiPattern {
+            label: "case no".to_string(),
+            pattern: r"\bC-\d{4}\b".to_string(),
+        });
+        let preview = preview(&PiiDetectors::new(&setting).unwrap(), "See C-1234.").await.unwrap();
+        assert_eq!(preview.masked, "See [CASE_NO_1].");
+        setting.custom_patterns[0].pattern = "(".to_string();
+        assert!(PiiDetectors::new(&setting).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mask_round_trips_with_stable_placeholders() {
+        let messages = vec![
+            MessageDTO {
+                role: "user".to_string(),
+                content: "Write to jan@example.nl and piet@example.nl".to_string(),
+            },
+            MessageDTO {
+                role: "user".to_string(),
+                content: "Again: jan@example.nl".to_string(),
+            },
+        ];
+        let mut pseudonyms = Pseudonyms::default();
+        let masked = mask_messages(&all_detectors(), messages, &mut pseudonyms).await.unwrap();
+        assert_eq!(masked[0].content, "Write to [EMAIL_1] and [EMAIL_2]");
+        assert_eq!(masked[1].content, "Again: [EMAIL_1]");
+        assert_eq!(pseudonyms.counts().get("EMAIL"), Some(&2));
+        assert_eq!(
+            pseudonyms.unmask("Dear [EMAIL_2], cc [EMAIL_1] [EMAIL_9]"),
+            "Dear piet@example.nl, cc jan@example.nl [EMAIL_9]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_known_placeholders_are_reused() {
+        let mut pseudonyms = Pseudonyms::with_known([Pseudonym {
+            placeholder: "[EMAIL_3]".to_string(),
+            label: "EMAIL".to_string(),
+            value: "jan@example.nl".to_string(),
+        }]);
+        let masked = mask_messages(
+            &all_detectors(),
+            vec![MessageDTO {
+                role: "user".to_string(),
+                content: "jan@example.nl, piet@example.nl".to_string(),
+            }],
+            &mut pseudonyms,
+        )
+        .await
+        .unwrap();
+        assert_eq!(masked[0].content, "[EMAIL_3], [EMAIL_4]");
+        assert_eq!(pseudonyms.counts().get("EMAIL"), Some(&2));
+        let fresh: Vec<&str> = pseudonyms.fresh().iter().map(|p| p.placeholder.as_str()).collect();
+        assert_eq!(fresh, vec!["[EMAIL_4]"]);
+        // loaded for the next turn: nothing masked yet, but it unmasks
+        let next = Pseudonyms::with_known(pseudonyms.fresh().to_vec());
+        assert!(next.is_empty());
+        assert_eq!(next.unmask("[EMAIL_4]"), "piet@example.nl");
+    }
+
+    #[tokio::test]
+    async fn test_known_names_are_masked_without_tagging() {
+        let mut pseudonyms = Pseudonyms::with_known([Pseudonym {
+            placeholder: "[PERSON_1]".to_string(),
+            label: "PERSON".to_string(),
+            value: "Jan de Vries".to_string(),
+        }]);
+        let masked = mask_messages(
+            &all_detectors(),
+            vec![MessageDTO {
+                role: "assistant".to_string(),
+                content: "Jan de Vries signed; Jan de Vriesen did not.".to_string(),
+            }],
+            &mut pseudonyms,
+        )
+        .await
+        .unwrap();
+        assert_eq!(masked[0].content, "[PERSON_1] signed; Jan de Vriesen did not.");
+        assert!(pseudonyms.fresh().is_empty());
+    }
+
+    struct Fixed(Vec<PiiSpan>);
+
+    impl PiiDetector for Fixed {
+        fn detect<'a>(&'a self, _: &'a str) -> DetectFuture<'a> {
+            Box::pin(std::future::ready(Ok(self.0.clone())))
+        }
+    }
+
+    fn span(kind: PiiKind, label: &str, start: usize, text: &str) -> PiiSpan {
+        PiiSpan {
+            kind,
+            label: label.to_string(),
+            start,
+            end: start + text.len(),
+            text: text.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_detectors_are_merged_and_deduplicated() {
+        let text = "Jan de Vries, jan@example.nl, works at Acme";
+        let detectors = all_detectors()
+            .with(Fixed(vec![
+                span(PiiKind::Person, "PERSON", 0, "Jan de Vries"),
+                span(PiiKind::Organisation, "ORG", 39, "Acme"),
+                // the part of the address before the @, already covered
+                span(PiiKind::Person, "PERSON", 14, "jan"),
+            ]))
+            .with(Fixed(vec![span(PiiKind::Person, "PERSON", 0, "Jan de Vries")]));
+        let spans = detectors.detect(text).await.unwrap();
+        let labels: Vec<&str> = spans.iter().map(|span| span.label.as_str()).collect();
+        assert_eq!(labels, vec!["PERSON", "EMAIL", "ORG"]);
+        assert_eq!(
+            Pseudonyms::default().mask(text, &spans),
+            "[PERSON_1], [EMAIL_1], works at [ORG_1]"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_unmasker_joins_split_placeholders() {
+        let mut pseudonyms = Pseudonyms::default();
+        mask_messages(
+            &all_detectors(),
+            vec![MessageDTO {
+                role: "user".to_string(),
+                content: "jan@example.nl".to_string(),
+            }],
+            &mut pseudonyms,
+        )
+        .await
+        .unwrap();
+        let mut unmasker = StreamUnmasker::default();
+        let mut out = String::new();
+        for chunk in ["Hello [EM", "AIL_", "1], see [", "note"] {
+            out.push_str(&unmasker.push(chunk, &pseudonyms));
+        }
+        assert_eq!(out, "Hello jan@example.nl, see ");
+        out.push_str(&unmasker.finish(&pseudonyms));
+        assert_eq!(out, "Hello jan@example.nl, see [note");
+    }
+}
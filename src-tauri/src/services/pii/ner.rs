@@ -0,0 +1,221 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Names of people, organisations and places, which no pattern can catch,
+//! tagged by a local model.
+//!
+//! The model is asked for JSON listing each name with a confidence; names
+//! below the threshold for their type are ignored. Every occurrence of a
+//! tagged name in the text is masked, not just the first.
+//!
+//! A chat resends the whole history on every turn, so what the model found
+//! in a message is kept for the life of the process and each message is
+//! only tagged once. The cache holds names and is dropped on lock.
+use std::{collections::HashMap, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{DetectFuture, PiiDetector, PiiSpan};
+use crate::services::llm::{chat::GlobalSettings, client::LLMClient};
+use entity::entities::{
+    conversations::GenericOptions,
+    messages::MessageDTO,
+    settings::{NerThresholds, PiiKind},
+};
+
+const NER_INSTRUCTION: &str = "Find the names of people, organisations and locations in the text \
+the user sends. Reply with JSON only, in the form \
+{\"entities\": [{\"type\": \"person\", \"text\": \"Jan de Vries\", \"confidence\": 0.9}]}. \
+\"type\" is one of \"person\", \"organisation\" or \"location\", \"text\" is the name exactly \
+as written and \"confidence\" is between 0 and 1. Reply with {\"entities\": []} if there are none.";
+const NER_MAX_TOKENS: u32 = 1024;
+/// Messages whose entities are kept before the cache starts over
+const MAX_TAGGED: usize = 4096;
+
+/// Entities per message, by a hash of the tagging model and the text. Kept
+/// before the thresholds are applied, so changing those needs no new tagging.
+static TAGGED: Lazy<Mutex<HashMap<[u8; 32], Vec<NerEntity>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Drop what was tagged so far, e.g. when the app is locked
+pub fn forget_tagged() {
+    TAGGED.lock().unwrap().clear();
+}
+
+fn tagged_key(model: &str, text: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(model.as_bytes());
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    hasher.finalize().into()
+}
+
+#[derive(Debug, Deserialize)]
+struct NerReply {
+    #[serde(default)]
+    entities: Vec<NerEntity>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct NerEntity {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+    /// Taken as certain when the model leaves it out
+    #[serde(default = "certain")]
+    pub confidence: f32,
+}
+
+fn certain() -> f32 {
+    1.0
+}
+
+pub(super) fn entity_kind(kind: &str) -> Option<(PiiKind, &'static str)> {
+    match kind.trim().to_lowercase().as_str() {
+        "person" | "per" => Some((PiiKind::Person, "PERSON")),
+        "organisation" | "organization" | "org" => Some((PiiKind::Organisation, "ORG")),
+        "location" | "loc" => Some((PiiKind::Location, "LOCATION")),
+        _ => None,
+    }
+}
+
+/// The entities in a reply, which may wrap the JSON in prose or a code fence
+pub fn parse_entities(reply: &str) -> Result<Vec<NerEntity>, String> {
+    let json = match (reply.find('{'), reply.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &reply[start..=end],
+        _ => return Err("NER model did not reply with JSON".to_string()),
+    };
+    serde_json::from_str::<NerReply>(json)
+        .map(|reply| reply.entities)
+        .map_err(|err| format!("Failed to parse NER reply: {}", err))
+}
+
+fn is_word_boundary(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let after = text[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+/// Spans for every whole-word occurrence of the entities that meet their
+/// threshold
+pub fn entity_spans(text: &str, entities: &[NerEntity], thresholds: &NerThresholds) -> Vec<PiiSpan> {
+    let mut spans = Vec::new();
+    for entity in entities {
+        let name = entity.text.trim();
+        let Some((kind, label)) = entity_kind(&entity.kind) else {
+            continue;
+        };
+        if name.is_empty() || thresholds.get(kind).map_or(true, |threshold| entity.confidence < threshold) {
+            continue;
+        }
+        for (start, matched) in text.match_indices(name) {
+            let end = start + matched.len();
+            if is_word_boundary(text, start, end) {
+                spans.push(PiiSpan {
+                    kind,
+                    label: label.to_string(),
+                    start,
+                    end,
+                    text: matched.to_string(),
+                });
+            }
+        }
+    }
+    spans
+}
+
+pub struct NerDetector {
+    client: LLMClient,
+    thresholds: NerThresholds,
+}
+
+impl NerDetector {
+    pub fn new(client: LLMClient, thresholds: NerThresholds) -> Self {
+        Self { client, thresholds }
+    }
+
+    async fn tag(&self, text: &str) -> Result<Vec<PiiSpan>, String> {
+        if text.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let key = tagged_key(self.client.model_name().unwrap_or_default(), text);
+        if let Some(entities) = TAGGED.lock().unwrap().get(&key) {
+            return Ok(entity_spans(text, entities, &self.thresholds));
+        }
+        let entities = self.ask(text).await?;
+        let spans = entity_spans(text, &entities, &self.thresholds);
+        let mut tagged = TAGGED.lock().unwrap();
+        if tagged.len() >= MAX_TAGGED {
+            tagged.clear();
+        }
+        tagged.insert(key, entities);
+        Ok(spans)
+    }
+
+    async fn ask(&self, text: &str) -> Result<Vec<NerEntity>, String> {
+        let request = vec![
+            MessageDTO {
+                role: "system".to_string(),
+                content: NER_INSTRUCTION.to_string(),
+            },
+            MessageDTO {
+                role: "user".to_string(),
+                content: text.to_string(),
+            },
+        ];
+        let options = GenericOptions {
+            options: "{}".to_string(),
+        };
+        let global_settings = GlobalSettings {
+            max_tokens: NER_MAX_TOKENS,
+            context_length: None,
+        };
+        let reply = self.client.chat(request, options, global_settings).await?;
+        parse_entities(&reply.message)
+    }
+}
+
+impl PiiDetector for NerDetector {
+    fn detect<'a>(&'a self, text: &'a str) -> DetectFuture<'a> {
+        Box::pin(self.tag(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_fenced_reply() {
+        let reply = "Here you go:\n```json\n{\"entities\": [{\"type\": \"Person\", \"text\": \"Jan\"}]}\n```";
+        let entities = parse_entities(reply).unwrap();
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities[0].confidence, 1.0);
+        assert!(parse_entities("No names here.").is_err());
+    }
+
+    #[test]
+    fn test_spans_respect_thresholds_and_word_boundaries() {
+        let text = "Anna met Jan at Acme in Utrecht. Jan's brother Janssen stayed.";
+        let entities = parse_entities(
+            r#"{"entities": [
+                {"type": "person", "text": "Jan", "confidence": 0.9},
+                {"type": "organization", "text": "Acme", "confidence": 0.6},
+                {"type": "location", "text": "Utrecht", "confidence": 0.8},
+                {"type": "date", "text": "today", "confidence": 1.0}
+            ]}"#,
+        )
+        .unwrap();
+        let spans = entity_spans(text, &entities, &NerThresholds::default());
+        let found: Vec<(&str, usize)> = spans.iter().map(|span| (span.label.as_str(), span.start)).collect();
+        // "Acme" is below the organisation threshold, "Janssen" is not "Jan"
+        assert_eq!(found, vec![("PERSON", 9), ("PERSON", 33), ("LOCATION", 24)]);
+    }
+
+    #[test]
+    fn test_tagged_key_depends_on_model_and_text() {
+        assert_eq!(tagged_key("llama3", "Jan"), tagged_key("llama3", "Jan"));
+        assert_ne!(tagged_key("llama3", "Jan"), tagged_key("mistral", "Jan"));
+        assert_ne!(tagged_key("llama3", "Jan"), tagged_key("llama3", "Anna"));
+        assert_ne!(tagged_key("a", "bc"), tagged_key("ab", "c"));
+    }
+}
@@ -0,0 +1,106 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Clients and matters. Each conversation, prompt and document belongs to at
+//! most one project and is never listed, searched or sent to a model
+//! together with another project's data (purpose limitation, GDPR
+//! Art. 5(1)(b)). Rows without a project are the general workspace.
+use crate::errors::BearLlmAiError;
+use entity::entities::projects::ProjectPayload;
+
+const MAX_NAME_LEN: usize = 200;
+
+fn invalid(message: impl Into<String>) -> BearLlmAiError {
+    BearLlmAiError::Invalid(message.into())
+}
+
+/// Trim the payload and check it: a name, a `#rrggbb` colour and a JSON
+/// object as metadata. Blank optional fields become `None`.
+pub fn normalize(payload: ProjectPayload) -> Result<ProjectPayload, BearLlmAiError> {
+    let name = payload.name.trim().to_string();
+    if name.is_empty() {
+        return Err(invalid("The project needs a name"));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(invalid(format!("The project name is longer than {} characters", MAX_NAME_LEN)));
+    }
+    let reference = payload
+        .reference
+        .map(|reference| reference.trim().to_string())
+        .filter(|reference| !reference.is_empty());
+    let colour = match payload.colour.map(|colour| colour.trim().to_lowercase()) {
+        Some(colour) if colour.is_empty() => None,
+        Some(colour) => {
+            let hex = colour.strip_prefix('#').unwrap_or_default();
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid(format!("{} is not a colour like #1f6feb", colour)));
+            }
+            Some(colour)
+        }
+        None => None,
+    };
+    let metadata = match payload.metadata.filter(|metadata| !metadata.trim().is_empty()) {
+        Some(metadata) => match serde_json::from_str::<serde_json::Value>(&metadata) {
+            Ok(serde_json::Value::Object(_)) => metadata,
+            _ => return Err(invalid("Project metadata must be a JSON object")),
+        },
+        None => "{}".to_string(),
+    };
+    if payload.retention_days.is_some_and(|days| days < 0) {
+        return Err(invalid("The retention period cannot be negative"));
+    }
+    Ok(ProjectPayload {
+        name,
+        reference,
+        colour,
+        metadata: Some(metadata),
+        retention_days: payload.retention_days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(name: &str) -> ProjectPayload {
+        ProjectPayload {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_normalize_trims_and_fills_defaults() {
+        let normalized = normalize(ProjectPayload {
+            name: "  Acme v. Smith ".to_string(),
+            reference: Some("  ".to_string()),
+            colour: Some(" #1F6FEB".to_string()),
+            metadata: None,
+            retention_days: Some(90),
+        })
+        .unwrap();
+        assert_eq!(normalized.name, "Acme v. Smith");
+        assert_eq!(normalized.reference, None);
+        assert_eq!(normalized.colour.as_deref(), Some("#1f6feb"));
+        assert_eq!(normalized.metadata.as_deref(), Some("{}"));
+        assert_eq!(normalized.retention_days, Some(90));
+    }
+
+    #[test]
+    fn test_normalize_rejects_bad_input() {
+        assert!(normalize(payload(" ")).is_err());
+        assert!(normalize(payload(&"x".repeat(MAX_NAME_LEN + 1))).is_err());
+        for colour in ["red", "#12345", "#12345g", "1f6feb"] {
+            let mut p = payload("Acme");
+            p.colour = Some(colour.to_string());
+            assert!(normalize(p).is_err(), "{}", colour);
+        }
+        let mut p = payload("Acme");
+        p.metadata = Some("[1, 2]".to_string());
+        assert!(matches!(normalize(p), Err(BearLlmAiError::Invalid(_))));
+        let mut p = payload("Acme");
+        p.retention_days = Some(-1);
+        assert!(normalize(p).is_err());
+        let mut p = payload("Acme");
+        p.metadata = Some(r#"{"partner": "J. Doe"}"#.to_string());
+        assert_eq!(normalize(p).unwrap().metadata.as_deref(), Some(r#"{"partner": "J. Doe"}"#));
+    }
+}
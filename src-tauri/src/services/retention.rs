@@ -0,0 +1,121 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Storage limitation (GDPR Art. 5(1)(e)): conversations are purged once
+//! they have been quiet for longer than their retention period. The period
+//! comes from the conversation itself, else from its project, else from the
+//! global `retentionDays` setting; 0 means keep forever. Pinned conversations
+//! and those under legal hold are never purged.
+use chrono::{Duration, NaiveDateTime};
+
+use entity::entities::conversations;
+
+pub const GLOBAL_POLICY: &str = "global";
+pub const PROJECT_POLICY: &str = "project";
+pub const CONVERSATION_POLICY: &str = "conversation";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Expiry {
+    pub retention_days: i32,
+    /// `CONVERSATION_POLICY`, `PROJECT_POLICY` or `GLOBAL_POLICY`
+    pub policy: &'static str,
+}
+
+/// The policy under which `conversation` is due for purging at `now`, if it
+/// is. `project_days` is the period of the project it is filed under.
+pub fn expiry(
+    conversation: &conversations::Model,
+    project_days: Option<i32>,
+    global_days: u64,
+    now: NaiveDateTime,
+) -> Option<Expiry> {
+    if conversation.pinned || conversation.legal_hold {
+        return None;
+    }
+    let (days, policy) = match (conversation.retention_days, project_days) {
+        (Some(days), _) => (days.max(0), CONVERSATION_POLICY),
+        (None, Some(days)) => (days.max(0), PROJECT_POLICY),
+        (None, None) => (i32::try_from(global_days).unwrap_or(i32::MAX), GLOBAL_POLICY),
+    };
+    if days == 0 {
+        return None;
+    }
+    let expires_at = conversation
+        .last_message_at
+        .checked_add_signed(Duration::days(days.into()))?;
+    (expires_at <= now).then_some(Expiry {
+        retention_days: days,
+        policy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(idle_days: i64, now: NaiveDateTime) -> conversations::Model {
+        conversations::Model {
+            id: 1,
+            name: "Old".to_string(),
+            model_id: 1,
+            system_message: None,
+            options: "{}".to_string(),
+            last_message_at: now - Duration::days(idle_days),
+            active_message_id: None,
+            retention_days: None,
+            pinned: false,
+            legal_hold: false,
+            project_id: None,
+        }
+    }
+
+    #[test]
+    fn test_global_policy_applies_without_override() {
+        let now = chrono::Utc::now().naive_utc();
+        assert_eq!(
+            expiry(&conversation(31, now), None, 30, now),
+            Some(Expiry {
+                retention_days: 30,
+                policy: GLOBAL_POLICY
+            })
+        );
+        assert_eq!(expiry(&conversation(29, now), None, 30, now), None);
+        // 0 keeps everything
+        assert_eq!(expiry(&conversation(3650, now), None, 0, now), None);
+    }
+
+    #[test]
+    fn test_conversation_override_wins() {
+        let now = chrono::Utc::now().naive_utc();
+        let mut c = conversation(10, now);
+        c.retention_days = Some(7);
+        assert_eq!(expiry(&c, Some(365), 365, now).map(|e| e.policy), Some(CONVERSATION_POLICY));
+        c.retention_days = Some(0);
+        assert_eq!(expiry(&c, Some(1), 1, now), None);
+    }
+
+    #[test]
+    fn test_project_period_comes_before_the_global_one() {
+        let now = chrono::Utc::now().naive_utc();
+        let c = conversation(10, now);
+        assert_eq!(
+            expiry(&c, Some(7), 365, now),
+            Some(Expiry {
+                retention_days: 7,
+                policy: PROJECT_POLICY
+            })
+        );
+        assert_eq!(expiry(&c, Some(30), 1, now), None);
+        // 0 keeps the project's conversations although the global period ran out
+        assert_eq!(expiry(&c, Some(0), 1, now), None);
+    }
+
+    #[test]
+    fn test_pinned_and_legal_hold_are_kept() {
+        let now = chrono::Utc::now().naive_utc();
+        let mut c = conversation(400, now);
+        c.pinned = true;
+        assert_eq!(expiry(&c, None, 30, now), None);
+        c.pinned = false;
+        c.legal_hold = true;
+        assert_eq!(expiry(&c, None, 30, now), None);
+    }
+}
@@ -0,0 +1,168 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Turns what the user typed into an FTS5 `MATCH` expression.
+//!
+//! Raw input cannot be passed through: quotes, `-`, `:` or a stray `AND` are
+//! FTS5 syntax and would either fail the query or change its meaning. Every
+//! word is quoted instead, so all words must appear, in any order.
+//!
+//! An encrypted history is not indexed; `TextScan` searches it after
+//! decryption instead.
+
+pub const SNIPPET_OPEN: &str = "<mark>";
+pub const SNIPPET_CLOSE: &str = "</mark>";
+pub const DEFAULT_LIMIT: u64 = 50;
+pub const MAX_LIMIT: u64 = 500;
+/// Messages decrypted per round when scanning an encrypted history
+pub const SCAN_BATCH: u64 = 200;
+/// Words shown around the first hit, as for the FTS `snippet()`
+const SCAN_SNIPPET_WORDS: usize = 24;
+
+/// `None` when the input holds no searchable word
+pub fn match_expression(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .filter_map(|word| {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(stem) => (stem, true),
+                None => (word, false),
+            };
+            let word = word.trim_matches('"');
+            if word.is_empty() {
+                return None;
+            }
+            let quoted = format!("\"{}\"", word.replace('"', "\"\""));
+            Some(if prefix { format!("{}*", quoted) } else { quoted })
+        })
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Search over text that cannot be indexed because it is stored encrypted.
+/// As with the index every word has to appear, ignoring case, and a
+/// trailing `*` matches by prefix. Diacritics are not folded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextScan {
+    /// Lower-cased term and whether it is a prefix
+    terms: Vec<(String, bool)>,
+}
+
+fn normalise(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+impl TextScan {
+    /// `None` when the input holds no searchable word
+    pub fn new(input: &str) -> Option<Self> {
+        let terms: Vec<(String, bool)> = input
+            .split_whitespace()
+            .filter_map(|word| {
+                let (word, prefix) = match word.strip_suffix('*') {
+                    Some(stem) => (stem, true),
+                    None => (word, false),
+                };
+                let word = normalise(word);
+                (!word.is_empty()).then_some((word, prefix))
+            })
+            .collect();
+        (!terms.is_empty()).then_some(Self { terms })
+    }
+
+    fn is_hit(&self, word: &str) -> bool {
+        let word = normalise(word);
+        self.terms
+            .iter()
+            .any(|(term, prefix)| word == *term || (*prefix && word.starts_with(term.as_str())))
+    }
+
+    /// Snippet and score when `text` contains every term. The score is the
+    /// number of hits, so, as with bm25, higher is better.
+    pub fn scan(&self, text: &str) -> Option<(String, f64)> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let normalised: Vec<String> = words.iter().map(|w| normalise(w)).collect();
+        let all_present = self.terms.iter().all(|(term, prefix)| {
+            normalised
+                .iter()
+                .any(|word| word == term || (*prefix && word.starts_with(term.as_str())))
+        });
+        if !all_present {
+            return None;
+        }
+        let hits: Vec<usize> = (0..words.len()).filter(|&i| self.is_hit(words[i])).collect();
+        let start = hits[0].saturating_sub(SCAN_SNIPPET_WORDS / 2);
+        let end = (start + SCAN_SNIPPET_WORDS).min(words.len());
+        let mut snippet: Vec<String> = words[start..end]
+            .iter()
+            .map(|word| {
+                if self.is_hit(word) {
+                    format!("{}{}{}", SNIPPET_OPEN, word, SNIPPET_CLOSE)
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect();
+        if start > 0 {
+            snippet.insert(0, "…".to_string());
+        }
+        if end < words.len() {
+            snippet.push("…".to_string());
+        }
+        Some((snippet.join(" "), hits.len() as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_are_quoted() {
+        assert_eq!(
+            match_expression("  force   majeure "),
+            Some("\"force\" \"majeure\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fts_syntax_is_neutralised() {
+        assert_eq!(
+            match_expression("NOT art:6 -x"),
+            Some("\"NOT\" \"art:6\" \"-x\"".to_string())
+        );
+        assert_eq!(match_expression("say\"what"), Some("\"say\"\"what\"".to_string()));
+    }
+
+    #[test]
+    fn test_trailing_star_is_a_prefix_query() {
+        assert_eq!(match_expression("indemn*"), Some("\"indemn\"*".to_string()));
+    }
+
+    #[test]
+    fn test_empty_input_has_no_expression() {
+        assert_eq!(match_expression(""), None);
+        assert_eq!(match_expression(" * \"\" "), None);
+    }
+
+    #[test]
+    fn test_scan_requires_every_term() {
+        let scan = TextScan::new("Force majeure").unwrap();
+        let (snippet, score) = scan.scan("The force majeure clause, see FORCE.").unwrap();
+        assert_eq!(snippet, "The <mark>force</mark> <mark>majeure</mark> clause, see <mark>FORCE.</mark>");
+        assert_eq!(score, 3.0);
+        assert!(scan.scan("force of habit").is_none());
+    }
+
+    #[test]
+    fn test_scan_prefix_and_long_text() {
+        let scan = TextScan::new("indemn*").unwrap();
+        let text = format!("{} indemnity {}", "a ".repeat(40), "b ".repeat(40));
+        let (snippet, _) = scan.scan(&text).unwrap();
+        assert!(snippet.starts_with("… a"));
+        assert!(snippet.ends_with("b …"));
+        assert!(snippet.contains("<mark>indemnity</mark>"));
+        assert!(TextScan::new(" * ").is_none());
+    }
+}
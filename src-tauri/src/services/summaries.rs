@@ -0,0 +1,358 @@
+// MIT License Copyright (c) 2024-present Frank Zhang
+//! Rolling summaries of long conversations.
+//!
+//! When older turns no longer fit the context window they are condensed by
+//! the conversation's own model and the result is stored in
+//! `conversation_summaries`, so later requests reuse it instead of asking the
+//! model again. Each new summary folds the previous one together with the
+//! turns that have dropped out since. Messages themselves are never deleted.
+//!
+//! Summaries are stored as written, like the messages. With redaction on,
+//! the summariser is sent the masked turns and its summary is masked again
+//! each time it goes into a request.
+use sea_orm::DatabaseConnection;
+
+use crate::services::{
+    db::Db,
+    encryption::Encryption,
+    llm::{
+        client::LLMClient,
+        context::{self, ContextBudget, ContextReport, SUMMARY_ROLE},
+    },
+    pii::Masking,
+};
+use entity::entities::{
+    conversation_summaries,
+    messages::{self, MessageDTO},
+    settings::ContextStrategy,
+};
+
+/// Like `LLMClient::fit_context`, but backed by the summaries stored for a
+/// persisted conversation. With `masking`, `messages` are the masked history.
+#[allow(clippy::too_many_arguments)]
+pub async fn fit_conversation_context(
+    db: &DatabaseConnection,
+    encryption: &Encryption,
+    client: &LLMClient,
+    conversation_id: i32,
+    messages: Vec<MessageDTO>,
+    strategy: ContextStrategy,
+    max_tokens: u32,
+    mut masking: Option<&mut Masking>,
+) -> Result<(Vec<MessageDTO>, ContextReport), String> {
+    if strategy != ContextStrategy::SummariseOlderTurns {
+        return client.fit_context(messages, strategy, max_tokens).await;
+    }
+    let (mut plan, budget) = client.plan_context(messages, strategy, max_tokens).await;
+    if plan.dropped.is_empty() {
+        return Ok((plan.kept, plan.report));
+    }
+    let mut stored = Db::get_message_path(db, encryption, conversation_id, None)
+        .await
+        .map_err(|err| err.to_string())?;
+    stored.truncate(plan.dropped.len());
+    let covered = match masking.as_deref_mut() {
+        Some(masking) => covered_until(&masked(masking, &stored).await?, &plan.dropped),
+        None => covered_until(&stored, &plan.dropped),
+    };
+    let summary = match covered {
+        Some(last_message_id) => {
+            match summarise_until(
+                db,
+                encryption,
+                client,
+                conversation_id,
+                &stored,
+                last_message_id,
+                &budget,
+                masking.as_deref_mut(),
+            )
+            .await
+            {
+                // a summary that cannot be masked is left out, not sent as is
+                Ok(summary) => match masking {
+                    Some(masking) => masking.mask_text(summary.content).await,
+                    None => Ok(summary.content),
+                },
+                Err(err) => Err(err),
+            }
+        }
+        // the history sent by the webview does not line up with the database,
+        // e.g. it was edited before sending, so nothing can be reused; the
+        // dropped turns are already masked
+        None => client.summarise(&plan.dropped, &budget).await,
+    };
+    match summary {
+        Ok(summary) => context::insert_summary(&mut plan, &summary),
+        Err(err) => log::warn!("Failed to summarise older turns of conversation {}: {}", conversation_id, err),
+    }
+    Ok((plan.kept, plan.report))
+}
+
+/// Id of the last stored message covered by `dropped`, if the dropped turns
+/// are exactly the start of the stored history
+fn covered_until(stored: &[messages::Model], dropped: &[MessageDTO]) -> Option<i32> {
+    if dropped.is_empty() || dropped.len() > stored.len() {
+        return None;
+    }
+    let matches = stored
+        .iter()
+        .zip(dropped)
+        .all(|(s, d)| s.role == d.role && s.content == d.content);
+    matches.then(|| stored[dropped.len() - 1].id)
+}
+
+/// `stored` with its content masked the way the history was
+async fn masked(masking: &mut Masking, stored: &[messages::Model]) -> Result<Vec<messages::Model>, String> {
+    let input = stored
+        .iter()
+        .map(|m| MessageDTO {
+            role: m.role.clone(),
+            content: m.content.clone(),
+        })
+        .collect();
+    let masked = masking.mask_messages(input).await?;
+    Ok(stored
+        .iter()
+        .zip(masked)
+        .map(|(m, masked)| messages::Model {
+            content: masked.content,
+            ..m.clone()
+        })
+        .collect())
+}
+
+/// Return the summary ending at `last_message_id`, rolling the latest stored
+/// summary forward when it covers less
+#[allow(clippy::too_many_arguments)]
+pub async fn summarise_until(
+    db: &DatabaseConnection,
+    encryption: &Encryption,
+    client: &LLMClient,
+    conversation_id: i32,
+    stored: &[messages::Model],
+    last_message_id: i32,
+    budget: &ContextBudget,
+    masking: Option<&mut Masking>,
+) -> Result<conversation_summaries::Model, String> {
+    let latest = Db::get_latest_conversation_summary(db, encryption, conversation_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let previous = match latest {
+        Some(latest) if latest.last_message_id == last_message_id => return Ok(latest),
+        // a summary reaching further than needed cannot be cut back, and one
+        // made on another branch does not apply, start over
+        Some(latest)
+            if latest.last_message_id < last_message_id
+                && stored.iter().any(|m| m.id == latest.last_message_id) =>
+        {
+            Some(latest)
+        }
+        _ => None,
+    };
+    create_summary(db, encryption, client, conversation_id, stored, previous, last_message_id, budget, masking).await
+}
+
+/// Summarise the branch from the first message up to `last_message_id` from
+/// scratch, ignoring earlier summaries
+pub async fn regenerate_summary(
+    db: &DatabaseConnection,
+    encryption: &Encryption,
+    client: &LLMClient,
+    conversation_id: i32,
+    last_message_id: Option<i32>,
+    budget: &ContextBudget,
+    masking: Option<&mut Masking>,
+) -> Result<conversation_summaries::Model, String> {
+    let last_message_id = match last_message_id {
+        Some(id) => Some(id),
+        None => Db::get_latest_conversation_summary(db, encryption, conversation_id)
+            .await
+            .map_err(|err| err.to_string())?
+            .map(|summary| summary.last_message_id),
+    };
+    let stored = Db::get_message_path(db, encryption, conversation_id, last_message_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    let last_message_id = match stored.last() {
+        Some(message) => message.id,
+        None => return Err(format!("Conversation {} has no messages to summarise", conversation_id)),
+    };
+    create_summary(db, encryption, client, conversation_id, &stored, None, last_message_id, budget, masking).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn create_summary(
+    db: &DatabaseConnection,
+    encryption: &Encryption,
+    client: &LLMClient,
+    conversation_id: i32,
+    stored: &[messages::Model],
+    previous: Option<conversation_summaries::Model>,
+    last_message_id: i32,
+    budget: &ContextBudget,
+    masking: Option<&mut Masking>,
+) -> Result<conversation_summaries::Model, String> {
+    let mut input = Vec::new();
+    let mut first_message_id = None;
+    let mut after = None;
+    if let Some(previous) = previous {
+        first_message_id = Some(previous.first_message_id);
+        after = Some(previous.last_message_id);
+        input.push(MessageDTO {
+            role: SUMMARY_ROLE.to_string(),
+            content: previous.content,
+        });
+    }
+    for message in stored
+        .iter()
+        .filter(|m| m.id <= last_message_id && after.map_or(true, |after| m.id > after))
+    {
+        first_message_id.get_or_insert(message.id);
+        input.push(MessageDTO {
+            role: message.role.clone(),
+            content: message.content.clone(),
+        });
+    }
+    let first_message_id = first_message_id.ok_or_else(|| "Nothing to summarise".to_string())?;
+    // the summariser sees what the chat model would, and the summary is
+    // stored with the real values like the messages it covers
+    let content = match masking {
+        Some(masking) => {
+            let input = masking.mask_messages(input).await?;
+            let content = client.summarise(&input, budget).await?;
+            masking.pseudonyms.unmask(&content)
+        }
+        None => client.summarise(&input, budget).await?,
+    };
+    Db::create_conversation_summary(db, encryption, conversation_id, content, first_message_id, last_message_id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::{
+        llm::providers::openai::config::OpenAIConfig,
+        pii::{PiiDetectors, Pseudonyms},
+    };
+    use entity::entities::{conversations, settings::RedactionSetting};
+    use migration::Migrator;
+    use sea_orm::Database;
+    use sea_orm_migration::MigratorTrait;
+
+    fn stored(id: i32, role: &str, content: &str) -> messages::Model {
+        messages::Model {
+            id,
+            conversation_id: 1,
+            role: role.to_string(),
+            content: content.to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+            prompt_token: None,
+            completion_token: None,
+            reasoning_token: None,
+            reasoning: None,
+            parent_id: None,
+            model_id: None,
+            provider: None,
+            model_name: None,
+            generation_options: None,
+            redaction: None,
+            app_version: None,
+        }
+    }
+
+    fn dto(role: &str, content: &str) -> MessageDTO {
+        MessageDTO {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_covered_until_finds_last_dropped_message() {
+        let history = vec![stored(4, "user", "a"), stored(7, "assistant", "b"), stored(9, "user", "c")];
+        assert_eq!(covered_until(&history, &[dto("user", "a"), dto("assistant", "b")]), Some(7));
+    }
+
+    #[test]
+    fn test_covered_until_rejects_edited_history() {
+        let history = vec![stored(4, "user", "a"), stored(7, "assistant", "b")];
+        assert_eq!(covered_until(&history, &[dto("user", "a"), dto("assistant", "edited")]), None);
+        assert_eq!(covered_until(&history, &[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_stored_summary_is_masked_in_a_redacted_request() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let encryption = Encryption::default();
+        let conversation = conversations::Model {
+            id: 0,
+            name: "Acme".to_string(),
+            model_id: 1,
+            system_message: None,
+            options: "{}".to_string(),
+            last_message_at: chrono::Utc::now().naive_utc(),
+            active_message_id: None,
+            retention_days: None,
+            pinned: false,
+            legal_hold: false,
+            project_id: None,
+        };
+        let conversation_id = Db::create_conversation(&db, &encryption, conversation).await.unwrap().id;
+        let older = format!("Pay to NL91 ABNA 0417 1643 00. {}", "Details follow. ".repeat(300));
+        let history = [("user", older.as_str()), ("assistant", "Noted."), ("user", "And the deadline?")];
+        let payload = history
+            .iter()
+            .map(|(role, content)| messages::Model {
+                conversation_id,
+                ..stored(0, role, content)
+            })
+            .collect();
+        let stored = Db::create_messages(&db, &encryption, payload).await.unwrap();
+        // reused as is, so the model is never asked
+        Db::create_conversation_summary(
+            &db,
+            &encryption,
+            conversation_id,
+            "The client pays to NL91 ABNA 0417 1643 00.".to_string(),
+            stored[0].id,
+            stored[0].id,
+        )
+        .await
+        .unwrap();
+
+        let mut masking = Masking {
+            detectors: PiiDetectors::new(&RedactionSetting::default()).unwrap(),
+            pseudonyms: Pseudonyms::default(),
+        };
+        let messages = masking
+            .mask_messages(history.iter().map(|(role, content)| dto(role, content)).collect())
+            .await
+            .unwrap();
+        let config = OpenAIConfig {
+            api_base: "http://127.0.0.1:9".to_string(),
+            api_key: None,
+            context_length: Some(1000),
+            ..Default::default()
+        };
+        let client = LLMClient::OpenAIClient(config, Some("test".to_string()));
+        let (fitted, report) = fit_conversation_context(
+            &db,
+            &encryption,
+            &client,
+            conversation_id,
+            messages,
+            ContextStrategy::SummariseOlderTurns,
+            100,
+            Some(&mut masking),
+        )
+        .await
+        .unwrap();
+        assert!(report.summarised);
+        assert!(fitted[0].content.contains("The client pays to [IBAN_1]."), "{}", fitted[0].content);
+        assert!(fitted.iter().all(|m| !m.content.contains("NL91")));
+    }
+}